@@ -10,6 +10,14 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         .build_client(true)
         .build_server(true)
         .out_dir(OUT_DIR)
-        .compile(&["proto/public/image_predction_service.proto"], &["proto/"])?;
+        .compile(
+            &[
+                "proto/public/image_predction_service.proto",
+                // TensorFlow Serving的原生gRPC接口
+                "proto/tensorflow_serving/apis/prediction_service.proto",
+                "proto/tensorflow_serving/apis/model_service.proto",
+            ],
+            &["proto/"],
+        )?;
     Ok(())
 }
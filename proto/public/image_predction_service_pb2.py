@@ -13,7 +13,7 @@ _sym_db = _symbol_database.Default()
 
 
 
-DESCRIPTOR = _descriptor_pool.Default().AddSerializedFile(b'\n\x1dimage_predction_service.proto\x12\x10image_prediction\"B\n\x16ImagePredictionRequest\x12\r\n\x05image\x18\x01 \x01(\x0c\x12\r\n\x05model\x18\x02 \x01(\t\x12\n\n\x02id\x18\x03 \x01(\x05\"?\n\x13ImageVectorResponse\x12\x1c\n\x06vector\x18\x01 \x03(\x02R\x0cimage_vector\x12\n\n\x02id\x18\x02 \x01(\x05\"5\n\x05\x45rror\x12\x12\n\x04\x63ode\x18\x01 \x01(\x05R\x04\x63ode\x12\x18\n\x07message\x18\x02 \x01(\tR\x07message2q\n\x0fImagePrediction\x12^\n\x07Predict\x12(.image_prediction.ImagePredictionRequest\x1a%.image_prediction.ImageVectorResponse(\x01\x30\x01\x62\x06proto3')
+DESCRIPTOR = _descriptor_pool.Default().AddSerializedFile(b'\n\x1dimage_predction_service.proto\x12\x10image_prediction\"B\n\x16ImagePredictionRequest\x12\r\n\x05image\x18\x01 \x01(\x0c\x12\r\n\x05model\x18\x02 \x01(\t\x12\n\n\x02id\x18\x03 \x01(\x05\"O\n\x11ImageBatchRequest\x12:\n\x08requests\x18\x01 \x03(\x0b\x32(.image_prediction.ImagePredictionRequest\"\xa8\x01\n\x13ImageVectorResponse\x12\n\n\x02id\x18\x02 \x01(\x05\x12(\n\x05\x65rror\x18\x03 \x01(\x0b\x32\x17.image_prediction.ErrorH\x00\x12\x43\n\x0cimage_vector\x18\x04 \x01(\x0b\x32\x1d.image_prediction.ImageVectorH\x00R\x0cimage_vectorB\x08\n\x06resultJ\x04\x08\x01\x10\x02R\x06vector\"\x1d\n\x0bImageVector\x12\x0e\n\x06values\x18\x01 \x03(\x02\"5\n\x05\x45rror\x12\x12\n\x04\x63ode\x18\x01 \x01(\x05R\x04\x63ode\x12\x18\n\x07message\x18\x02 \x01(\tR\x07message2\xae\x02\n\x0fImagePrediction\x12^\n\x07Predict\x12(.image_prediction.ImagePredictionRequest\x1a%.image_prediction.ImageVectorResponse(\x01\x30\x01\x12]\n\nPredictOne\x12(.image_prediction.ImagePredictionRequest\x1a%.image_prediction.ImageVectorResponse\x12\\\n\x0cPredictBatch\x12#.image_prediction.ImageBatchRequest\x1a%.image_prediction.ImageVectorResponse0\x01\x62\x06proto3')
 
 _globals = globals()
 _builder.BuildMessageAndEnumDescriptors(DESCRIPTOR, _globals)
@@ -23,10 +23,14 @@ if _descriptor._USE_C_DESCRIPTORS == False:
   DESCRIPTOR._options = None
   _globals['_IMAGEPREDICTIONREQUEST']._serialized_start=51
   _globals['_IMAGEPREDICTIONREQUEST']._serialized_end=117
-  _globals['_IMAGEVECTORRESPONSE']._serialized_start=119
-  _globals['_IMAGEVECTORRESPONSE']._serialized_end=182
-  _globals['_ERROR']._serialized_start=184
-  _globals['_ERROR']._serialized_end=237
-  _globals['_IMAGEPREDICTION']._serialized_start=239
-  _globals['_IMAGEPREDICTION']._serialized_end=352
+  _globals['_IMAGEBATCHREQUEST']._serialized_start=119
+  _globals['_IMAGEBATCHREQUEST']._serialized_end=198
+  _globals['_IMAGEVECTORRESPONSE']._serialized_start=201
+  _globals['_IMAGEVECTORRESPONSE']._serialized_end=369
+  _globals['_IMAGEVECTOR']._serialized_start=371
+  _globals['_IMAGEVECTOR']._serialized_end=400
+  _globals['_ERROR']._serialized_start=402
+  _globals['_ERROR']._serialized_end=455
+  _globals['_IMAGEPREDICTION']._serialized_start=458
+  _globals['_IMAGEPREDICTION']._serialized_end=760
 # @@protoc_insertion_point(module_scope)
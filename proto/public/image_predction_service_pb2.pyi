@@ -1,26 +1,45 @@
 from google.protobuf.internal import containers as _containers
 from google.protobuf import descriptor as _descriptor
 from google.protobuf import message as _message
-from typing import ClassVar as _ClassVar, Iterable as _Iterable, Optional as _Optional
+from collections.abc import Iterable as _Iterable, Mapping as _Mapping
+from typing import ClassVar as _ClassVar, Optional as _Optional, Union as _Union
 
 DESCRIPTOR: _descriptor.FileDescriptor
 
 class ImagePredictionRequest(_message.Message):
-    __slots__ = ["image", "model"]
+    __slots__ = ("image", "model", "id")
     IMAGE_FIELD_NUMBER: _ClassVar[int]
     MODEL_FIELD_NUMBER: _ClassVar[int]
+    ID_FIELD_NUMBER: _ClassVar[int]
     image: bytes
     model: str
-    def __init__(self, image: _Optional[bytes] = ..., model: _Optional[str] = ...) -> None: ...
+    id: int
+    def __init__(self, image: _Optional[bytes] = ..., model: _Optional[str] = ..., id: _Optional[int] = ...) -> None: ...
 
-class ImagePredictionResponse(_message.Message):
-    __slots__ = ["vector"]
-    VECTOR_FIELD_NUMBER: _ClassVar[int]
-    vector: _containers.RepeatedScalarFieldContainer[float]
-    def __init__(self, vector: _Optional[_Iterable[float]] = ...) -> None: ...
+class ImageBatchRequest(_message.Message):
+    __slots__ = ("requests",)
+    REQUESTS_FIELD_NUMBER: _ClassVar[int]
+    requests: _containers.RepeatedCompositeFieldContainer[ImagePredictionRequest]
+    def __init__(self, requests: _Optional[_Iterable[_Union[ImagePredictionRequest, _Mapping]]] = ...) -> None: ...
+
+class ImageVectorResponse(_message.Message):
+    __slots__ = ("id", "error", "image_vector")
+    ID_FIELD_NUMBER: _ClassVar[int]
+    ERROR_FIELD_NUMBER: _ClassVar[int]
+    IMAGE_VECTOR_FIELD_NUMBER: _ClassVar[int]
+    id: int
+    error: Error
+    image_vector: ImageVector
+    def __init__(self, id: _Optional[int] = ..., error: _Optional[_Union[Error, _Mapping]] = ..., image_vector: _Optional[_Union[ImageVector, _Mapping]] = ...) -> None: ...
+
+class ImageVector(_message.Message):
+    __slots__ = ("values",)
+    VALUES_FIELD_NUMBER: _ClassVar[int]
+    values: _containers.RepeatedScalarFieldContainer[float]
+    def __init__(self, values: _Optional[_Iterable[float]] = ...) -> None: ...
 
 class Error(_message.Message):
-    __slots__ = ["code", "message"]
+    __slots__ = ("code", "message")
     CODE_FIELD_NUMBER: _ClassVar[int]
     MESSAGE_FIELD_NUMBER: _ClassVar[int]
     code: int
@@ -19,6 +19,16 @@ class ImagePredictionStub(object):
                 request_serializer=image__predction__service__pb2.ImagePredictionRequest.SerializeToString,
                 response_deserializer=image__predction__service__pb2.ImageVectorResponse.FromString,
                 )
+        self.PredictOne = channel.unary_unary(
+                '/image_prediction.ImagePrediction/PredictOne',
+                request_serializer=image__predction__service__pb2.ImagePredictionRequest.SerializeToString,
+                response_deserializer=image__predction__service__pb2.ImageVectorResponse.FromString,
+                )
+        self.PredictBatch = channel.unary_stream(
+                '/image_prediction.ImagePrediction/PredictBatch',
+                request_serializer=image__predction__service__pb2.ImageBatchRequest.SerializeToString,
+                response_deserializer=image__predction__service__pb2.ImageVectorResponse.FromString,
+                )
 
 
 class ImagePredictionServicer(object):
@@ -31,6 +41,21 @@ class ImagePredictionServicer(object):
         context.set_details('Method not implemented!')
         raise NotImplementedError('Method not implemented!')
 
+    def PredictOne(self, request, context):
+        """Predict a single image, errors are returned as the gRPC status
+        """
+        context.set_code(grpc.StatusCode.UNIMPLEMENTED)
+        context.set_details('Method not implemented!')
+        raise NotImplementedError('Method not implemented!')
+
+    def PredictBatch(self, request, context):
+        """Predict a list of images, one response per image is streamed back as soon
+        as it is ready, errors are attached to the response of that image
+        """
+        context.set_code(grpc.StatusCode.UNIMPLEMENTED)
+        context.set_details('Method not implemented!')
+        raise NotImplementedError('Method not implemented!')
+
 
 def add_ImagePredictionServicer_to_server(servicer, server):
     rpc_method_handlers = {
@@ -39,6 +64,16 @@ def add_ImagePredictionServicer_to_server(servicer, server):
                     request_deserializer=image__predction__service__pb2.ImagePredictionRequest.FromString,
                     response_serializer=image__predction__service__pb2.ImageVectorResponse.SerializeToString,
             ),
+            'PredictOne': grpc.unary_unary_rpc_method_handler(
+                    servicer.PredictOne,
+                    request_deserializer=image__predction__service__pb2.ImagePredictionRequest.FromString,
+                    response_serializer=image__predction__service__pb2.ImageVectorResponse.SerializeToString,
+            ),
+            'PredictBatch': grpc.unary_stream_rpc_method_handler(
+                    servicer.PredictBatch,
+                    request_deserializer=image__predction__service__pb2.ImageBatchRequest.FromString,
+                    response_serializer=image__predction__service__pb2.ImageVectorResponse.SerializeToString,
+            ),
     }
     generic_handler = grpc.method_handlers_generic_handler(
             'image_prediction.ImagePrediction', rpc_method_handlers)
@@ -65,3 +100,37 @@ class ImagePrediction(object):
             image__predction__service__pb2.ImageVectorResponse.FromString,
             options, channel_credentials,
             insecure, call_credentials, compression, wait_for_ready, timeout, metadata)
+
+    @staticmethod
+    def PredictOne(request,
+            target,
+            options=(),
+            channel_credentials=None,
+            call_credentials=None,
+            insecure=False,
+            compression=None,
+            wait_for_ready=None,
+            timeout=None,
+            metadata=None):
+        return grpc.experimental.unary_unary(request, target, '/image_prediction.ImagePrediction/PredictOne',
+            image__predction__service__pb2.ImagePredictionRequest.SerializeToString,
+            image__predction__service__pb2.ImageVectorResponse.FromString,
+            options, channel_credentials,
+            insecure, call_credentials, compression, wait_for_ready, timeout, metadata)
+
+    @staticmethod
+    def PredictBatch(request,
+            target,
+            options=(),
+            channel_credentials=None,
+            call_credentials=None,
+            insecure=False,
+            compression=None,
+            wait_for_ready=None,
+            timeout=None,
+            metadata=None):
+        return grpc.experimental.unary_stream(request, target, '/image_prediction.ImagePrediction/PredictBatch',
+            image__predction__service__pb2.ImageBatchRequest.SerializeToString,
+            image__predction__service__pb2.ImageVectorResponse.FromString,
+            options, channel_credentials,
+            insecure, call_credentials, compression, wait_for_ready, timeout, metadata)
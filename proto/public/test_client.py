@@ -30,8 +30,11 @@ def run_client():
     try:
         # Call the service method with the request generator
         for entry_response in stub.Predict(generate_image_requests()):
-            # Print the response
-            print(len(entry_response.vector))
+            # Print the response, each response has either a vector or an error
+            if entry_response.WhichOneof('result') == 'error':
+                print(f'id {entry_response.id} failed: {entry_response.error.code} {entry_response.error.message}')
+            else:
+                print(entry_response.id, len(entry_response.image_vector.values))
 
     except grpc.RpcError as e:
         print(f'Prediction failed: {e}')
@@ -0,0 +1,416 @@
+use log::{debug, error};
+use opentelemetry::trace::FutureExt;
+use opentelemetry::Context;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{mpsc, oneshot};
+use tokio::time::Instant;
+use tonic::Status;
+
+use crate::config::Model;
+use crate::inference::InferenceBackend;
+use crate::telemetry::batch_context;
+use crate::tf_serving::error::{is_model_not_found, is_retryable};
+
+// 队列中最多缓存的待批处理图片数量
+const QUEUE_CAPACITY: usize = 4096;
+
+// 模型的批处理参数，可以在config.yaml中每个模型的batch部分配置
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct BatchOptions {
+    // 一次请求中最多包含的图片数量
+    pub max_size: usize,
+    // 收集一个批次最多等待的时间（毫秒）
+    pub max_delay_ms: u64,
+}
+
+impl Default for BatchOptions {
+    fn default() -> Self {
+        BatchOptions {
+            max_size: 8,
+            max_delay_ms: 5,
+        }
+    }
+}
+
+// 队列中等待批处理的一张图片，预测结果通过reply返回给对应的请求
+// cx是加入队列时的上下文，发送批次时用来关联每张图片的span
+struct BatchItem {
+    image: Vec<u8>,
+    reply: oneshot::Sender<Result<Vec<f32>, Status>>,
+    cx: Context,
+}
+
+// 单个模型的批处理队列，来自所有流的请求都会汇总到这里
+#[derive(Clone)]
+pub struct Batcher {
+    tx: mpsc::Sender<BatchItem>,
+}
+
+impl Batcher {
+    // 创建批处理队列并在后台启动收集任务，需要在tokio运行时中调用
+    pub fn spawn(client: Arc<dyn InferenceBackend>, model: Model, options: BatchOptions) -> Self {
+        let (tx, rx) = mpsc::channel(QUEUE_CAPACITY);
+        tokio::spawn(run_batcher(client, model, options, rx));
+        Batcher { tx }
+    }
+
+    // 将图片加入队列，并等待所在批次的预测结果
+    pub async fn predict(&self, image: Vec<u8>) -> Result<Vec<f32>, Status> {
+        let (reply, rx) = oneshot::channel();
+        self.tx
+            .send(BatchItem {
+                image,
+                reply,
+                cx: Context::current(),
+            })
+            .await
+            .map_err(|_| Status::unavailable("Batch queue is closed"))?;
+
+        rx.await
+            .map_err(|_| Status::internal("Batch worker dropped the request"))?
+    }
+}
+
+// 后台收集任务：凑满max_size张图片或等待max_delay_ms后发送一次请求
+async fn run_batcher(
+    client: Arc<dyn InferenceBackend>,
+    model: Model,
+    options: BatchOptions,
+    mut rx: mpsc::Receiver<BatchItem>,
+) {
+    let max_size = options.max_size.max(1);
+    let max_delay = Duration::from_millis(options.max_delay_ms);
+
+    while let Some(first) = rx.recv().await {
+        let deadline = Instant::now() + max_delay;
+        let mut batch = vec![first];
+
+        while batch.len() < max_size {
+            match tokio::time::timeout_at(deadline, rx.recv()).await {
+                Ok(Some(item)) => batch.push(item),
+                // 队列已关闭或等待超时，发送当前批次
+                Ok(None) | Err(_) => break,
+            }
+        }
+
+        debug!(
+            "send batch of {} images to model {} version {}",
+            batch.len(),
+            model.name,
+            model.version
+        );
+
+        // 在独立的任务中发送请求，收集任务可以继续收集下一批
+        // 后台任务没有调用方的上下文，使用关联了批次中每张图片的span
+        let contexts: Vec<Context> = batch.iter().map(|item| item.cx.clone()).collect();
+        let cx = batch_context(&model.name, &contexts);
+        tokio::spawn(send_batch(Arc::clone(&client), model.clone(), batch).with_context(cx));
+    }
+}
+
+// 发送一个批次，并将predictions中的每一行分发给对应的请求
+// 调用方已经放弃等待的图片不再发送
+async fn send_batch(client: Arc<dyn InferenceBackend>, model: Model, mut batch: Vec<BatchItem>) {
+    batch.retain(|item| !item.reply.is_closed());
+    if batch.is_empty() {
+        return;
+    }
+    let images: Vec<&[u8]> = batch.iter().map(|item| item.image.as_slice()).collect();
+    let result = client.predict_batch(&model, &images).await;
+
+    match result {
+        Ok(predictions) if predictions.len() == batch.len() => {
+            for (item, prediction) in batch.into_iter().zip(predictions) {
+                let _ = item.reply.send(Ok(prediction));
+            }
+        }
+        Ok(predictions) => {
+            error!(
+                "model {} returned {} predictions for a batch of {} images",
+                model.name,
+                predictions.len(),
+                batch.len()
+            );
+            let status = Status::internal(format!(
+                "Model {} returned {} predictions for {} images",
+                model.name,
+                predictions.len(),
+                batch.len()
+            ));
+            for item in batch {
+                let _ = item.reply.send(Err(status.clone()));
+            }
+        }
+        // 推理服务不可用或模型不存在时整个批次共享同一个错误，重试已经在推理服务中进行过
+        Err(status) if batch.len() == 1 || is_retryable(&status) || is_model_not_found(&status) => {
+            for item in batch {
+                let _ = item.reply.send(Err(status.clone()));
+            }
+        }
+        // 其他错误可能只是由其中一张图片引起的，每张图片单独重新发送，不影响其他流中的图片
+        Err(status) => {
+            debug!(
+                "batch of {} images for model {} failed, predicting them one by one: {}",
+                batch.len(),
+                model.name,
+                status.message()
+            );
+            for item in batch {
+                let cx = item.cx.clone();
+                tokio::spawn(send_one(Arc::clone(&client), model.clone(), item).with_context(cx));
+            }
+        }
+    }
+}
+
+// 单独发送批次中的一张图片
+async fn send_one(client: Arc<dyn InferenceBackend>, model: Model, item: BatchItem) {
+    if item.reply.is_closed() {
+        return;
+    }
+    let result = client.predict(&model, &item.image).await;
+    let _ = item.reply.send(result);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::inference::fake::FakeBackend;
+    use crate::tf_serving::backend::new_backend;
+    use crate::tf_serving::client::ClientOptions;
+    use base64_simd::URL_SAFE;
+    use hyper::service::{make_service_fn, service_fn};
+    use hyper::{Body, Request, Response, Server};
+    use std::convert::Infallible;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tonic::Code;
+
+    // 启动一个TensorFlow Serving替身：每个instance返回[Base64解码后的长度]，并统计请求次数
+    fn start_echo_server(status: u16) -> (String, Arc<AtomicUsize>) {
+        let requests = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&requests);
+        let make_svc = make_service_fn(move |_| {
+            let counter = Arc::clone(&counter);
+            async move {
+                Ok::<_, Infallible>(service_fn(move |req: Request<Body>| {
+                    let counter = Arc::clone(&counter);
+                    async move {
+                        counter.fetch_add(1, Ordering::SeqCst);
+                        let body = hyper::body::to_bytes(req.into_body()).await.unwrap();
+                        let request: serde_json::Value = serde_json::from_slice(&body).unwrap();
+                        let predictions: Vec<Vec<f32>> = request["instances"]
+                            .as_array()
+                            .unwrap()
+                            .iter()
+                            .map(|instance| {
+                                let image = instance["input"].as_str().unwrap();
+                                vec![URL_SAFE.decode_to_vec(image).unwrap().len() as f32]
+                            })
+                            .collect();
+                        // 空图片模拟无法解码的图片，整个请求返回400
+                        let status = match predictions.iter().any(|p| p[0] == 0.0) {
+                            true => 400,
+                            false => status,
+                        };
+                        let body = serde_json::json!({ "predictions": predictions }).to_string();
+                        Ok::<_, Infallible>(
+                            Response::builder()
+                                .status(status)
+                                .header("content-type", "application/json")
+                                .body(Body::from(body))
+                                .unwrap(),
+                        )
+                    }
+                }))
+            }
+        });
+
+        let server = Server::bind(&"127.0.0.1:0".parse().unwrap()).serve(make_svc);
+        let url = format!("http://{}", server.local_addr());
+        tokio::spawn(server);
+        (url, requests)
+    }
+
+    fn test_model() -> Model {
+        Model {
+            name: "foo".to_string(),
+            version: 1,
+            input_name: "input".to_string(),
+            ..Model::default()
+        }
+    }
+
+    fn test_batcher(url: &str, options: BatchOptions) -> Batcher {
+        let client = new_backend(url, &ClientOptions::default()).unwrap();
+        Batcher::spawn(client, test_model(), options)
+    }
+
+    // 测试同时到达的请求被合并为一次调用，并且每个请求拿到自己的结果
+    #[tokio::test]
+    async fn test_batch_fan_out() {
+        let (url, requests) = start_echo_server(200);
+        let batcher = test_batcher(
+            &url,
+            BatchOptions {
+                max_size: 8,
+                max_delay_ms: 1_000,
+            },
+        );
+
+        let handles: Vec<_> = (1..=8)
+            .map(|i| {
+                let batcher = batcher.clone();
+                tokio::spawn(async move { batcher.predict(vec![0; i]).await })
+            })
+            .collect();
+
+        for (i, handle) in handles.into_iter().enumerate() {
+            let prediction = handle.await.unwrap().unwrap();
+            assert_eq!(prediction, vec![(i + 1) as f32]);
+        }
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+    }
+
+    // 测试批次未凑满时，等待max_delay_ms后仍然会发送
+    #[tokio::test]
+    async fn test_batch_flush_on_delay() {
+        let (url, requests) = start_echo_server(200);
+        let batcher = test_batcher(
+            &url,
+            BatchOptions {
+                max_size: 64,
+                max_delay_ms: 20,
+            },
+        );
+
+        let (a, b, c) = tokio::join!(
+            batcher.predict(vec![0; 1]),
+            batcher.predict(vec![0; 2]),
+            batcher.predict(vec![0; 3]),
+        );
+        assert_eq!(a.unwrap(), vec![1.0]);
+        assert_eq!(b.unwrap(), vec![2.0]);
+        assert_eq!(c.unwrap(), vec![3.0]);
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+    }
+
+    // 测试超过max_size的请求被拆分为多个批次
+    #[tokio::test]
+    async fn test_batch_split_by_max_size() {
+        let (url, requests) = start_echo_server(200);
+        let batcher = test_batcher(
+            &url,
+            BatchOptions {
+                max_size: 2,
+                max_delay_ms: 1_000,
+            },
+        );
+
+        let handles: Vec<_> = (1..=6)
+            .map(|i| {
+                let batcher = batcher.clone();
+                tokio::spawn(async move { batcher.predict(vec![0; i]).await })
+            })
+            .collect();
+        for handle in handles {
+            handle.await.unwrap().unwrap();
+        }
+        assert_eq!(requests.load(Ordering::SeqCst), 3);
+    }
+
+    // 测试推理服务不可用导致批次请求失败时，错误被分发给批次中的所有请求
+    #[tokio::test]
+    async fn test_batch_error_fan_out() {
+        let (url, _) = start_echo_server(503);
+        let batcher = test_batcher(
+            &url,
+            BatchOptions {
+                max_size: 2,
+                max_delay_ms: 1_000,
+            },
+        );
+
+        let (a, b) = tokio::join!(batcher.predict(vec![0; 1]), batcher.predict(vec![0; 2]),);
+        assert_eq!(a.unwrap_err().code(), Code::Unavailable);
+        assert_eq!(b.unwrap_err().code(), Code::Unavailable);
+    }
+
+    // 测试一张损坏的图片导致批次失败时，其他图片单独重新发送，只有损坏的图片返回错误
+    #[tokio::test]
+    async fn test_batch_error_isolated() {
+        let (url, requests) = start_echo_server(200);
+        let batcher = test_batcher(
+            &url,
+            BatchOptions {
+                max_size: 3,
+                max_delay_ms: 1_000,
+            },
+        );
+
+        let (corrupt, b, c) = tokio::join!(
+            batcher.predict(vec![]),
+            batcher.predict(vec![0; 2]),
+            batcher.predict(vec![0; 3]),
+        );
+        assert_eq!(corrupt.unwrap_err().code(), Code::InvalidArgument);
+        assert_eq!(b.unwrap(), vec![2.0]);
+        assert_eq!(c.unwrap(), vec![3.0]);
+        // 一次批次请求，之后每张图片各一次
+        assert_eq!(requests.load(Ordering::SeqCst), 4);
+    }
+
+    // 测试调用方已经放弃的图片不会被发送
+    #[tokio::test]
+    async fn test_batch_skips_cancelled_items() {
+        let backend = Arc::new(FakeBackend::new());
+        let batcher = Batcher::spawn(
+            backend.clone(),
+            test_model(),
+            BatchOptions {
+                max_size: 4,
+                max_delay_ms: 100,
+            },
+        );
+
+        let cancelled = {
+            let batcher = batcher.clone();
+            tokio::spawn(async move { batcher.predict(vec![0; 1]).await })
+        };
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        cancelled.abort();
+
+        assert_eq!(batcher.predict(vec![0; 2]).await.unwrap(), vec![2.0, 1.0]);
+        assert_eq!(backend.calls(), 1);
+        assert_eq!(backend.images(), 1);
+    }
+
+    // 测试批处理队列只依赖推理服务的抽象，可以直接使用测试替身
+    #[tokio::test]
+    async fn test_batch_with_fake_backend() {
+        let backend = Arc::new(FakeBackend::new());
+        let batcher = Batcher::spawn(
+            backend.clone(),
+            test_model(),
+            BatchOptions {
+                max_size: 4,
+                max_delay_ms: 1_000,
+            },
+        );
+
+        let (a, b, c, d) = tokio::join!(
+            batcher.predict(vec![0; 1]),
+            batcher.predict(vec![0; 2]),
+            batcher.predict(vec![0; 3]),
+            batcher.predict(vec![0; 4]),
+        );
+        assert_eq!(a.unwrap(), vec![1.0, 1.0]);
+        assert_eq!(b.unwrap(), vec![2.0, 1.0]);
+        assert_eq!(c.unwrap(), vec![3.0, 1.0]);
+        assert_eq!(d.unwrap(), vec![4.0, 1.0]);
+        assert_eq!(backend.calls(), 1);
+        assert_eq!(backend.images(), 4);
+    }
+}
@@ -0,0 +1,502 @@
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tonic::{Code, Status};
+
+use crate::config::Model;
+
+// 每个模型的熔断器参数，可以在config.yaml的circuit_breaker部分配置
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct BreakerOptions {
+    pub enabled: bool,
+    // 按最近多少次调用计算失败率
+    pub window_size: usize,
+    // 最近的调用少于这个数量时不熔断
+    pub min_calls: usize,
+    // 失败率达到这个比例时熔断，0到1之间
+    pub failure_rate_threshold: f64,
+    // 熔断后等待多久（毫秒）再放行试探的请求
+    pub open_ms: u64,
+    // 半开状态下同时放行的试探请求数量，全部成功后恢复
+    pub half_open_max_calls: usize,
+}
+
+impl Default for BreakerOptions {
+    fn default() -> Self {
+        BreakerOptions {
+            enabled: true,
+            window_size: 20,
+            min_calls: 10,
+            failure_rate_threshold: 0.5,
+            open_ms: 10_000,
+            half_open_max_calls: 3,
+        }
+    }
+}
+
+// 熔断器的状态
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BreakerState {
+    // 正常转发请求
+    Closed,
+    // 直接返回UNAVAILABLE，不调用推理服务
+    Open,
+    // 只放行少量试探的请求
+    HalfOpen,
+}
+
+impl fmt::Display for BreakerState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BreakerState::Closed => write!(f, "closed"),
+            BreakerState::Open => write!(f, "open"),
+            BreakerState::HalfOpen => write!(f, "half-open"),
+        }
+    }
+}
+
+// 熔断器使用的时钟，测试中可以替换为手动推进的时钟
+pub trait Clock: Send + Sync + fmt::Debug {
+    fn now(&self) -> Instant;
+}
+
+#[derive(Debug)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+// 所有模型的熔断器，同一个模型的所有请求共享一个熔断器
+#[derive(Debug)]
+pub struct CircuitBreakers {
+    options: BreakerOptions,
+    clock: Arc<dyn Clock>,
+    // 模型名称对应的版本和熔断器，版本变化后重新开始统计
+    breakers: Mutex<HashMap<String, (u32, Arc<CircuitBreaker>)>>,
+}
+
+impl CircuitBreakers {
+    pub fn new(options: BreakerOptions) -> Self {
+        CircuitBreakers::with_clock(options, Arc::new(SystemClock))
+    }
+
+    pub fn with_clock(options: BreakerOptions, clock: Arc<dyn Clock>) -> Self {
+        CircuitBreakers {
+            options,
+            clock,
+            breakers: Mutex::new(HashMap::new()),
+        }
+    }
+
+    // 模型对应的熔断器，没有时创建一个
+    pub fn get(&self, model: &Model) -> Arc<CircuitBreaker> {
+        let mut breakers = self.breakers.lock().unwrap();
+        match breakers.get(&model.name) {
+            Some((version, breaker)) if *version == model.version => Arc::clone(breaker),
+            _ => {
+                let breaker = Arc::new(CircuitBreaker::new(
+                    &model.name,
+                    self.options.clone(),
+                    Arc::clone(&self.clock),
+                ));
+                breakers.insert(model.name.clone(), (model.version, Arc::clone(&breaker)));
+                breaker
+            }
+        }
+    }
+
+    // 模型的熔断器状态，还没有请求过的模型视为closed
+    pub fn state(&self, model_name: &str) -> BreakerState {
+        match self.breakers.lock().unwrap().get(model_name) {
+            Some((_, breaker)) => breaker.state(),
+            None => BreakerState::Closed,
+        }
+    }
+
+    // 模型从配置中删除后丢弃它的熔断器
+    pub fn forget(&self, model_names: &[String]) {
+        let mut breakers = self.breakers.lock().unwrap();
+        for name in model_names {
+            breakers.remove(name);
+        }
+    }
+}
+
+// 一个模型的熔断器：最近的调用中失败率过高时熔断，等待open_ms后放行试探的请求，
+// 试探的请求全部成功后恢复，任何一个失败则重新熔断
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    model_name: String,
+    options: BreakerOptions,
+    clock: Arc<dyn Clock>,
+    inner: Mutex<Inner>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    state: BreakerState,
+    // 最近的调用是否失败
+    outcomes: VecDeque<bool>,
+    failures: usize,
+    opened_at: Instant,
+    // 每次进入半开状态时加一，用来识别之前放行的试探请求
+    epoch: u64,
+    probes_in_flight: usize,
+    probe_successes: usize,
+}
+
+impl CircuitBreaker {
+    fn new(model_name: &str, options: BreakerOptions, clock: Arc<dyn Clock>) -> Self {
+        let now = clock.now();
+        CircuitBreaker {
+            model_name: model_name.to_string(),
+            options,
+            clock,
+            inner: Mutex::new(Inner {
+                state: BreakerState::Closed,
+                outcomes: VecDeque::new(),
+                failures: 0,
+                opened_at: now,
+                epoch: 0,
+                probes_in_flight: 0,
+                probe_successes: 0,
+            }),
+        }
+    }
+
+    // 熔断后等待时间已过时视为半开，下一个请求会被放行
+    pub fn state(&self) -> BreakerState {
+        let inner = self.inner.lock().unwrap();
+        match inner.state {
+            BreakerState::Open if self.cool_down_elapsed(&inner) => BreakerState::HalfOpen,
+            state => state,
+        }
+    }
+
+    // 调用推理服务之前获取许可，熔断时返回错误信息，调用方返回UNAVAILABLE
+    // 返回的BreakerCall需要在调用结束后通过finish记录结果
+    pub fn acquire(self: &Arc<Self>) -> Result<BreakerCall, String> {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.state == BreakerState::Open && self.cool_down_elapsed(&inner) {
+            inner.state = BreakerState::HalfOpen;
+            inner.epoch += 1;
+            inner.probes_in_flight = 0;
+            inner.probe_successes = 0;
+            info!(
+                "circuit breaker for model {} is half-open, probing TF Serving",
+                self.model_name
+            );
+        }
+
+        let probe = match inner.state {
+            BreakerState::Closed => None,
+            BreakerState::HalfOpen if inner.probes_in_flight < self.options.half_open_max_calls => {
+                inner.probes_in_flight += 1;
+                Some(inner.epoch)
+            }
+            state => {
+                return Err(format!(
+                    "Circuit breaker for model {} is {}",
+                    self.model_name, state
+                ))
+            }
+        };
+        Ok(BreakerCall {
+            breaker: Arc::clone(self),
+            probe,
+            finished: false,
+        })
+    }
+
+    fn cool_down_elapsed(&self, inner: &Inner) -> bool {
+        self.clock.now().duration_since(inner.opened_at)
+            >= Duration::from_millis(self.options.open_ms)
+    }
+
+    // failed为None表示调用被取消，不计入统计
+    fn record(&self, probe: Option<u64>, failed: Option<bool>) {
+        let mut inner = self.inner.lock().unwrap();
+        match (inner.state, probe) {
+            (BreakerState::Closed, None) => {
+                if let Some(failed) = failed {
+                    self.record_closed(&mut inner, failed);
+                }
+            }
+            (BreakerState::HalfOpen, Some(epoch)) if epoch == inner.epoch => {
+                inner.probes_in_flight -= 1;
+                match failed {
+                    Some(true) => {
+                        warn!(
+                            "circuit breaker for model {} is open again, probe failed",
+                            self.model_name
+                        );
+                        self.open(&mut inner);
+                    }
+                    Some(false) => {
+                        inner.probe_successes += 1;
+                        if inner.probe_successes >= self.options.half_open_max_calls {
+                            info!("circuit breaker for model {} is closed", self.model_name);
+                            inner.state = BreakerState::Closed;
+                            inner.outcomes.clear();
+                            inner.failures = 0;
+                        }
+                    }
+                    None => {}
+                }
+            }
+            // 状态变化之前放行的请求不影响当前的状态
+            _ => {}
+        }
+    }
+
+    fn record_closed(&self, inner: &mut Inner, failed: bool) {
+        inner.outcomes.push_back(failed);
+        if failed {
+            inner.failures += 1;
+        }
+        while inner.outcomes.len() > self.options.window_size.max(1) {
+            if inner.outcomes.pop_front() == Some(true) {
+                inner.failures -= 1;
+            }
+        }
+
+        let calls = inner.outcomes.len();
+        let rate = inner.failures as f64 / calls as f64;
+        if calls >= self.options.min_calls && rate >= self.options.failure_rate_threshold {
+            warn!(
+                "circuit breaker for model {} is open, {} of the last {} calls failed",
+                self.model_name, inner.failures, calls
+            );
+            self.open(inner);
+        }
+    }
+
+    fn open(&self, inner: &mut Inner) {
+        inner.state = BreakerState::Open;
+        inner.opened_at = self.clock.now();
+    }
+}
+
+// 一次被放行的调用，被释放前没有记录结果时视为取消
+pub struct BreakerCall {
+    breaker: Arc<CircuitBreaker>,
+    // 半开状态下放行的试探请求所属的epoch
+    probe: Option<u64>,
+    finished: bool,
+}
+
+impl BreakerCall {
+    // 推理服务不可用、超时或内部错误视为失败，请求本身的错误不影响熔断
+    pub fn finish<T>(mut self, result: &Result<T, Status>) {
+        let failed = match result {
+            Ok(_) => false,
+            Err(status) => matches!(
+                status.code(),
+                Code::Unavailable | Code::DeadlineExceeded | Code::Internal | Code::Unknown
+            ),
+        };
+        self.finished = true;
+        self.breaker.record(self.probe, Some(failed));
+    }
+}
+
+impl Drop for BreakerCall {
+    fn drop(&mut self) {
+        if !self.finished {
+            self.breaker.record(self.probe, None);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 手动推进的时钟
+    #[derive(Debug)]
+    struct FakeClock(Mutex<Instant>);
+
+    impl FakeClock {
+        fn new() -> Arc<Self> {
+            Arc::new(FakeClock(Mutex::new(Instant::now())))
+        }
+
+        fn advance(&self, millis: u64) {
+            *self.0.lock().unwrap() += Duration::from_millis(millis);
+        }
+    }
+
+    impl Clock for FakeClock {
+        fn now(&self) -> Instant {
+            *self.0.lock().unwrap()
+        }
+    }
+
+    fn model(version: u32) -> Model {
+        Model {
+            name: "foo".to_string(),
+            version,
+            input_name: "input".to_string(),
+            ..Model::default()
+        }
+    }
+
+    fn options() -> BreakerOptions {
+        BreakerOptions {
+            enabled: true,
+            window_size: 4,
+            min_calls: 4,
+            failure_rate_threshold: 0.5,
+            open_ms: 1_000,
+            half_open_max_calls: 2,
+        }
+    }
+
+    fn call(breaker: &Arc<CircuitBreaker>, code: Code) {
+        let result = match code {
+            Code::Ok => Ok(()),
+            code => Err(Status::new(code, "")),
+        };
+        breaker.acquire().unwrap().finish(&result);
+    }
+
+    // 测试失败率达到阈值后熔断，熔断期间直接返回UNAVAILABLE
+    #[test]
+    fn test_opens_on_failure_rate() {
+        let breakers = CircuitBreakers::with_clock(options(), FakeClock::new());
+        let breaker = breakers.get(&model(1));
+
+        call(&breaker, Code::Ok);
+        call(&breaker, Code::Unavailable);
+        call(&breaker, Code::Ok);
+        assert_eq!(breaker.state(), BreakerState::Closed);
+        // 请求本身的错误不计为失败
+        call(&breaker, Code::InvalidArgument);
+        assert_eq!(breaker.state(), BreakerState::Closed);
+
+        // 最近4次中有2次失败
+        call(&breaker, Code::DeadlineExceeded);
+        assert_eq!(breaker.state(), BreakerState::Open);
+        assert_eq!(breakers.state("foo"), BreakerState::Open);
+
+        assert_eq!(
+            breaker.acquire().err().unwrap(),
+            "Circuit breaker for model foo is open"
+        );
+    }
+
+    // 测试不足min_calls次调用时不熔断，窗口之外的失败不再计入
+    #[test]
+    fn test_window() {
+        let breaker = CircuitBreakers::with_clock(options(), FakeClock::new()).get(&model(1));
+
+        call(&breaker, Code::Unavailable);
+        call(&breaker, Code::Unavailable);
+        call(&breaker, Code::Unavailable);
+        assert_eq!(breaker.state(), BreakerState::Closed);
+
+        call(&breaker, Code::Unavailable);
+        assert_eq!(breaker.state(), BreakerState::Open);
+
+        let breaker = CircuitBreakers::with_clock(options(), FakeClock::new()).get(&model(1));
+        for code in [Code::Unavailable, Code::Ok, Code::Ok, Code::Ok] {
+            call(&breaker, code);
+        }
+        call(&breaker, Code::Unavailable);
+        assert_eq!(breaker.state(), BreakerState::Closed);
+    }
+
+    // 测试等待open_ms后进入半开状态，试探的请求全部成功后恢复
+    #[test]
+    fn test_half_open_closes_after_successful_probes() {
+        let clock = FakeClock::new();
+        let breaker = CircuitBreakers::with_clock(options(), clock.clone()).get(&model(1));
+        for _ in 0..4 {
+            call(&breaker, Code::Unavailable);
+        }
+
+        clock.advance(999);
+        assert!(breaker.acquire().is_err());
+        clock.advance(1);
+        assert_eq!(breaker.state(), BreakerState::HalfOpen);
+
+        // 同时只放行half_open_max_calls个试探请求
+        let first = breaker.acquire().unwrap();
+        let second = breaker.acquire().unwrap();
+        assert_eq!(
+            breaker.acquire().err().unwrap(),
+            "Circuit breaker for model foo is half-open"
+        );
+
+        first.finish(&Ok::<_, Status>(()));
+        assert_eq!(breaker.state(), BreakerState::HalfOpen);
+        second.finish(&Ok::<_, Status>(()));
+        assert_eq!(breaker.state(), BreakerState::Closed);
+        call(&breaker, Code::Unavailable);
+        assert_eq!(breaker.state(), BreakerState::Closed);
+    }
+
+    // 测试试探的请求失败后重新熔断，并重新开始等待
+    #[test]
+    fn test_half_open_reopens_on_failure() {
+        let clock = FakeClock::new();
+        let breaker = CircuitBreakers::with_clock(options(), clock.clone()).get(&model(1));
+        for _ in 0..4 {
+            call(&breaker, Code::Unavailable);
+        }
+        clock.advance(1_000);
+
+        call(&breaker, Code::Unavailable);
+        assert_eq!(breaker.state(), BreakerState::Open);
+        clock.advance(500);
+        assert!(breaker.acquire().is_err());
+        clock.advance(500);
+        assert_eq!(breaker.state(), BreakerState::HalfOpen);
+    }
+
+    // 测试被取消的试探请求归还名额，熔断之前放行的请求不影响半开状态
+    #[test]
+    fn test_cancelled_and_stale_calls() {
+        let clock = FakeClock::new();
+        let breaker = CircuitBreakers::with_clock(options(), clock.clone()).get(&model(1));
+        let stale = breaker.acquire().unwrap();
+        for _ in 0..4 {
+            call(&breaker, Code::Unavailable);
+        }
+        clock.advance(1_000);
+
+        let first = breaker.acquire().unwrap();
+        let _second = breaker.acquire().unwrap();
+        assert!(breaker.acquire().is_err());
+        drop(first);
+        let _third = breaker.acquire().unwrap();
+
+        stale.finish(&Err::<(), _>(Status::unavailable("")));
+        assert_eq!(breaker.state(), BreakerState::HalfOpen);
+    }
+
+    // 测试模型的版本变化后使用新的熔断器
+    #[test]
+    fn test_new_version_resets_breaker() {
+        let breakers = CircuitBreakers::with_clock(options(), FakeClock::new());
+        let breaker = breakers.get(&model(1));
+        for _ in 0..4 {
+            call(&breaker, Code::Unavailable);
+        }
+        assert_eq!(breakers.state("foo"), BreakerState::Open);
+        assert!(breakers.get(&model(1)).acquire().is_err());
+
+        assert!(breakers.get(&model(2)).acquire().is_ok());
+        assert_eq!(breakers.state("foo"), BreakerState::Closed);
+
+        breakers.forget(&["foo".to_string()]);
+        assert!(breakers.breakers.lock().unwrap().is_empty());
+    }
+}
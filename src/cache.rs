@@ -0,0 +1,504 @@
+use log::info;
+use moka::policy::EvictionPolicy;
+use moka::sync::Cache;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::future::Future;
+use std::mem::size_of;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::task;
+use tonic::Status;
+
+use crate::coalesce::SingleFlight;
+use crate::config::Model;
+use crate::metrics::metrics;
+use crate::store::VectorStore;
+use crate::transcode::TranscodeOptions;
+
+// 超过这个大小的图片在阻塞线程池中计算摘要，避免长时间占用处理请求的异步运行时
+const BLOCKING_DIGEST_BYTES: usize = 64 * 1024;
+
+// 缓存满了之后淘汰条目的方式
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone, Copy, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum EvictionKind {
+    // 只接纳比被淘汰的条目更常用的新条目，适合有少量热点图片的负载
+    #[default]
+    TinyLfu,
+    // 淘汰最久没有使用的条目
+    Lru,
+}
+
+// 预测结果缓存的参数，可以在config.yaml的cache部分配置
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct CacheOptions {
+    pub enabled: bool,
+    // 最多缓存的图片数量
+    pub max_entries: u64,
+    // 缓存的预测结果最多占用的字节数
+    pub max_bytes: u64,
+    // 预测结果的有效期，0表示不过期
+    pub ttl_ms: u64,
+    pub eviction: EvictionKind,
+}
+
+impl Default for CacheOptions {
+    fn default() -> Self {
+        CacheOptions {
+            enabled: true,
+            max_entries: 100_000,
+            max_bytes: 256 * 1024 * 1024,
+            ttl_ms: 0,
+            eviction: EvictionKind::TinyLfu,
+        }
+    }
+}
+
+// 图片内容的SHA-256摘要加上模型名称和版本，同一张图片在不同的模型或版本下是不同的条目
+// 模型配置了transcode时摘要也包含转换参数，参数在热加载中变化后不再使用按旧参数转换得到的结果
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CacheKey {
+    pub digest: [u8; 32],
+    pub model_name: String,
+    pub version: u32,
+}
+
+impl CacheKey {
+    pub fn new(model: &Model, image: &[u8]) -> Self {
+        CacheKey {
+            digest: digest(model.transcode.as_ref(), image),
+            model_name: model.name.clone(),
+            version: model.version,
+        }
+    }
+
+    // 较大的图片在阻塞线程池中计算摘要，计算完成后把图片交还给调用方
+    pub async fn compute(model: &Model, image: Vec<u8>) -> Result<(Self, Vec<u8>), Status> {
+        if image.len() < BLOCKING_DIGEST_BYTES {
+            return Ok((CacheKey::new(model, &image), image));
+        }
+        let transcode = model.transcode.clone();
+        let (digest, image) = task::spawn_blocking(move || {
+            let digest = digest(transcode.as_ref(), &image);
+            (digest, image)
+        })
+        .await
+        .map_err(|e| Status::internal(format!("Digest task failed: {}", e)))?;
+        let key = CacheKey {
+            digest,
+            model_name: model.name.clone(),
+            version: model.version,
+        };
+        Ok((key, image))
+    }
+}
+
+// 没有配置transcode时只是图片内容的摘要，与磁盘上已有的结果兼容
+fn digest(transcode: Option<&TranscodeOptions>, image: &[u8]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(image);
+    if let Some(transcode) = transcode {
+        // 序列化一个只包含枚举和数字的结构体不会失败
+        hasher.update(serde_json::to_vec(transcode).unwrap());
+    }
+    hasher.finalize().into()
+}
+
+// 以图片内容为键的预测结果缓存，同一张图片重复提交时不再调用推理服务
+// 先查找内存中的缓存，再查找磁盘上的存储
+pub struct EmbeddingCache {
+    // cache部分的enabled为false时只使用磁盘上的存储
+    cache: Option<Cache<CacheKey, Arc<Vec<f32>>>>,
+    store: Option<Arc<VectorStore>>,
+    // 每个模型最近一次使用的版本，版本变化时清除旧版本的结果
+    versions: Mutex<HashMap<String, u32>>,
+}
+
+impl EmbeddingCache {
+    pub fn new(options: &CacheOptions) -> Self {
+        // moka只支持一个容量上限：每个条目至少按平均份额计算大小，
+        // 这样条目数量不超过max_entries，总大小也不超过max_bytes
+        let max_bytes = options.max_bytes.max(1);
+        let min_weight = max_bytes.div_ceil(options.max_entries.max(1));
+        let eviction = match options.eviction {
+            EvictionKind::TinyLfu => EvictionPolicy::tiny_lfu(),
+            EvictionKind::Lru => EvictionPolicy::lru(),
+        };
+        let mut builder = Cache::builder()
+            .max_capacity(max_bytes)
+            .weigher(move |key: &CacheKey, values: &Arc<Vec<f32>>| {
+                let bytes = (size_of::<CacheKey>()
+                    + key.model_name.len()
+                    + values.len() * size_of::<f32>()) as u64;
+                bytes.max(min_weight).min(u32::MAX as u64) as u32
+            })
+            .eviction_policy(eviction)
+            .support_invalidation_closures();
+        if options.ttl_ms > 0 {
+            builder = builder.time_to_live(Duration::from_millis(options.ttl_ms));
+        }
+        EmbeddingCache {
+            cache: options.enabled.then(|| builder.build()),
+            store: None,
+            versions: Mutex::new(HashMap::new()),
+        }
+    }
+
+    // 内存中没有的结果从磁盘上的存储中查找，新的结果也写入存储
+    pub fn with_store(mut self, store: Arc<VectorStore>) -> Self {
+        self.store = Some(store);
+        self
+    }
+
+    // 查找缓存的预测结果，并记录命中的位置或未命中
+    pub async fn get(&self, model: &Model, key: &CacheKey) -> Option<Vec<f32>> {
+        self.set_version(model);
+        if let Some(values) = self.cache.as_ref().and_then(|cache| cache.get(key)) {
+            metrics().cache_lookup(&model.name, "hit");
+            return Some(values.as_ref().clone());
+        }
+        if let Some(store) = &self.store {
+            if let Some(values) = store.get(key).await {
+                metrics().cache_lookup(&model.name, "disk_hit");
+                if let Some(cache) = &self.cache {
+                    cache.insert(key.clone(), Arc::new(values.clone()));
+                }
+                return Some(values);
+            }
+        }
+        metrics().cache_lookup(&model.name, "miss");
+        None
+    }
+
+    pub fn insert(&self, model: &Model, key: CacheKey, values: Vec<f32>) {
+        // 查找之后模型的版本可能已经变化，旧版本的结果不再放入缓存
+        let current = *self
+            .versions
+            .lock()
+            .unwrap()
+            .entry(model.name.clone())
+            .or_insert(model.version);
+        if current != model.version {
+            return;
+        }
+        // 写入磁盘在后台进行，不等待写入完成
+        if let Some(store) = &self.store {
+            let store = Arc::clone(store);
+            let (key, values) = (key.clone(), values.clone());
+            tokio::spawn(async move { store.put(key, values).await });
+        }
+        if let Some(cache) = &self.cache {
+            cache.insert(key, Arc::new(values));
+        }
+    }
+
+    // 模型的版本变化后清除其他版本的结果，查找时和配置热加载后调用
+    pub fn set_version(&self, model: &Model) {
+        let mut versions = self.versions.lock().unwrap();
+        let previous = versions.insert(model.name.clone(), model.version);
+        if previous.is_none() || previous == Some(model.version) {
+            return;
+        }
+        info!(
+            "model {} version changed to {}, dropping cached predictions",
+            model.name, model.version
+        );
+        let name = model.name.clone();
+        let version = model.version;
+        // 创建缓存时开启了support_invalidation_closures
+        if let Some(cache) = &self.cache {
+            cache
+                .invalidate_entries_if(move |key, _| {
+                    key.model_name == name && key.version != version
+                })
+                .unwrap();
+        }
+    }
+
+    // 配置热加载中被删除的模型不再需要缓存的结果
+    pub fn forget(&self, names: &[String]) {
+        let mut versions = self.versions.lock().unwrap();
+        for name in names {
+            versions.remove(name);
+        }
+        let names = names.to_vec();
+        if let Some(cache) = &self.cache {
+            cache
+                .invalidate_entries_if(move |key, _| names.contains(&key.model_name))
+                .unwrap();
+        }
+    }
+
+    // 删除磁盘上已经不在配置中的模型和版本的结果
+    pub async fn retain(&self, models: &[Model]) {
+        if let Some(store) = &self.store {
+            store.retain(models).await;
+        }
+    }
+}
+
+// 缓存中有该图片的结果时直接返回，否则用图片调用predict并缓存成功的结果
+// 同时进行的相同请求只有一个会调用predict，其他请求等待它的结果
+// 既不缓存也不合并请求时不计算图片的摘要
+pub async fn cached<P, F>(
+    cache: Option<&EmbeddingCache>,
+    flights: Option<&SingleFlight>,
+    model: &Model,
+    image: Vec<u8>,
+    predict: P,
+) -> Result<Vec<f32>, Status>
+where
+    P: FnOnce(Vec<u8>) -> F,
+    F: Future<Output = Result<Vec<f32>, Status>>,
+{
+    if cache.is_none() && flights.is_none() {
+        return predict(image).await;
+    }
+    let (key, image) = CacheKey::compute(model, image).await?;
+    if let Some(cache) = cache {
+        if let Some(values) = cache.get(model, &key).await {
+            return Ok(values);
+        }
+    }
+    // 在等待的请求得到结果之前放入缓存，之后的请求直接命中
+    let predict_and_insert = async {
+        let result = predict(image).await;
+        if let (Some(cache), Ok(values)) = (cache, &result) {
+            cache.insert(model, key.clone(), values.clone());
+        }
+        result
+    };
+    match flights {
+        Some(flights) => flights.run(key.clone(), predict_and_insert).await,
+        None => predict_and_insert.await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::StoreOptions;
+    use tempfile::tempdir;
+
+    fn test_model(name: &str, version: u32) -> Model {
+        Model {
+            name: name.to_string(),
+            version,
+            input_name: "input".to_string(),
+            ..Model::default()
+        }
+    }
+
+    // 测试键由图片内容、模型名称和版本共同决定
+    #[test]
+    fn test_cache_key() {
+        let model = test_model("foo", 1);
+        assert_eq!(CacheKey::new(&model, b"a"), CacheKey::new(&model, b"a"));
+        assert_ne!(CacheKey::new(&model, b"a"), CacheKey::new(&model, b"b"));
+        assert_ne!(
+            CacheKey::new(&model, b"a"),
+            CacheKey::new(&test_model("bar", 1), b"a")
+        );
+        assert_ne!(
+            CacheKey::new(&model, b"a"),
+            CacheKey::new(&test_model("foo", 2), b"a")
+        );
+    }
+
+    // 测试命中时不再调用predict，失败的结果不被缓存
+    #[tokio::test]
+    async fn test_cached_prediction() {
+        let cache = EmbeddingCache::new(&CacheOptions::default());
+        let flights = SingleFlight::new();
+        let model = test_model("cache_hit_model", 1);
+        let image = || b"hello".to_vec();
+
+        let result = cached(Some(&cache), Some(&flights), &model, image(), |_| async {
+            Err(Status::unavailable("down"))
+        })
+        .await;
+        assert_eq!(result.unwrap_err().code(), tonic::Code::Unavailable);
+
+        let result = cached(
+            Some(&cache),
+            Some(&flights),
+            &model,
+            image(),
+            |image| async move {
+                assert_eq!(image, b"hello");
+                Ok(vec![1.0, 2.0])
+            },
+        )
+        .await;
+        assert_eq!(result.unwrap(), vec![1.0, 2.0]);
+        let result = cached(Some(&cache), Some(&flights), &model, image(), |_| async {
+            panic!("the cached prediction is not used")
+        })
+        .await;
+        assert_eq!(result.unwrap(), vec![1.0, 2.0]);
+    }
+
+    // 测试较大的图片在阻塞线程池中计算的摘要与直接计算的相同，图片被交还给调用方
+    #[tokio::test]
+    async fn test_compute_large_image() {
+        let model = test_model("foo", 1);
+        let image = vec![7; BLOCKING_DIGEST_BYTES * 2];
+        let (key, returned) = CacheKey::compute(&model, image.clone()).await.unwrap();
+        assert_eq!(key, CacheKey::new(&model, &image));
+        assert_eq!(returned, image);
+    }
+
+    // 测试模型的transcode参数变化后是不同的条目，没有transcode时只取决于图片内容
+    #[test]
+    fn test_cache_key_transcode() {
+        let plain = test_model("foo", 1);
+        let jpeg = Model {
+            transcode: Some(TranscodeOptions::default()),
+            ..test_model("foo", 1)
+        };
+        let lower_quality = Model {
+            transcode: Some(TranscodeOptions {
+                jpeg_quality: 50,
+                ..TranscodeOptions::default()
+            }),
+            ..test_model("foo", 1)
+        };
+        assert_eq!(
+            CacheKey::new(&plain, b"a").digest,
+            <[u8; 32]>::from(Sha256::digest(b"a"))
+        );
+        assert_ne!(CacheKey::new(&plain, b"a"), CacheKey::new(&jpeg, b"a"));
+        assert_ne!(
+            CacheKey::new(&jpeg, b"a"),
+            CacheKey::new(&lower_quality, b"a")
+        );
+    }
+
+    // 测试模型的版本变化后旧版本的结果被清除，旧版本的结果也不会再被放入缓存
+    #[tokio::test]
+    async fn test_version_change_invalidates() {
+        let cache = EmbeddingCache::new(&CacheOptions::default());
+        let v1 = test_model("versioned_model", 1);
+        let v2 = test_model("versioned_model", 2);
+        let other = test_model("other_model", 1);
+        cache.insert(&v1, CacheKey::new(&v1, b"a"), vec![1.0]);
+        cache.insert(&other, CacheKey::new(&other, b"a"), vec![3.0]);
+
+        assert_eq!(cache.get(&v2, &CacheKey::new(&v2, b"a")).await, None);
+        cache.insert(&v2, CacheKey::new(&v2, b"a"), vec![2.0]);
+        cache.insert(&v1, CacheKey::new(&v1, b"b"), vec![1.0]);
+        memory(&cache).run_pending_tasks();
+
+        assert_eq!(memory(&cache).get(&CacheKey::new(&v1, b"a")), None);
+        assert_eq!(memory(&cache).get(&CacheKey::new(&v1, b"b")), None);
+        assert_eq!(
+            cache.get(&v2, &CacheKey::new(&v2, b"a")).await,
+            Some(vec![2.0])
+        );
+        assert_eq!(
+            cache.get(&other, &CacheKey::new(&other, b"a")).await,
+            Some(vec![3.0])
+        );
+
+        cache.forget(&["other_model".to_string()]);
+        assert_eq!(cache.get(&other, &CacheKey::new(&other, b"a")).await, None);
+    }
+
+    // 测试条目数量和总大小的上限
+    #[test]
+    fn test_capacity_limits() {
+        let model = test_model("bounded_model", 1);
+        let cache = EmbeddingCache::new(&CacheOptions {
+            max_entries: 10,
+            max_bytes: 1024 * 1024,
+            ..CacheOptions::default()
+        });
+        for i in 0..100u32 {
+            cache.insert(&model, CacheKey::new(&model, &i.to_le_bytes()), vec![0.0]);
+        }
+        memory(&cache).run_pending_tasks();
+        assert!(memory(&cache).entry_count() <= 10);
+
+        // 每个结果4KB，1MB只能放下不到256个
+        let cache = EmbeddingCache::new(&CacheOptions {
+            max_entries: 100_000,
+            max_bytes: 1024 * 1024,
+            eviction: EvictionKind::Lru,
+            ..CacheOptions::default()
+        });
+        for i in 0..1000u32 {
+            let key = CacheKey::new(&model, &i.to_le_bytes());
+            cache.insert(&model, key, vec![0.0; 1024]);
+        }
+        memory(&cache).run_pending_tasks();
+        assert!(memory(&cache).entry_count() < 256);
+        assert!(memory(&cache).weighted_size() <= 1024 * 1024);
+    }
+
+    // 测试过期的结果不再被使用
+    #[tokio::test]
+    async fn test_ttl() {
+        let model = test_model("ttl_model", 1);
+        let cache = EmbeddingCache::new(&CacheOptions {
+            ttl_ms: 20,
+            ..CacheOptions::default()
+        });
+        let key = CacheKey::new(&model, b"a");
+        cache.insert(&model, key.clone(), vec![1.0]);
+        assert_eq!(cache.get(&model, &key).await, Some(vec![1.0]));
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(cache.get(&model, &key).await, None);
+    }
+
+    // 测试内存中没有的结果从磁盘上的存储中读取，重启后仍然可用
+    #[tokio::test]
+    async fn test_disk_store() {
+        let dir = tempdir().unwrap();
+        let options = StoreOptions {
+            enabled: true,
+            path: dir.path().to_str().unwrap().to_string(),
+            ..StoreOptions::default()
+        };
+        let open_store = || Arc::new(VectorStore::open(&options).unwrap().0);
+        let model = test_model("disk_model", 1);
+        let key = CacheKey::new(&model, b"a");
+
+        let cache = EmbeddingCache::new(&CacheOptions::default()).with_store(open_store());
+        cache.insert(&model, key.clone(), vec![1.0, 2.0]);
+        // 写入磁盘在后台进行，重新打开存储直到能读到结果
+        let mut written = false;
+        for _ in 0..100 {
+            if open_store().get(&key).await.is_some() {
+                written = true;
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert!(written);
+
+        // 重启后内存中的缓存为空，结果从磁盘读取后放入内存
+        let cache = EmbeddingCache::new(&CacheOptions::default()).with_store(open_store());
+        assert_eq!(cache.get(&model, &key).await, Some(vec![1.0, 2.0]));
+        assert!(memory(&cache).get(&key).is_some());
+
+        // 只使用磁盘上的存储
+        let cache = EmbeddingCache::new(&CacheOptions {
+            enabled: false,
+            ..CacheOptions::default()
+        })
+        .with_store(open_store());
+        assert!(cache.cache.is_none());
+        assert_eq!(cache.get(&model, &key).await, Some(vec![1.0, 2.0]));
+        let text = metrics().encode();
+        assert!(text.contains(
+            r#"image_prediction_cache_lookups_total{model="disk_model",result="disk_hit"} 2"#
+        ));
+    }
+
+    fn memory(cache: &EmbeddingCache) -> &Cache<CacheKey, Arc<Vec<f32>>> {
+        cache.cache.as_ref().unwrap()
+    }
+}
@@ -0,0 +1,218 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Mutex;
+use tokio::sync::watch;
+use tonic::Status;
+
+use crate::cache::CacheKey;
+use crate::metrics::metrics;
+
+// 合并相同请求的参数，可以在config.yaml的coalesce部分配置
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct CoalesceOptions {
+    pub enabled: bool,
+}
+
+impl Default for CoalesceOptions {
+    fn default() -> Self {
+        CoalesceOptions { enabled: true }
+    }
+}
+
+type Flight = watch::Receiver<Option<Result<Vec<f32>, Status>>>;
+
+// 合并同时进行的相同请求：同一个模型和版本的同一张图片只调用一次推理服务，
+// 其他请求等待并共用这次调用的结果或错误
+#[derive(Default)]
+pub struct SingleFlight {
+    flights: Mutex<HashMap<CacheKey, Flight>>,
+}
+
+// 发起调用的请求结束时删除对应的记录，请求被取消时等待的请求会重新发起调用
+struct Leader<'a> {
+    flights: &'a SingleFlight,
+    key: &'a CacheKey,
+}
+
+impl Drop for Leader<'_> {
+    fn drop(&mut self) {
+        self.flights.flights.lock().unwrap().remove(self.key);
+    }
+}
+
+impl SingleFlight {
+    pub fn new() -> Self {
+        SingleFlight::default()
+    }
+
+    // 没有相同的请求在进行时调用predict，否则等待那次调用的结果
+    pub async fn run<F>(&self, key: CacheKey, predict: F) -> Result<Vec<f32>, Status>
+    where
+        F: Future<Output = Result<Vec<f32>, Status>>,
+    {
+        let mut predict = Some(predict);
+        loop {
+            let joined = {
+                let mut flights = self.flights.lock().unwrap();
+                match flights.get(&key) {
+                    Some(flight) => Err(flight.clone()),
+                    None => {
+                        let (tx, rx) = watch::channel(None);
+                        flights.insert(key.clone(), rx);
+                        Ok(tx)
+                    }
+                }
+            };
+            match joined {
+                Ok(tx) => {
+                    let _leader = Leader {
+                        flights: self,
+                        key: &key,
+                    };
+                    // 只有发起调用的请求会走到这里，之后直接返回
+                    let result = predict.take().unwrap().await;
+                    let _ = tx.send(Some(result.clone()));
+                    return result;
+                }
+                Err(mut flight) => {
+                    metrics().coalesced(&key.model_name);
+                    if let Ok(result) = flight.wait_for(Option::is_some).await {
+                        return result.clone().unwrap();
+                    }
+                    // 发起调用的请求被取消，由等待的请求重新发起
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Model;
+    use crate::inference::fake::FakeBackend;
+    use crate::inference::InferenceBackend;
+    use std::sync::Arc;
+    use std::time::Duration;
+    use tonic::Code;
+
+    fn test_model(name: &str) -> Model {
+        Model {
+            name: name.to_string(),
+            version: 1,
+            input_name: "input".to_string(),
+            ..Model::default()
+        }
+    }
+
+    // 同时发起count个相同的请求
+    async fn run_concurrently(
+        flights: &Arc<SingleFlight>,
+        backend: &Arc<FakeBackend>,
+        model: &Model,
+        count: usize,
+    ) -> Vec<Result<Vec<f32>, Status>> {
+        let tasks: Vec<_> = (0..count)
+            .map(|_| {
+                let flights = Arc::clone(flights);
+                let backend = Arc::clone(backend);
+                let model = model.clone();
+                tokio::spawn(async move {
+                    let key = CacheKey::new(&model, b"same image");
+                    flights
+                        .run(key, backend.predict(&model, b"same image"))
+                        .await
+                })
+            })
+            .collect();
+        let mut results = vec![];
+        for task in tasks {
+            results.push(task.await.unwrap());
+        }
+        results
+    }
+
+    // 测试同时进行的相同请求只调用一次推理服务，所有请求得到相同的结果
+    #[tokio::test]
+    async fn test_identical_requests_share_one_call() {
+        let flights = Arc::new(SingleFlight::new());
+        let backend = Arc::new(FakeBackend::new().with_delay(Duration::from_millis(100)));
+        let model = test_model("coalesced_model");
+
+        let results = run_concurrently(&flights, &backend, &model, 10).await;
+        assert_eq!(backend.calls(), 1);
+        for result in results {
+            assert_eq!(
+                result.unwrap(),
+                FakeBackend::expected(&model, b"same image")
+            );
+        }
+        assert!(flights.flights.lock().unwrap().is_empty());
+
+        // 之前的调用结束后再次请求时重新调用
+        run_concurrently(&flights, &backend, &model, 1).await;
+        assert_eq!(backend.calls(), 2);
+    }
+
+    // 测试等待的请求共用同一个错误
+    #[tokio::test]
+    async fn test_identical_requests_share_error() {
+        let flights = Arc::new(SingleFlight::new());
+        let backend = Arc::new(
+            FakeBackend::new()
+                .failing("coalesced_broken")
+                .with_delay(Duration::from_millis(100)),
+        );
+        let model = test_model("coalesced_broken");
+
+        let results = run_concurrently(&flights, &backend, &model, 5).await;
+        assert_eq!(backend.calls(), 1);
+        for result in results {
+            assert_eq!(result.unwrap_err().code(), Code::Unavailable);
+        }
+    }
+
+    // 测试发起调用的请求被取消后，等待的请求重新发起调用
+    #[tokio::test]
+    async fn test_cancelled_leader() {
+        let flights = Arc::new(SingleFlight::new());
+        let backend = Arc::new(FakeBackend::new().with_delay(Duration::from_millis(100)));
+        let model = test_model("coalesced_cancelled");
+        let key = CacheKey::new(&model, b"same image");
+
+        let leader = {
+            let (flights, backend, model, key) = (
+                Arc::clone(&flights),
+                Arc::clone(&backend),
+                model.clone(),
+                key.clone(),
+            );
+            tokio::spawn(async move {
+                flights
+                    .run(key, backend.predict(&model, b"same image"))
+                    .await
+            })
+        };
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        let follower = {
+            let (flights, backend, model) =
+                (Arc::clone(&flights), Arc::clone(&backend), model.clone());
+            tokio::spawn(async move {
+                flights
+                    .run(key, backend.predict(&model, b"same image"))
+                    .await
+            })
+        };
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        leader.abort();
+
+        let result = follower.await.unwrap();
+        assert_eq!(
+            result.unwrap(),
+            FakeBackend::expected(&model, b"same image")
+        );
+        assert_eq!(backend.calls(), 2);
+    }
+}
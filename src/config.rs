@@ -1,42 +1,200 @@
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
-#[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
+use crate::batching::BatchOptions;
+use crate::breaker::BreakerOptions;
+use crate::cache::CacheOptions;
+use crate::coalesce::CoalesceOptions;
+use crate::input::check_api_addr;
+use crate::limits::ConcurrencyOptions;
+use crate::metrics::MetricsOptions;
+use crate::readiness::ReadinessOptions;
+use crate::reload::ReloadOptions;
+use crate::reorder::ReorderOptions;
+use crate::store::StoreOptions;
+use crate::telemetry::TracingOptions;
+use crate::tf_serving::client::{AuthOptions, ClientOptions};
+use crate::tf_serving::grpc_client::GrpcInputEncoding;
+use crate::tf_serving::retry::RetryOptions;
+use crate::transcode::TranscodeOptions;
+use crate::validation::ValidationOptions;
+
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone, Default)]
 pub struct Model {
     pub name: String,
     pub version: u32,
     pub input_name: String,
+    // 批处理参数，不填写时每张图片单独请求TensorFlow Serving
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub batch: Option<BatchOptions>,
+    // 该模型同时进行的预测数量，不填写时使用concurrency部分的max_in_flight_per_model
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_in_flight: Option<usize>,
+    // 调用TensorFlow Serving失败后的重试参数，不填写时使用tf_serving部分的retry
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retry: Option<RetryOptions>,
+    // 该模型所在的TensorFlow Serving实例，不填写时使用tf_serving部分的endpoints或--tensorflow_api_addr
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub endpoints: Option<Vec<String>>,
+    // 调用该模型的超时时间，不填写时使用tf_serving部分的request_timeout_ms或--tf_request_timeout_ms
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_timeout_ms: Option<u64>,
+    // 调用该模型时附带的认证信息，不填写时使用tf_serving部分的auth
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auth: Option<AuthOptions>,
+    // 通过gRPC发送时图片的编码方式，不填写时发送原始字节
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub grpc_input_encoding: Option<GrpcInputEncoding>,
+    // 该模型接受的图片大小和尺寸，不填写时使用validation部分
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub validation: Option<ValidationOptions>,
+    // 模型无法解码的图片格式转换为的格式，不填写时原样转发
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub transcode: Option<TranscodeOptions>,
+}
+
+impl Model {
+    // 检查模型自己的TensorFlow Serving地址、超时时间、认证信息、图片限制和格式转换参数
+    fn validate(&self) -> Result<(), String> {
+        if let Some(endpoints) = &self.endpoints {
+            if endpoints.is_empty() {
+                return Err(format!("Model {} has empty endpoints", self.name));
+            }
+            for addr in endpoints {
+                check_api_addr(addr).map_err(|e| format!("Model {}: {}", self.name, e))?;
+            }
+        }
+        if self.request_timeout_ms == Some(0) {
+            return Err(format!("Model {} has zero request_timeout_ms", self.name));
+        }
+        if let Some(auth) = &self.auth {
+            auth.header_map()
+                .map_err(|e| format!("Model {}: {}", self.name, e))?;
+        }
+        if let Some(validation) = &self.validation {
+            validation
+                .check()
+                .map_err(|e| format!("Model {}: {}", self.name, e))?;
+        }
+        if let Some(transcode) = &self.transcode {
+            transcode
+                .check()
+                .map_err(|e| format!("Model {}: {}", self.name, e))?;
+        }
+        Ok(())
+    }
 }
 
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
 pub struct Config {
     models: Vec<Model>,
+    // TensorFlow Serving客户端的连接参数，不填写时使用默认值
+    #[serde(default)]
+    pub tf_serving: ClientOptions,
+    // 模型可用性检查的参数，不填写时使用默认值
+    #[serde(default)]
+    pub readiness: ReadinessOptions,
+    // 配置文件热加载的参数，不填写时使用默认值
+    #[serde(default)]
+    pub reload: ReloadOptions,
+    // Prometheus指标服务的参数，不填写时使用默认值
+    #[serde(default)]
+    pub metrics: MetricsOptions,
+    // OpenTelemetry链路追踪的参数，不填写时不导出span
+    #[serde(default)]
+    pub tracing: TracingOptions,
+    // 并发预测数量的限制，不填写时使用默认值
+    #[serde(default)]
+    pub concurrency: ConcurrencyOptions,
+    // 按到达顺序返回结果时重排缓冲区的参数，不填写时使用默认值
+    #[serde(default)]
+    pub reorder: ReorderOptions,
+    // 每个模型的熔断器参数，不填写时使用默认值
+    #[serde(default)]
+    pub circuit_breaker: BreakerOptions,
+    // 预测结果缓存的参数，不填写时使用默认值
+    #[serde(default)]
+    pub cache: CacheOptions,
+    // 磁盘上的预测结果存储的参数，不填写时不使用
+    #[serde(default)]
+    pub store: StoreOptions,
+    // 合并同时进行的相同请求，不填写时开启
+    #[serde(default)]
+    pub coalesce: CoalesceOptions,
+    // 转发给TensorFlow Serving之前对图片的校验，不填写时使用默认的限制
+    #[serde(default)]
+    pub validation: ValidationOptions,
 }
 
-pub fn read_config_from_path(
+impl Config {
+    // 构建以模型名称为键的 HashMap，并检查模型名称是否重复、模型自己的客户端参数和图片限制
+    fn model_map(&self) -> Result<HashMap<String, Model>, Box<dyn std::error::Error>> {
+        let mut model_map: HashMap<String, Model> = HashMap::new();
+        self.validation.check()?;
+        for model in &self.models {
+            if model_map.contains_key(&model.name) {
+                return Err(format!("Duplicate model name: {}", model.name).into());
+            }
+            model.validate()?;
+            self.check_batch_size(model)?;
+            model_map.insert(model.name.clone(), model.clone());
+        }
+
+        Ok(model_map)
+    }
+
+    // 批处理队列中等待的图片已经占用了并发名额，批次的大小超过模型或全局的并发上限时永远凑不满
+    fn check_batch_size(&self, model: &Model) -> Result<(), String> {
+        let batch = match &model.batch {
+            Some(batch) => batch,
+            None => return Ok(()),
+        };
+        let model_limit = model
+            .max_in_flight
+            .unwrap_or(self.concurrency.max_in_flight_per_model);
+        for (name, limit) in [
+            ("max_in_flight", model_limit),
+            ("concurrency.max_in_flight", self.concurrency.max_in_flight),
+        ] {
+            if limit > 0 && batch.max_size > limit {
+                return Err(format!(
+                    "Model {} has batch max_size {} larger than {} {}",
+                    model.name, batch.max_size, name, limit
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+// 读取并检查配置文件，返回完整的配置和以模型名称为键的模型表，启动时使用
+pub fn read_config(
     file_path: &str,
-) -> Result<HashMap<String, Model>, Box<dyn std::error::Error>> {
+) -> Result<(Config, HashMap<String, Model>), Box<dyn std::error::Error>> {
     // 读取文件内容
     let file_contents = std::fs::read_to_string(file_path)?;
 
     // 解析 YAML 文件
     let config: Config = serde_yaml::from_str(&file_contents)?;
 
-    // 构建 HashMap
-    let mut model_map: HashMap<String, Model> = HashMap::new();
-    for model in config.models {
-        if model_map.contains_key(&model.name) {
-            return Err(format!("Duplicate model name: {}", model.name).into());
-        }
-        model_map.insert(model.name.clone(), model);
-    }
+    let model_map = config.model_map()?;
+    Ok((config, model_map))
+}
 
+// 只读取模型表，配置热加载时使用，与启动时的检查相同
+pub fn read_config_from_path(
+    file_path: &str,
+) -> Result<HashMap<String, Model>, Box<dyn std::error::Error>> {
+    let (_, model_map) = read_config(file_path)?;
     Ok(model_map)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::tf_serving::balancer::{BalancePolicy, BalancerOptions};
+    use crate::transcode::TargetFormat;
+    use crate::validation::ImageFormat;
     use std::fs::File;
     use std::io::Write;
     use tempfile::tempdir;
@@ -96,7 +254,8 @@ mod tests {
             Some(&Model {
                 version: 1,
                 name: "model1".to_string(),
-                input_name: "input1".to_string()
+                input_name: "input1".to_string(),
+                ..Model::default()
             })
         );
         assert_eq!(
@@ -104,8 +263,265 @@ mod tests {
             Some(&Model {
                 version: 2,
                 name: "model2".to_string(),
-                input_name: "input2".to_string()
+                input_name: "input2".to_string(),
+                ..Model::default()
             })
         );
     }
+
+    // 测试tf_serving部分的解析，未填写的字段使用默认值
+    #[test]
+    fn test_tf_serving_options() {
+        let dir = tempdir().unwrap();
+        let file_path = dir.path().join("client.yaml");
+        let mut file = File::create(&file_path).unwrap();
+        writeln!(
+            file,
+            "models:\n  - name: model1\n    version: 1\n    input_name: input1\ntf_serving:\n  request_timeout_ms: 500\n  http2_prior_knowledge: true"
+        )
+        .unwrap();
+
+        let (config, _) = read_config(file_path.to_str().unwrap()).unwrap();
+        assert_eq!(
+            config.tf_serving,
+            ClientOptions {
+                request_timeout_ms: 500,
+                http2_prior_knowledge: true,
+                ..ClientOptions::default()
+            }
+        );
+
+        // 没有tf_serving部分时使用默认值
+        let file_path = dir.path().join("default.yaml");
+        let mut file = File::create(&file_path).unwrap();
+        writeln!(
+            file,
+            "models:\n  - name: model1\n    version: 1\n    input_name: input1"
+        )
+        .unwrap();
+        let (config, _) = read_config(file_path.to_str().unwrap()).unwrap();
+        assert_eq!(config.tf_serving, ClientOptions::default());
+        assert_eq!(config.readiness, ReadinessOptions::default());
+        assert_eq!(config.reload, ReloadOptions::default());
+        assert_eq!(config.metrics, MetricsOptions::default());
+        assert_eq!(config.cache, CacheOptions::default());
+        assert_eq!(config.store, StoreOptions::default());
+        assert_eq!(config.coalesce, CoalesceOptions::default());
+    }
+
+    // 测试多个TensorFlow Serving实例和负载均衡参数的解析
+    #[test]
+    fn test_endpoints() {
+        let dir = tempdir().unwrap();
+        let file_path = dir.path().join("endpoints.yaml");
+        let mut file = File::create(&file_path).unwrap();
+        writeln!(
+            file,
+            "models:\n  - name: model1\n    version: 1\n    input_name: input1\ntf_serving:\n  endpoints:\n    - http://tfs-0:8501/v1\n    - grpc://tfs-1:8500\n  load_balancing:\n    policy: least_outstanding"
+        )
+        .unwrap();
+
+        let (config, _) = read_config(file_path.to_str().unwrap()).unwrap();
+        assert_eq!(
+            config.tf_serving.endpoints,
+            vec!["http://tfs-0:8501/v1", "grpc://tfs-1:8500"]
+        );
+        assert_eq!(
+            config.tf_serving.load_balancing,
+            BalancerOptions {
+                policy: BalancePolicy::LeastOutstanding,
+                ..BalancerOptions::default()
+            }
+        );
+    }
+
+    // 测试readiness部分的解析
+    #[test]
+    fn test_readiness_options() {
+        let dir = tempdir().unwrap();
+        let file_path = dir.path().join("readiness.yaml");
+        let mut file = File::create(&file_path).unwrap();
+        writeln!(
+            file,
+            "models:\n  - name: model1\n    version: 1\n    input_name: input1\nreadiness:\n  startup_timeout_ms: 0\n  poll_interval_ms: 2000"
+        )
+        .unwrap();
+
+        let (config, _) = read_config(file_path.to_str().unwrap()).unwrap();
+        assert_eq!(
+            config.readiness,
+            ReadinessOptions {
+                startup_timeout_ms: 0,
+                poll_interval_ms: 2000,
+                ..ReadinessOptions::default()
+            }
+        );
+    }
+
+    // 测试模型的batch部分的解析
+    #[test]
+    fn test_model_batch_options() {
+        let dir = tempdir().unwrap();
+        let file_path = dir.path().join("batch.yaml");
+        let mut file = File::create(&file_path).unwrap();
+        writeln!(
+            file,
+            "models:\n  - name: model1\n    version: 1\n    input_name: input1\n    batch:\n      max_size: 16\n      max_delay_ms: 3\n  - name: model2\n    version: 2\n    input_name: input2"
+        )
+        .unwrap();
+
+        let model_map = read_config_from_path(file_path.to_str().unwrap()).unwrap();
+        assert_eq!(
+            model_map["model1"].batch,
+            Some(BatchOptions {
+                max_size: 16,
+                max_delay_ms: 3
+            })
+        );
+        assert_eq!(model_map["model2"].batch, None);
+    }
+
+    // 测试批次的大小不能超过模型或全局的并发上限
+    #[test]
+    fn test_batch_size_within_concurrency() {
+        let dir = tempdir().unwrap();
+        let cases = [
+            ("    max_in_flight: 8\n", "", true),
+            ("    max_in_flight: 4\n", "", false),
+            ("", "concurrency:\n  max_in_flight_per_model: 4\n", false),
+            ("", "concurrency:\n  max_in_flight: 4\n", false),
+            ("", "concurrency:\n  max_in_flight: 0\n", true),
+        ];
+        for (model, concurrency, valid) in cases {
+            let file_path = dir.path().join("batch_concurrency.yaml");
+            let mut file = File::create(&file_path).unwrap();
+            write!(
+                file,
+                "models:\n  - name: model1\n    version: 1\n    input_name: input1\n{}    batch:\n      max_size: 8\n{}",
+                model, concurrency
+            )
+            .unwrap();
+
+            let result = read_config_from_path(file_path.to_str().unwrap());
+            assert_eq!(result.is_ok(), valid, "{}{}", model, concurrency);
+        }
+
+        let config: Config = serde_yaml::from_str(
+            "models:\n  - name: model1\n    version: 1\n    input_name: input1\n    max_in_flight: 4\n    batch:\n      max_size: 8\n",
+        )
+        .unwrap();
+        assert_eq!(
+            config.model_map().unwrap_err().to_string(),
+            "Model model1 has batch max_size 8 larger than max_in_flight 4"
+        );
+    }
+
+    // 测试tf_serving和模型的retry部分的解析，模型的retry覆盖tf_serving部分的设置
+    #[test]
+    fn test_retry_options() {
+        let dir = tempdir().unwrap();
+        let file_path = dir.path().join("retry.yaml");
+        let mut file = File::create(&file_path).unwrap();
+        writeln!(
+            file,
+            "models:\n  - name: model1\n    version: 1\n    input_name: input1\n    retry:\n      max_attempts: 5\n  - name: model2\n    version: 2\n    input_name: input2\ntf_serving:\n  retry:\n    max_attempts: 1\n    initial_backoff_ms: 10"
+        )
+        .unwrap();
+
+        let (config, model_map) = read_config(file_path.to_str().unwrap()).unwrap();
+        assert_eq!(
+            config.tf_serving.retry,
+            RetryOptions {
+                max_attempts: 1,
+                initial_backoff_ms: 10,
+                ..RetryOptions::default()
+            }
+        );
+        assert_eq!(
+            model_map["model1"].retry,
+            Some(RetryOptions {
+                max_attempts: 5,
+                ..RetryOptions::default()
+            })
+        );
+        assert_eq!(model_map["model2"].retry, None);
+    }
+
+    // 测试模型自己的TensorFlow Serving地址、超时时间和认证信息的解析
+    #[test]
+    fn test_model_backend_overrides() {
+        let dir = tempdir().unwrap();
+        let file_path = dir.path().join("routing.yaml");
+        let mut file = File::create(&file_path).unwrap();
+        writeln!(
+            file,
+            "models:\n  - name: model1\n    version: 1\n    input_name: input1\n    endpoints:\n      - grpc://tfs-a:8500\n      - grpc://tfs-b:8500\n    request_timeout_ms: 800\n    auth:\n      bearer_token: secret\n      headers:\n        x-api-key: abc123\n    validation:\n      max_bytes: 1048576\n    transcode:\n      accepted: [jpeg, png, gif]\n      target: png\n  - name: model2\n    version: 2\n    input_name: input2"
+        )
+        .unwrap();
+
+        let model_map = read_config_from_path(file_path.to_str().unwrap()).unwrap();
+        let model1 = &model_map["model1"];
+        assert_eq!(
+            model1.endpoints,
+            Some(vec![
+                "grpc://tfs-a:8500".to_string(),
+                "grpc://tfs-b:8500".to_string()
+            ])
+        );
+        assert_eq!(model1.request_timeout_ms, Some(800));
+        let auth = model1.auth.as_ref().unwrap();
+        assert_eq!(auth.bearer_token.as_deref(), Some("secret"));
+        assert_eq!(auth.headers["x-api-key"], "abc123");
+        // 日志中不出现令牌和请求头的值
+        let debug = format!("{:?}", model1);
+        assert!(!debug.contains("secret"));
+        assert!(!debug.contains("abc123"));
+        let validation = model1.validation.as_ref().unwrap();
+        assert_eq!(validation.max_bytes, 1048576);
+        assert_eq!(validation.max_width, ValidationOptions::default().max_width);
+        let transcode = model1.transcode.as_ref().unwrap();
+        assert_eq!(
+            transcode.accepted,
+            vec![ImageFormat::Jpeg, ImageFormat::Png, ImageFormat::Gif]
+        );
+        assert_eq!(transcode.target, TargetFormat::Png);
+        assert_eq!(transcode.jpeg_quality, 90);
+
+        let model2 = &model_map["model2"];
+        assert_eq!(model2.endpoints, None);
+        assert_eq!(model2.request_timeout_ms, None);
+        assert_eq!(model2.auth, None);
+        assert_eq!(model2.validation, None);
+        assert_eq!(model2.transcode, None);
+    }
+
+    // 测试模型自己的客户端参数不正确的情况
+    #[test]
+    fn test_invalid_model_backend_overrides() {
+        let dir = tempdir().unwrap();
+        let cases = [
+            "    endpoints: []",
+            "    endpoints:\n      - tfs-a:8500",
+            "    request_timeout_ms: 0",
+            "    auth:\n      headers:\n        \"bad header\": value",
+            "    auth:\n      bearer_token: \"line\\nbreak\"",
+            "    validation:\n      max_bytes: 0",
+            "    validation:\n      max_width: 0",
+            "    transcode:\n      jpeg_quality: 0",
+            "    transcode:\n      target: gif",
+        ];
+        for (i, case) in cases.iter().enumerate() {
+            let file_path = dir.path().join(format!("invalid{}.yaml", i));
+            let mut file = File::create(&file_path).unwrap();
+            writeln!(
+                file,
+                "models:\n  - name: model1\n    version: 1\n    input_name: input1\n{}",
+                case
+            )
+            .unwrap();
+
+            let result = read_config_from_path(file_path.to_str().unwrap());
+            assert!(result.is_err(), "case {}", case);
+        }
+    }
 }
@@ -0,0 +1,140 @@
+use std::time::Duration;
+use tokio::time::Instant;
+use tonic::metadata::MetadataMap;
+use tonic::service::Interceptor;
+use tonic::{Request, Status};
+
+// 客户端设置的截止时间通过这个metadata传递，例如"200m"表示200毫秒
+const GRPC_TIMEOUT_KEY: &str = "grpc-timeout";
+
+// 截止时间比客户端设置的提前这么多，留出响应返回客户端的时间
+// tonic的客户端和服务端在grpc-timeout到达时都以CANCELLED结束调用，服务需要在这之前返回DEADLINE_EXCEEDED
+const RESPONSE_MARGIN: Duration = Duration::from_millis(10);
+
+// 请求到达时根据grpc-timeout计算的截止时间，保存在请求的extensions中
+#[derive(Debug, Clone, Copy)]
+struct Deadline(Instant);
+
+// 根据请求中的grpc-timeout计算截止时间，没有设置或格式错误时没有截止时间
+pub fn from_metadata(metadata: &MetadataMap) -> Option<Instant> {
+    let value = metadata.get(GRPC_TIMEOUT_KEY)?.to_str().ok()?;
+    Some(Instant::now() + parse_timeout(value)?.saturating_sub(RESPONSE_MARGIN))
+}
+
+// 作为服务端的拦截器层使用，在请求到达时记录截止时间，读取较大的请求体不会推迟截止时间
+#[derive(Debug, Clone, Copy)]
+pub struct RecordDeadline;
+
+impl Interceptor for RecordDeadline {
+    fn call(&mut self, mut request: Request<()>) -> Result<Request<()>, Status> {
+        if let Some(deadline) = from_metadata(request.metadata()) {
+            request.extensions_mut().insert(Deadline(deadline));
+        }
+        Ok(request)
+    }
+}
+
+// 优先使用请求到达时记录的截止时间
+pub fn from_request<T>(request: &Request<T>) -> Option<Instant> {
+    match request.extensions().get::<Deadline>() {
+        Some(deadline) => Some(deadline.0),
+        None => from_metadata(request.metadata()),
+    }
+}
+
+// grpc-timeout的格式为最多8位数字加一个单位：H、M、S、m、u、n
+fn parse_timeout(value: &str) -> Option<Duration> {
+    if value.len() < 2 || value.len() > 9 {
+        return None;
+    }
+    let (digits, unit) = value.split_at(value.len() - 1);
+    if !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let amount: u64 = digits.parse().ok()?;
+    let timeout = match unit {
+        "H" => Duration::from_secs(amount * 60 * 60),
+        "M" => Duration::from_secs(amount * 60),
+        "S" => Duration::from_secs(amount),
+        "m" => Duration::from_millis(amount),
+        "u" => Duration::from_micros(amount),
+        "n" => Duration::from_nanos(amount),
+        _ => return None,
+    };
+    Some(timeout)
+}
+
+// 没有截止时间时永远等待
+pub async fn sleep_until(deadline: Option<Instant>) {
+    match deadline {
+        Some(deadline) => tokio::time::sleep_until(deadline).await,
+        None => std::future::pending().await,
+    }
+}
+
+pub fn deadline_exceeded(id: i32) -> Status {
+    Status::deadline_exceeded(format!(
+        "Deadline exceeded before the prediction of id {} finished",
+        id
+    ))
+}
+
+pub fn cancelled() -> Status {
+    Status::cancelled("The client cancelled the request")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 测试解析各种单位的grpc-timeout，格式错误时返回None
+    #[test]
+    fn test_parse_timeout() {
+        assert_eq!(parse_timeout("1H"), Some(Duration::from_secs(3600)));
+        assert_eq!(parse_timeout("2M"), Some(Duration::from_secs(120)));
+        assert_eq!(parse_timeout("3S"), Some(Duration::from_secs(3)));
+        assert_eq!(parse_timeout("200m"), Some(Duration::from_millis(200)));
+        assert_eq!(
+            parse_timeout("99999999u"),
+            Some(Duration::from_micros(99999999))
+        );
+        assert_eq!(parse_timeout("5n"), Some(Duration::from_nanos(5)));
+
+        assert_eq!(parse_timeout(""), None);
+        assert_eq!(parse_timeout("m"), None);
+        assert_eq!(parse_timeout("100"), None);
+        assert_eq!(parse_timeout("10x"), None);
+        assert_eq!(parse_timeout("-1S"), None);
+        assert_eq!(parse_timeout("123456789S"), None);
+    }
+
+    // 测试根据metadata计算截止时间
+    #[tokio::test]
+    async fn test_deadline_from_metadata() {
+        let mut metadata = MetadataMap::new();
+        assert!(from_metadata(&metadata).is_none());
+
+        metadata.insert(GRPC_TIMEOUT_KEY, "500m".parse().unwrap());
+        let deadline = from_metadata(&metadata).unwrap();
+        let remaining = deadline - Instant::now();
+        assert!(remaining <= Duration::from_millis(490));
+        assert!(remaining > Duration::from_millis(400));
+    }
+
+    // 测试处理函数使用拦截器记录的截止时间，没有记录时读取metadata
+    #[tokio::test]
+    async fn test_deadline_from_request() {
+        let mut request = Request::new(());
+        assert!(from_request(&request).is_none());
+
+        request
+            .metadata_mut()
+            .insert(GRPC_TIMEOUT_KEY, "500m".parse().unwrap());
+        assert!(from_request(&request).unwrap() > Instant::now() + Duration::from_millis(400));
+
+        let recorded = RecordDeadline.call(request).unwrap();
+        let deadline = from_request(&recorded).unwrap();
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert_eq!(from_request(&recorded), Some(deadline));
+    }
+}
@@ -0,0 +1,164 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+use tonic::Status;
+
+use super::{InferenceBackend, ModelMetadata, TensorSpec};
+use crate::config::Model;
+use crate::tf_serving::error::retryable;
+
+// 用于测试的确定性推理服务：每张图片返回[图片长度, 模型版本]
+#[derive(Default)]
+pub struct FakeBackend {
+    // 这些模型的所有请求都返回可以重试的UNAVAILABLE
+    failing: Mutex<HashSet<String>>,
+    // predict_batch被调用的次数
+    calls: AtomicUsize,
+    // 所有调用中收到的图片总数
+    images: AtomicUsize,
+    // 每次调用返回前等待的时间，用于模拟较慢的推理服务
+    delay: Duration,
+    // 包含这些长度的图片的调用额外等待的时间，用于让结果乱序完成
+    image_delays: HashMap<usize, Duration>,
+    // 正在进行的调用数量，以及它曾经达到的最大值
+    in_flight: AtomicUsize,
+    max_in_flight: AtomicUsize,
+}
+
+impl FakeBackend {
+    pub fn new() -> Self {
+        FakeBackend::default()
+    }
+
+    pub fn failing(self, model_name: &str) -> Self {
+        self.set_failing(model_name, true);
+        self
+    }
+
+    pub fn with_delay(mut self, delay: Duration) -> Self {
+        self.delay = delay;
+        self
+    }
+
+    pub fn with_image_delay(mut self, image_len: usize, delay: Duration) -> Self {
+        self.image_delays.insert(image_len, delay);
+        self
+    }
+
+    // 在运行过程中让模型失败或恢复
+    pub fn set_failing(&self, model_name: &str, failing: bool) {
+        let mut models = self.failing.lock().unwrap();
+        if failing {
+            models.insert(model_name.to_string());
+        } else {
+            models.remove(model_name);
+        }
+    }
+
+    pub fn calls(&self) -> usize {
+        self.calls.load(Ordering::SeqCst)
+    }
+
+    pub fn images(&self) -> usize {
+        self.images.load(Ordering::SeqCst)
+    }
+
+    // 被取消的调用也会被减去
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.load(Ordering::SeqCst)
+    }
+
+    pub fn max_in_flight(&self) -> usize {
+        self.max_in_flight.load(Ordering::SeqCst)
+    }
+
+    // 与predict_batch相同的确定性结果，便于测试断言
+    pub fn expected(model: &Model, image: &[u8]) -> Vec<f32> {
+        vec![image.len() as f32, model.version as f32]
+    }
+
+    // 失败的模型返回的错误
+    fn failure(&self, model: &Model) -> Option<Status> {
+        self.failing.lock().unwrap().contains(&model.name).then(|| {
+            retryable(Status::unavailable(format!(
+                "model {} is broken",
+                model.name
+            )))
+        })
+    }
+}
+
+#[tonic::async_trait]
+impl InferenceBackend for FakeBackend {
+    async fn predict_batch(
+        &self,
+        model: &Model,
+        images: &[&[u8]],
+    ) -> Result<Vec<Vec<f32>>, Status> {
+        self.calls.fetch_add(1, Ordering::SeqCst);
+        self.images.fetch_add(images.len(), Ordering::SeqCst);
+        let delay = images
+            .iter()
+            .filter_map(|image| self.image_delays.get(&image.len()))
+            .fold(self.delay, |delay, image_delay| delay.max(*image_delay));
+        if !delay.is_zero() {
+            let _in_flight = InFlight::enter(self);
+            tokio::time::sleep(delay).await;
+        }
+        if let Some(status) = self.failure(model) {
+            return Err(status);
+        }
+
+        Ok(images
+            .iter()
+            .map(|image| FakeBackend::expected(model, image))
+            .collect())
+    }
+
+    async fn model_status(&self, model: &Model) -> Result<(), Status> {
+        match self.failure(model) {
+            Some(status) => Err(status),
+            None => Ok(()),
+        }
+    }
+
+    async fn model_metadata(&self, model: &Model) -> Result<ModelMetadata, Status> {
+        if let Some(status) = self.failure(model) {
+            return Err(status);
+        }
+        Ok(ModelMetadata {
+            inputs: HashMap::from([(
+                model.input_name.clone(),
+                TensorSpec {
+                    dtype: "DT_STRING".to_string(),
+                    shape: vec![-1],
+                },
+            )]),
+            outputs: HashMap::from([(
+                "output".to_string(),
+                TensorSpec {
+                    dtype: "DT_FLOAT".to_string(),
+                    shape: vec![-1, 2],
+                },
+            )]),
+        })
+    }
+}
+
+// 记录一次正在进行的调用，调用结束或被取消时释放
+struct InFlight<'a>(&'a AtomicUsize);
+
+impl<'a> InFlight<'a> {
+    fn enter(backend: &'a FakeBackend) -> Self {
+        let in_flight = backend.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+        backend.max_in_flight.fetch_max(in_flight, Ordering::SeqCst);
+        InFlight(&backend.in_flight)
+    }
+}
+
+impl Drop for InFlight<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
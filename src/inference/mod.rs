@@ -0,0 +1,42 @@
+#[cfg(test)]
+pub mod fake;
+
+use std::collections::HashMap;
+use tonic::Status;
+
+use crate::config::Model;
+
+// 模型签名中一个输入或输出张量的描述
+#[derive(Debug, Clone, PartialEq)]
+pub struct TensorSpec {
+    pub dtype: String,
+    // -1表示该维度的大小不固定
+    pub shape: Vec<i64>,
+}
+
+// 模型默认签名(serving_default)的输入和输出
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ModelMetadata {
+    pub inputs: HashMap<String, TensorSpec>,
+    pub outputs: HashMap<String, TensorSpec>,
+}
+
+// 推理服务的抽象，TensorFlow Serving的RESTful API和gRPC接口都是它的一种实现
+#[tonic::async_trait]
+pub trait InferenceBackend: Send + Sync {
+    // 对多张图片进行预测，结果的顺序与images的顺序一致
+    async fn predict_batch(&self, model: &Model, images: &[&[u8]])
+        -> Result<Vec<Vec<f32>>, Status>;
+
+    // 对单张图片进行预测，只取第一个预测结果
+    async fn predict(&self, model: &Model, image: &[u8]) -> Result<Vec<f32>, Status> {
+        let predictions = self.predict_batch(model, &[image]).await?;
+        Ok(predictions.into_iter().next().unwrap_or_default())
+    }
+
+    // 检查模型的指定版本是否可用
+    async fn model_status(&self, model: &Model) -> Result<(), Status>;
+
+    // 获取模型默认签名的输入和输出
+    async fn model_metadata(&self, model: &Model) -> Result<ModelMetadata, Status>;
+}
@@ -1,5 +1,7 @@
 use structopt::StructOpt;
 
+use crate::tf_serving::client::ClientOptions;
+
 #[derive(StructOpt, Debug)]
 #[structopt(name = "Image Prediction Service")]
 pub struct Opts {
@@ -11,9 +13,34 @@ pub struct Opts {
     #[structopt(short = "a", long, default_value = "0.0.0.0:1301")]
     pub addr: String,
 
-    /// The TensorFlow Serving RESTful API address.
-    #[structopt(long, default_value = "http://localhost:8501/v1")]
-    pub tensorflow_api_addr: String,
+    /// The TensorFlow Serving RESTful API addresses, or grpc://host:port for its gRPC API.
+    /// Separate several replicas with commas; overrides tf_serving.endpoints in config.yaml.
+    #[structopt(long, use_delimiter = true)]
+    pub tensorflow_api_addr: Vec<String>,
+
+    /// Connect timeout to TensorFlow Serving in milliseconds, overrides config.yaml.
+    #[structopt(long)]
+    pub tf_connect_timeout_ms: Option<u64>,
+
+    /// Request timeout to TensorFlow Serving in milliseconds, overrides config.yaml.
+    #[structopt(long)]
+    pub tf_request_timeout_ms: Option<u64>,
+
+    /// Maximum idle connections kept per TensorFlow Serving host, overrides config.yaml.
+    #[structopt(long)]
+    pub tf_pool_max_idle_per_host: Option<usize>,
+
+    /// Idle timeout of pooled connections in milliseconds, overrides config.yaml.
+    #[structopt(long)]
+    pub tf_pool_idle_timeout_ms: Option<u64>,
+
+    /// Talk HTTP/2 to TensorFlow Serving without upgrade negotiation.
+    #[structopt(long)]
+    pub tf_http2_prior_knowledge: bool,
+
+    /// The IP address and port of the Prometheus /metrics endpoint, overrides config.yaml.
+    #[structopt(long)]
+    pub metrics_addr: Option<String>,
 }
 
 impl Default for Opts {
@@ -21,7 +48,37 @@ impl Default for Opts {
         Opts {
             config: "config.yaml".to_string(),
             addr: "0.0.0.0:1301".to_string(),
-            tensorflow_api_addr: "http://localhost:8501/v1".to_string(),
+            tensorflow_api_addr: vec![],
+            tf_connect_timeout_ms: None,
+            tf_request_timeout_ms: None,
+            tf_pool_max_idle_per_host: None,
+            tf_pool_idle_timeout_ms: None,
+            tf_http2_prior_knowledge: false,
+            metrics_addr: None,
+        }
+    }
+}
+
+impl Opts {
+    // 命令行参数优先于配置文件中的客户端参数
+    pub fn apply_client_options(&self, options: &mut ClientOptions) {
+        if !self.tensorflow_api_addr.is_empty() {
+            options.endpoints = self.tensorflow_api_addr.clone();
+        }
+        if let Some(v) = self.tf_connect_timeout_ms {
+            options.connect_timeout_ms = v;
+        }
+        if let Some(v) = self.tf_request_timeout_ms {
+            options.request_timeout_ms = v;
+        }
+        if let Some(v) = self.tf_pool_max_idle_per_host {
+            options.pool_max_idle_per_host = v;
+        }
+        if let Some(v) = self.tf_pool_idle_timeout_ms {
+            options.pool_idle_timeout_ms = v;
+        }
+        if self.tf_http2_prior_knowledge {
+            options.http2_prior_knowledge = true;
         }
     }
 }
@@ -38,16 +95,22 @@ pub fn read_opts() -> Result<Opts, Box<dyn std::error::Error>> {
         }
     }
 
-    // Check if tensorflow_api_addr starts with "http://" or "https://"
-    if !opts.tensorflow_api_addr.starts_with("http://")
-        && !opts.tensorflow_api_addr.starts_with("https://")
-    {
-        return Err(format!("Invalid tensorflow_api_addr: {}", opts.tensorflow_api_addr).into());
+    for addr in &opts.tensorflow_api_addr {
+        check_api_addr(addr)?;
     }
 
     Ok(opts)
 }
 
+// Check if a TensorFlow Serving address starts with "http://", "https://" or "grpc://"
+pub fn check_api_addr(addr: &str) -> Result<(), String> {
+    if !addr.starts_with("http://") && !addr.starts_with("https://") && !addr.starts_with("grpc://")
+    {
+        return Err(format!("Invalid tensorflow_api_addr: {}", addr));
+    }
+    Ok(())
+}
+
 // Helper function to split IP and PORT from a string in IP:PORT format
 fn split_ip_port(s: &str) -> Option<(&str, &str)> {
     if let Some(idx) = s.find(':') {
@@ -0,0 +1,259 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore, TryAcquireError};
+use tonic::Status;
+
+use crate::config::Model;
+
+// 并发预测数量的限制，可以在config.yaml的concurrency部分配置，0表示不限制
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct ConcurrencyOptions {
+    // 所有模型同时进行的预测数量
+    pub max_in_flight: usize,
+    // 每个模型同时进行的预测数量，可以被模型的max_in_flight覆盖
+    pub max_in_flight_per_model: usize,
+    // 每个流同时进行的预测数量，达到上限后暂停读取该流中的后续图片
+    pub max_in_flight_per_stream: usize,
+    // 达到全局或模型的上限时立即返回RESOURCE_EXHAUSTED，而不是排队等待
+    pub shed_load: bool,
+}
+
+impl Default for ConcurrencyOptions {
+    fn default() -> Self {
+        ConcurrencyOptions {
+            max_in_flight: 512,
+            max_in_flight_per_model: 0,
+            max_in_flight_per_stream: 128,
+            shed_load: false,
+        }
+    }
+}
+
+// 限制全局和每个模型同时进行的预测数量
+pub struct ConcurrencyLimiter {
+    options: ConcurrencyOptions,
+    global: Option<Arc<Semaphore>>,
+    // 模型名称对应的上限和信号量，上限变化时替换为新的信号量
+    per_model: Mutex<HashMap<String, (usize, Arc<Semaphore>)>>,
+}
+
+// 一次预测占用的许可，被释放时归还
+pub struct Permit {
+    _global: Option<OwnedSemaphorePermit>,
+    _model: Option<OwnedSemaphorePermit>,
+}
+
+impl ConcurrencyLimiter {
+    pub fn new(options: ConcurrencyOptions) -> Self {
+        ConcurrencyLimiter {
+            global: limit(options.max_in_flight),
+            per_model: Mutex::new(HashMap::new()),
+            options,
+        }
+    }
+
+    // 每个流使用独立的信号量，不限制时返回None
+    pub fn stream_limit(&self) -> Option<Arc<Semaphore>> {
+        limit(self.options.max_in_flight_per_stream)
+    }
+
+    // 先获取模型的许可，再获取全局许可
+    // 等待模型许可时不占用全局许可，一个饱和的模型不会占满全局上限而影响其他模型
+    // shed_load打开时不等待，达到上限直接返回RESOURCE_EXHAUSTED
+    pub async fn acquire(&self, model: &Model) -> Result<Permit, Status> {
+        let model_limit = model
+            .max_in_flight
+            .unwrap_or(self.options.max_in_flight_per_model);
+        let model_permit = match self.model_semaphore(&model.name, model_limit) {
+            Some(semaphore) => Some(
+                self.acquire_one(&semaphore, || {
+                    format!(
+                        "Too many concurrent predictions for model {}, limit {}",
+                        model.name, model_limit
+                    )
+                })
+                .await?,
+            ),
+            None => None,
+        };
+        let global = match &self.global {
+            Some(semaphore) => Some(
+                self.acquire_one(semaphore, || {
+                    format!(
+                        "Too many concurrent predictions, limit {}",
+                        self.options.max_in_flight
+                    )
+                })
+                .await?,
+            ),
+            None => None,
+        };
+
+        Ok(Permit {
+            _global: global,
+            _model: model_permit,
+        })
+    }
+
+    async fn acquire_one(
+        &self,
+        semaphore: &Arc<Semaphore>,
+        message: impl FnOnce() -> String,
+    ) -> Result<OwnedSemaphorePermit, Status> {
+        if !self.options.shed_load {
+            // 信号量不会被关闭
+            return Ok(Arc::clone(semaphore).acquire_owned().await.unwrap());
+        }
+        match Arc::clone(semaphore).try_acquire_owned() {
+            Ok(permit) => Ok(permit),
+            Err(TryAcquireError::NoPermits) | Err(TryAcquireError::Closed) => {
+                Err(Status::resource_exhausted(message()))
+            }
+        }
+    }
+
+    // 配置热加载后模型的上限可能变化，旧信号量上的许可在释放后随之丢弃
+    fn model_semaphore(&self, model_name: &str, max: usize) -> Option<Arc<Semaphore>> {
+        if max == 0 {
+            return None;
+        }
+        let mut per_model = self.per_model.lock().unwrap();
+        match per_model.get(model_name) {
+            Some((current, semaphore)) if *current == max => Some(Arc::clone(semaphore)),
+            _ => {
+                let semaphore = Arc::new(Semaphore::new(max));
+                per_model.insert(model_name.to_string(), (max, Arc::clone(&semaphore)));
+                Some(semaphore)
+            }
+        }
+    }
+}
+
+impl Default for ConcurrencyLimiter {
+    fn default() -> Self {
+        ConcurrencyLimiter::new(ConcurrencyOptions::default())
+    }
+}
+
+fn limit(max: usize) -> Option<Arc<Semaphore>> {
+    (max > 0).then(|| Arc::new(Semaphore::new(max)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use tokio::time::timeout;
+    use tonic::Code;
+
+    fn model_named(name: &str) -> Model {
+        Model {
+            name: name.to_string(),
+            version: 1,
+            input_name: "input".to_string(),
+            ..Model::default()
+        }
+    }
+
+    fn options(max_in_flight: usize, per_model: usize, shed_load: bool) -> ConcurrencyOptions {
+        ConcurrencyOptions {
+            max_in_flight,
+            max_in_flight_per_model: per_model,
+            max_in_flight_per_stream: 0,
+            shed_load,
+        }
+    }
+
+    // 测试达到全局上限后新的预测等待，直到有许可被释放
+    #[tokio::test]
+    async fn test_global_limit_waits() {
+        let limiter = ConcurrencyLimiter::new(options(2, 0, false));
+        let foo = model_named("foo");
+        let bar = model_named("bar");
+
+        let first = limiter.acquire(&foo).await.unwrap();
+        let _second = limiter.acquire(&bar).await.unwrap();
+        let waiting = timeout(Duration::from_millis(50), limiter.acquire(&foo)).await;
+        assert!(waiting.is_err());
+
+        drop(first);
+        let third = timeout(Duration::from_millis(50), limiter.acquire(&foo)).await;
+        assert!(third.unwrap().is_ok());
+    }
+
+    // 测试每个模型的上限互不影响，模型配置中的max_in_flight覆盖默认值
+    #[tokio::test]
+    async fn test_per_model_limit() {
+        let limiter = ConcurrencyLimiter::new(options(0, 1, false));
+        let foo = model_named("foo");
+        let bar = Model {
+            max_in_flight: Some(2),
+            ..model_named("bar")
+        };
+
+        let _foo = limiter.acquire(&foo).await.unwrap();
+        assert!(timeout(Duration::from_millis(50), limiter.acquire(&foo))
+            .await
+            .is_err());
+
+        let _bar1 = limiter.acquire(&bar).await.unwrap();
+        let _bar2 = limiter.acquire(&bar).await.unwrap();
+        assert!(timeout(Duration::from_millis(50), limiter.acquire(&bar))
+            .await
+            .is_err());
+
+        // 上限变化后使用新的信号量
+        let bar = Model {
+            max_in_flight: Some(3),
+            ..bar
+        };
+        assert!(limiter.acquire(&bar).await.is_ok());
+    }
+
+    // 测试一个模型的许可用完后，等待它的请求不占用全局许可，其他模型仍然可以预测
+    #[tokio::test]
+    async fn test_saturated_model_does_not_starve_others() {
+        let limiter = Arc::new(ConcurrencyLimiter::new(options(2, 1, false)));
+        let foo = model_named("foo");
+
+        let _foo = limiter.acquire(&foo).await.unwrap();
+        let waiting: Vec<_> = (0..3)
+            .map(|_| {
+                let limiter = Arc::clone(&limiter);
+                let foo = foo.clone();
+                tokio::spawn(async move { limiter.acquire(&foo).await.map(|_| ()) })
+            })
+            .collect();
+        tokio::time::sleep(Duration::from_millis(20)).await;
+
+        let bar = timeout(
+            Duration::from_millis(50),
+            limiter.acquire(&model_named("bar")),
+        )
+        .await;
+        assert!(bar.unwrap().is_ok());
+        assert!(waiting.iter().all(|task| !task.is_finished()));
+    }
+
+    // 测试开启shed_load后达到上限直接返回RESOURCE_EXHAUSTED
+    #[tokio::test]
+    async fn test_shed_load() {
+        let limiter = ConcurrencyLimiter::new(options(2, 1, true));
+        let foo = model_named("foo");
+        let bar = model_named("bar");
+
+        let _foo = limiter.acquire(&foo).await.unwrap();
+        let status = limiter.acquire(&foo).await.err().unwrap();
+        assert_eq!(status.code(), Code::ResourceExhausted);
+        assert_eq!(
+            status.message(),
+            "Too many concurrent predictions for model foo, limit 1"
+        );
+
+        let _bar = limiter.acquire(&bar).await.unwrap();
+        let status = limiter.acquire(&model_named("baz")).await.err().unwrap();
+        assert_eq!(status.message(), "Too many concurrent predictions, limit 2");
+    }
+}
@@ -1,18 +1,44 @@
+mod batching;
+mod breaker;
+mod cache;
+mod coalesce;
 mod config;
+mod deadline;
+mod inference;
 mod input;
+mod limits;
 mod logger;
+mod metrics;
 mod pb;
+mod readiness;
+mod registry;
+mod reload;
+mod reorder;
 mod service;
+mod store;
+mod telemetry;
 mod tf_serving;
-use std::{collections::HashMap, sync::Arc};
+mod transcode;
+mod validation;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
 
-use config::{read_config_from_path, Model};
-use input::read_opts;
+use cache::EmbeddingCache;
+use config::{read_config, Config, Model};
+use deadline::RecordDeadline;
+use inference::InferenceBackend;
+use input::{check_api_addr, read_opts};
 use log::{debug, error, info, warn};
 use logger::init_logging;
 use pb::image_prediction_pb::image_prediction_server::ImagePredictionServer;
+use readiness::{spawn_poller, wait_until_ready, ModelReadiness};
+use reload::Reloader;
 use service::ImagePredictionService;
+use store::VectorStore;
+use tf_serving::router::ModelRouter;
 use tonic::transport::Server;
+use tonic_health::pb::health_server::{Health, HealthServer};
 
 use crate::input::Opts;
 
@@ -34,7 +60,7 @@ fn main() {
     debug!("{:?}", opts);
 
     // pass the config file name to the read_config function
-    let model_map: HashMap<String, Model> = match read_config_from_path(&opts.config) {
+    let (config, model_map): (Config, HashMap<String, Model>) = match read_config(&opts.config) {
         Ok(t) => t,
         Err(e) => {
             if e.to_string().contains("No such file") {
@@ -61,22 +87,185 @@ fn main() {
         .build()
         .unwrap();
 
-    let image_predction = ImagePredictionService {
-        models: Arc::new(model_map),
-        tf_serving_url: Arc::new(opts.tensorflow_api_addr),
+    // 命令行参数覆盖配置文件中的客户端参数
+    let mut client_options = config.tf_serving.clone();
+    opts.apply_client_options(&mut client_options);
+    info!("tf serving client options: {:?}", client_options);
+
+    if client_options.endpoints.is_empty() {
+        error!("no TensorFlow Serving endpoints in tf_serving.endpoints or --tensorflow_api_addr");
+        std::process::exit(1);
+    }
+    for addr in &client_options.endpoints {
+        if let Err(e) = check_api_addr(addr) {
+            error!("{}", e);
+            std::process::exit(1);
+        }
+    }
+
+    // 模型可以有自己的TensorFlow Serving地址、超时时间和认证信息
+    let backend = match ModelRouter::new(client_options) {
+        Ok(c) => Arc::new(c),
+        Err(e) => {
+            error!("cannot build tf serving client: {:?}", e);
+            std::process::exit(1);
+        }
     };
 
-    rt.block_on(start_gpc_server(&opts.addr, image_predction))
-        .unwrap();
+    // 批处理队列的后台任务需要运行在tokio运行时中
+    let _guard = rt.enter();
+
+    // 在单独的端口上提供Prometheus指标
+    if config.metrics.enabled {
+        let metrics_addr = opts.metrics_addr.as_ref().unwrap_or(&config.metrics.addr);
+        let started = match metrics_addr.parse() {
+            Ok(addr) => metrics::spawn_server(&addr).map_err(|e| e.to_string()),
+            Err(e) => Err(format!("{}", e)),
+        };
+        if let Err(e) = started {
+            error!("cannot start metrics server on {}: {}", metrics_addr, e);
+            std::process::exit(1);
+        }
+    }
+
+    // 通过OTLP导出span，traceparent的传递不依赖于这里的配置
+    if config.tracing.enabled {
+        if let Err(e) = telemetry::install(&config.tracing) {
+            error!("cannot export traces to {}: {}", config.tracing.endpoint, e);
+            std::process::exit(1);
+        }
+        info!("exporting traces to {}", config.tracing.endpoint);
+    }
+    rt.block_on(check_model_metadata(backend.as_ref(), &model_map));
+
+    // 启动前等待所有模型可用，并在后台定期检查模型状态
+    let (health_reporter, health_service) = tonic_health::server::health_reporter();
+    let readiness = ModelReadiness::new()
+        .with_health(health_reporter)
+        .with_breakers(config.circuit_breaker.clone());
+    let readiness_options = &config.readiness;
+    let models: Vec<Model> = model_map.values().cloned().collect();
+    rt.block_on(readiness.report_health(&models));
+    if readiness_options.enabled {
+        info!(
+            "waiting up to {} ms for {} models to become available",
+            readiness_options.startup_timeout_ms,
+            models.len()
+        );
+        let ready = rt.block_on(wait_until_ready(
+            backend.as_ref(),
+            &models,
+            &readiness,
+            readiness_options,
+        ));
+        if let Err(unavailable) = ready {
+            for (name, reason) in unavailable {
+                error!("model {} is not available: {}", name, reason);
+            }
+            std::process::exit(1);
+        }
+    }
+
+    let mut image_predction = ImagePredictionService::new(model_map, backend.clone())
+        .with_readiness(readiness.clone())
+        .with_concurrency(config.concurrency.clone())
+        .with_reorder(config.reorder.clone());
+    let mut reloader = Reloader::new(
+        &opts.config,
+        image_predction.registry.clone(),
+        readiness.clone(),
+    )
+    .with_status_check(readiness_options.enabled)
+    .with_router(backend);
+
+    // 不合法的图片在转发给推理服务之前被拒绝
+    if config.validation.enabled {
+        image_predction = image_predction.with_validation(config.validation.clone());
+    }
+
+    // 同时进行的相同请求只调用一次推理服务
+    if config.coalesce.enabled {
+        image_predction = image_predction.with_coalescing();
+    }
+
+    // 重复提交的图片直接使用缓存的预测结果，磁盘上的存储在重启后仍然可用
+    if config.cache.enabled || config.store.enabled {
+        info!("prediction cache options: {:?}", config.cache);
+        let mut cache = EmbeddingCache::new(&config.cache);
+        if config.store.enabled {
+            let store = match VectorStore::open(&config.store) {
+                Ok((store, summary)) => {
+                    info!(
+                        "vector store {} opened: {} vectors, {} bytes, {} corrupted files removed",
+                        config.store.path, summary.entries, summary.bytes, summary.removed
+                    );
+                    Arc::new(store)
+                }
+                Err(e) => {
+                    error!("cannot open vector store {}: {}", config.store.path, e);
+                    std::process::exit(1);
+                }
+            };
+            rt.block_on(store.retain(&models));
+            cache = cache.with_store(store);
+        }
+        let cache = Arc::new(cache);
+        image_predction = image_predction.with_cache(Arc::clone(&cache));
+        reloader = reloader.with_cache(cache);
+    }
+
+    // 后台检查使用最新的模型表，热加载后新增的模型也会被检查
+    if readiness_options.enabled && readiness_options.poll_interval_ms > 0 {
+        spawn_poller(
+            image_predction.registry.clone(),
+            readiness.clone(),
+            Duration::from_millis(readiness_options.poll_interval_ms),
+        );
+    }
+
+    // 配置文件变化或收到SIGHUP时重新加载模型配置
+    reloader.spawn(&config.reload);
+
+    let served = rt.block_on(start_gpc_server(
+        &opts.addr,
+        image_predction,
+        health_service,
+    ));
+    telemetry::shutdown();
+    served.unwrap();
+}
+
+// 检查配置中的input_name是否存在于模型的默认签名中，只记录警告，不阻止启动
+async fn check_model_metadata(backend: &dyn InferenceBackend, model_map: &HashMap<String, Model>) {
+    for model in model_map.values() {
+        match backend.model_metadata(model).await {
+            Ok(metadata) if !metadata.inputs.contains_key(&model.input_name) => warn!(
+                "model {} has no input named {}, available inputs: {:?}",
+                model.name,
+                model.input_name,
+                metadata.inputs.keys().collect::<Vec<_>>()
+            ),
+            Ok(metadata) => debug!("model {} metadata: {:?}", model.name, metadata),
+            Err(e) => warn!(
+                "cannot get metadata of model {}: {}",
+                model.name,
+                e.message()
+            ),
+        }
+    }
 }
 
+// 同时提供标准的grpc.health.v1.Health服务，每个模型对应一个同名的服务
 pub async fn start_gpc_server(
     addr: &str,
     service: ImagePredictionService,
+    health_service: HealthServer<impl Health>,
 ) -> Result<(), Box<dyn std::error::Error>> {
     info!("ImagePredictionServer listening on: {}", addr);
 
     Server::builder()
+        .layer(tonic::service::interceptor(RecordDeadline))
+        .add_service(health_service)
         .add_service(ImagePredictionServer::new(service))
         .serve(addr.parse().unwrap())
         .await?;
@@ -0,0 +1,380 @@
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use log::{error, info};
+use prometheus::{
+    Encoder, HistogramOpts, HistogramVec, IntCounterVec, IntGaugeVec, Opts, Registry, TextEncoder,
+};
+use serde::{Deserialize, Serialize};
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::LazyLock;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+use tokio_stream::Stream;
+use tonic::Status;
+
+// 指标服务的参数，可以在config.yaml的metrics部分配置
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct MetricsOptions {
+    pub enabled: bool,
+    // /metrics所在的地址，与gRPC服务使用不同的端口
+    pub addr: String,
+}
+
+impl Default for MetricsOptions {
+    fn default() -> Self {
+        MetricsOptions {
+            enabled: true,
+            addr: "0.0.0.0:9464".to_string(),
+        }
+    }
+}
+
+// 一次预测中被单独计时的阶段
+#[derive(Debug, Clone, Copy)]
+pub enum Stage {
+    // 模型无法解码的图片的格式转换
+    Transcode,
+    // 图片的Base64编码
+    Encode,
+    // 与TensorFlow Serving之间的一次往返，包括响应的解析
+    Backend,
+    // 从收到图片到得到结果的总耗时
+    Total,
+}
+
+impl Stage {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Stage::Transcode => "transcode",
+            Stage::Encode => "encode",
+            Stage::Backend => "backend",
+            Stage::Total => "total",
+        }
+    }
+}
+
+// 服务的所有Prometheus指标
+pub struct Metrics {
+    registry: Registry,
+    requests: IntCounterVec,
+    successes: IntCounterVec,
+    failures: IntCounterVec,
+    latency: HistogramVec,
+    in_flight: IntGaugeVec,
+    streams: IntCounterVec,
+    active_streams: IntGaugeVec,
+    retries: IntCounterVec,
+    cache_lookups: IntCounterVec,
+    coalesced: IntCounterVec,
+}
+
+static METRICS: LazyLock<Metrics> = LazyLock::new(Metrics::new);
+
+// 全局的指标，服务和TensorFlow Serving客户端都在这里记录
+pub fn metrics() -> &'static Metrics {
+    &METRICS
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let registry = Registry::new_custom(Some("image_prediction".to_string()), None).unwrap();
+
+        let requests = IntCounterVec::new(
+            Opts::new("requests_total", "Images received per model"),
+            &["model"],
+        )
+        .unwrap();
+        let successes = IntCounterVec::new(
+            Opts::new("successes_total", "Images predicted successfully per model"),
+            &["model"],
+        )
+        .unwrap();
+        let failures = IntCounterVec::new(
+            Opts::new(
+                "failures_total",
+                "Images that failed per model and gRPC status code",
+            ),
+            &["model", "code"],
+        )
+        .unwrap();
+        let latency = HistogramVec::new(
+            HistogramOpts::new(
+                "latency_seconds",
+                "Latency of transcoding, base64 encoding, TF Serving round-trip and the whole prediction",
+            ),
+            &["model", "stage"],
+        )
+        .unwrap();
+        let in_flight = IntGaugeVec::new(
+            Opts::new("in_flight", "Images currently being predicted per model"),
+            &["model"],
+        )
+        .unwrap();
+        let streams = IntCounterVec::new(
+            Opts::new("streams_total", "Streams opened per RPC"),
+            &["rpc"],
+        )
+        .unwrap();
+        let active_streams = IntGaugeVec::new(
+            Opts::new("streams_active", "Streams currently open per RPC"),
+            &["rpc"],
+        )
+        .unwrap();
+
+        let retries = IntCounterVec::new(
+            Opts::new("retries_total", "Retried TF Serving calls per model"),
+            &["model"],
+        )
+        .unwrap();
+
+        let cache_lookups = IntCounterVec::new(
+            Opts::new(
+                "cache_lookups_total",
+                "Prediction cache lookups per model and result (hit, disk_hit or miss)",
+            ),
+            &["model", "result"],
+        )
+        .unwrap();
+
+        let coalesced = IntCounterVec::new(
+            Opts::new(
+                "coalesced_total",
+                "Images that waited for an identical in-flight prediction per model",
+            ),
+            &["model"],
+        )
+        .unwrap();
+
+        registry.register(Box::new(requests.clone())).unwrap();
+        registry.register(Box::new(successes.clone())).unwrap();
+        registry.register(Box::new(failures.clone())).unwrap();
+        registry.register(Box::new(latency.clone())).unwrap();
+        registry.register(Box::new(in_flight.clone())).unwrap();
+        registry.register(Box::new(streams.clone())).unwrap();
+        registry.register(Box::new(active_streams.clone())).unwrap();
+        registry.register(Box::new(retries.clone())).unwrap();
+        registry.register(Box::new(cache_lookups.clone())).unwrap();
+        registry.register(Box::new(coalesced.clone())).unwrap();
+
+        Metrics {
+            registry,
+            requests,
+            successes,
+            failures,
+            latency,
+            in_flight,
+            streams,
+            active_streams,
+            retries,
+            cache_lookups,
+            coalesced,
+        }
+    }
+
+    pub fn observe(&self, model_name: &str, stage: Stage, elapsed: Duration) {
+        self.latency
+            .with_label_values(&[model_name, stage.as_str()])
+            .observe(elapsed.as_secs_f64());
+    }
+
+    // 对TensorFlow Serving的一次调用失败后被重试
+    pub fn retry(&self, model_name: &str) {
+        self.retries.with_label_values(&[model_name]).inc();
+    }
+
+    // 在预测结果缓存中查找一张图片，result为hit、disk_hit或miss
+    pub fn cache_lookup(&self, model_name: &str, result: &str) {
+        self.cache_lookups
+            .with_label_values(&[model_name, result])
+            .inc();
+    }
+
+    // 一张图片没有调用推理服务，而是等待相同的请求的结果
+    pub fn coalesced(&self, model_name: &str) {
+        self.coalesced.with_label_values(&[model_name]).inc();
+    }
+
+    // 开始预测一张图片，返回的RequestTimer在结束时记录结果和总耗时
+    pub fn start_request(&self, model_name: &str) -> RequestTimer {
+        self.requests.with_label_values(&[model_name]).inc();
+        self.in_flight.with_label_values(&[model_name]).inc();
+        RequestTimer {
+            model_name: model_name.to_string(),
+            start: Instant::now(),
+        }
+    }
+
+    // 打开一个流，返回的StreamGuard被释放时流被视为关闭
+    pub fn start_stream(&self, rpc: &'static str) -> StreamGuard {
+        self.streams.with_label_values(&[rpc]).inc();
+        self.active_streams.with_label_values(&[rpc]).inc();
+        StreamGuard { rpc }
+    }
+
+    // Prometheus文本格式的所有指标
+    pub fn encode(&self) -> String {
+        let mut buffer = vec![];
+        TextEncoder::new()
+            .encode(&self.registry.gather(), &mut buffer)
+            .unwrap();
+        String::from_utf8(buffer).unwrap()
+    }
+}
+
+// 一张图片的预测，被释放时减少in_flight
+pub struct RequestTimer {
+    model_name: String,
+    start: Instant,
+}
+
+impl RequestTimer {
+    // 按结果记录成功或失败，失败按gRPC状态码分类
+    pub fn finish<T>(self, result: &Result<T, Status>) {
+        let metrics = metrics();
+        match result {
+            Ok(_) => metrics
+                .successes
+                .with_label_values(&[&self.model_name])
+                .inc(),
+            Err(status) => metrics
+                .failures
+                .with_label_values(&[&self.model_name, &format!("{:?}", status.code())])
+                .inc(),
+        }
+        metrics.observe(&self.model_name, Stage::Total, self.start.elapsed());
+    }
+}
+
+impl Drop for RequestTimer {
+    fn drop(&mut self) {
+        metrics()
+            .in_flight
+            .with_label_values(&[&self.model_name])
+            .dec();
+    }
+}
+
+pub struct StreamGuard {
+    rpc: &'static str,
+}
+
+impl Drop for StreamGuard {
+    fn drop(&mut self) {
+        metrics()
+            .active_streams
+            .with_label_values(&[self.rpc])
+            .dec();
+    }
+}
+
+// 响应流结束或被客户端取消时释放StreamGuard
+pub struct MeteredStream<S> {
+    inner: S,
+    _guard: StreamGuard,
+}
+
+impl<S> MeteredStream<S> {
+    pub fn new(inner: S, guard: StreamGuard) -> Self {
+        MeteredStream {
+            inner,
+            _guard: guard,
+        }
+    }
+}
+
+impl<S: Stream + Unpin> Stream for MeteredStream<S> {
+    type Item = S::Item;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        Pin::new(&mut self.inner).poll_next(cx)
+    }
+}
+
+async fn handle(req: Request<Body>) -> Result<Response<Body>, Infallible> {
+    let response = match (req.method(), req.uri().path()) {
+        (&Method::GET, "/metrics") => Response::builder()
+            .header("content-type", TextEncoder::new().format_type())
+            .body(Body::from(metrics().encode())),
+        _ => Response::builder()
+            .status(StatusCode::NOT_FOUND)
+            .body(Body::empty()),
+    };
+    Ok(response.unwrap())
+}
+
+// 在单独的端口上提供/metrics，需要在tokio运行时中调用
+pub fn spawn_server(addr: &SocketAddr) -> Result<SocketAddr, hyper::Error> {
+    let make_svc = make_service_fn(|_| async { Ok::<_, Infallible>(service_fn(handle)) });
+    let server = Server::try_bind(addr)?.serve(make_svc);
+    let local_addr = server.local_addr();
+    info!("metrics listening on: {}", local_addr);
+
+    tokio::spawn(async move {
+        if let Err(e) = server.await {
+            error!("metrics server error: {}", e);
+        }
+    });
+    Ok(local_addr)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 测试请求的结果、耗时和进行中的数量被正确记录
+    #[test]
+    fn test_request_metrics() {
+        let timer = metrics().start_request("metrics_test");
+        assert_eq!(
+            metrics()
+                .in_flight
+                .with_label_values(&["metrics_test"])
+                .get(),
+            1
+        );
+        timer.finish(&Ok::<_, Status>(()));
+
+        metrics()
+            .start_request("metrics_test")
+            .finish(&Err::<(), _>(Status::unavailable("boom")));
+        metrics().observe("metrics_test", Stage::Encode, Duration::from_millis(1));
+
+        let text = metrics().encode();
+        assert!(text.contains(r#"image_prediction_requests_total{model="metrics_test"} 2"#));
+        assert!(text.contains(r#"image_prediction_successes_total{model="metrics_test"} 1"#));
+        assert!(text.contains(
+            r#"image_prediction_failures_total{code="Unavailable",model="metrics_test"} 1"#
+        ));
+        assert!(text.contains(r#"image_prediction_in_flight{model="metrics_test"} 0"#));
+        assert!(text.contains(
+            r#"image_prediction_latency_seconds_count{model="metrics_test",stage="total"} 2"#
+        ));
+        assert!(text.contains(
+            r#"image_prediction_latency_seconds_count{model="metrics_test",stage="encode"} 1"#
+        ));
+    }
+
+    // 测试/metrics接口返回文本格式的指标，其他路径返回404
+    #[tokio::test]
+    async fn test_metrics_endpoint() {
+        let addr = spawn_server(&"127.0.0.1:0".parse().unwrap()).unwrap();
+        metrics()
+            .start_request("endpoint_test")
+            .finish(&Ok::<_, Status>(()));
+
+        let response = reqwest::get(format!("http://{}/metrics", addr))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::OK);
+        let text = response.text().await.unwrap();
+        assert!(text.contains(r#"image_prediction_requests_total{model="endpoint_test"} 1"#));
+
+        let response = reqwest::get(format!("http://{}/other", addr))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::NOT_FOUND);
+    }
+}
@@ -1,3 +1,35 @@
 pub mod image_prediction_pb {
     include!("proto-gen/image_prediction.rs");
+
+    // 测试中读取响应的结果，失败的图片没有向量，成功的图片没有错误
+    #[cfg(test)]
+    impl ImageVectorResponse {
+        pub fn vector(&self) -> &[f32] {
+            match &self.result {
+                Some(image_vector_response::Result::ImageVector(vector)) => &vector.values,
+                _ => &[],
+            }
+        }
+
+        pub fn error(&self) -> Option<Error> {
+            match &self.result {
+                Some(image_vector_response::Result::Error(error)) => Some(error.clone()),
+                _ => None,
+            }
+        }
+    }
+}
+
+// TensorFlow Serving的原生gRPC接口，模块的嵌套关系需要与proto的package保持一致
+pub mod tensorflow {
+    include!("proto-gen/tensorflow.rs");
+
+    #[allow(clippy::doc_overindented_list_items)]
+    pub mod error {
+        include!("proto-gen/tensorflow.error.rs");
+    }
+
+    pub mod serving {
+        include!("proto-gen/tensorflow.serving.rs");
+    }
 }
@@ -11,15 +11,42 @@ pub struct ImagePredictionRequest {
     #[prost(int32, tag = "3")]
     pub id: i32,
 }
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ImageBatchRequest {
+    #[prost(message, repeated, tag = "1")]
+    pub requests: ::prost::alloc::vec::Vec<ImagePredictionRequest>,
+}
 /// Rename ImageVector to ImageVectorResponse
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct ImageVectorResponse {
-    #[prost(float, repeated, tag = "1")]
-    pub vector: ::prost::alloc::vec::Vec<f32>,
     /// Add an int field to indicate the identity and order of the response
     #[prost(int32, tag = "2")]
     pub id: i32,
+    /// Exactly one of the image vector or the error of this image, so that one
+    /// failed image does not tear down the whole stream
+    #[prost(oneof = "image_vector_response::Result", tags = "3, 4")]
+    pub result: ::core::option::Option<image_vector_response::Result>,
+}
+/// Nested message and enum types in `ImageVectorResponse`.
+pub mod image_vector_response {
+    /// Exactly one of the image vector or the error of this image, so that one
+    /// failed image does not tear down the whole stream
+    #[allow(clippy::derive_partial_eq_without_eq)]
+    #[derive(Clone, PartialEq, ::prost::Oneof)]
+    pub enum Result {
+        #[prost(message, tag = "3")]
+        Error(super::Error),
+        #[prost(message, tag = "4")]
+        ImageVector(super::ImageVector),
+    }
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ImageVector {
+    #[prost(float, repeated, tag = "1")]
+    pub values: ::prost::alloc::vec::Vec<f32>,
 }
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
@@ -144,6 +171,63 @@ pub mod image_prediction_client {
                 .insert(GrpcMethod::new("image_prediction.ImagePrediction", "Predict"));
             self.inner.streaming(req, path, codec).await
         }
+        /// Predict a single image, errors are returned as the gRPC status
+        pub async fn predict_one(
+            &mut self,
+            request: impl tonic::IntoRequest<super::ImagePredictionRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::ImageVectorResponse>,
+            tonic::Status,
+        > {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::new(
+                        tonic::Code::Unknown,
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/image_prediction.ImagePrediction/PredictOne",
+            );
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(
+                    GrpcMethod::new("image_prediction.ImagePrediction", "PredictOne"),
+                );
+            self.inner.unary(req, path, codec).await
+        }
+        /// Predict a list of images, one response per image is streamed back as soon
+        /// as it is ready, errors are attached to the response of that image
+        pub async fn predict_batch(
+            &mut self,
+            request: impl tonic::IntoRequest<super::ImageBatchRequest>,
+        ) -> std::result::Result<
+            tonic::Response<tonic::codec::Streaming<super::ImageVectorResponse>>,
+            tonic::Status,
+        > {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::new(
+                        tonic::Code::Unknown,
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/image_prediction.ImagePrediction/PredictBatch",
+            );
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(
+                    GrpcMethod::new("image_prediction.ImagePrediction", "PredictBatch"),
+                );
+            self.inner.server_streaming(req, path, codec).await
+        }
     }
 }
 /// Generated server implementations.
@@ -164,6 +248,29 @@ pub mod image_prediction_server {
             &self,
             request: tonic::Request<tonic::Streaming<super::ImagePredictionRequest>>,
         ) -> std::result::Result<tonic::Response<Self::PredictStream>, tonic::Status>;
+        /// Predict a single image, errors are returned as the gRPC status
+        async fn predict_one(
+            &self,
+            request: tonic::Request<super::ImagePredictionRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::ImageVectorResponse>,
+            tonic::Status,
+        >;
+        /// Server streaming response type for the PredictBatch method.
+        type PredictBatchStream: tonic::codegen::tokio_stream::Stream<
+                Item = std::result::Result<super::ImageVectorResponse, tonic::Status>,
+            >
+            + Send
+            + 'static;
+        /// Predict a list of images, one response per image is streamed back as soon
+        /// as it is ready, errors are attached to the response of that image
+        async fn predict_batch(
+            &self,
+            request: tonic::Request<super::ImageBatchRequest>,
+        ) -> std::result::Result<
+            tonic::Response<Self::PredictBatchStream>,
+            tonic::Status,
+        >;
     }
     #[derive(Debug)]
     pub struct ImagePredictionServer<T: ImagePrediction> {
@@ -293,6 +400,99 @@ pub mod image_prediction_server {
                     };
                     Box::pin(fut)
                 }
+                "/image_prediction.ImagePrediction/PredictOne" => {
+                    #[allow(non_camel_case_types)]
+                    struct PredictOneSvc<T: ImagePrediction>(pub Arc<T>);
+                    impl<
+                        T: ImagePrediction,
+                    > tonic::server::UnaryService<super::ImagePredictionRequest>
+                    for PredictOneSvc<T> {
+                        type Response = super::ImageVectorResponse;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::ImagePredictionRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as ImagePrediction>::predict_one(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = PredictOneSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/image_prediction.ImagePrediction/PredictBatch" => {
+                    #[allow(non_camel_case_types)]
+                    struct PredictBatchSvc<T: ImagePrediction>(pub Arc<T>);
+                    impl<
+                        T: ImagePrediction,
+                    > tonic::server::ServerStreamingService<super::ImageBatchRequest>
+                    for PredictBatchSvc<T> {
+                        type Response = super::ImageVectorResponse;
+                        type ResponseStream = T::PredictBatchStream;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::ResponseStream>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::ImageBatchRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as ImagePrediction>::predict_batch(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = PredictBatchSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.server_streaming(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
                 _ => {
                     Box::pin(async move {
                         Ok(
@@ -0,0 +1,112 @@
+/// The canonical error codes for TensorFlow APIs.
+///
+/// Warnings:
+///
+/// -   Do not change any numeric assignments.
+/// -   Changes to this list should only be made if there is a compelling
+///      need that can't be satisfied in another way.  Such changes
+///      must be approved by at least two OWNERS.
+/// -   These error codes must match gRPC and protobuf error codes (except for
+///      DO_NOT_USE_RESERVED_FOR_FUTURE_EXPANSION_USE_DEFAULT_IN_SWITCH_INSTEAD_).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+#[repr(i32)]
+pub enum Code {
+    /// Not an error; returned on success
+    Ok = 0,
+    /// The operation was cancelled (typically by the caller).
+    Cancelled = 1,
+    /// Unknown error.
+    Unknown = 2,
+    /// Client specified an invalid argument.
+    InvalidArgument = 3,
+    /// Deadline expired before operation could complete.
+    DeadlineExceeded = 4,
+    /// Some requested entity (e.g., file or directory) was not found.
+    NotFound = 5,
+    /// Some entity that we attempted to create (e.g., file or directory)
+    /// already exists.
+    AlreadyExists = 6,
+    /// The caller does not have permission to execute the specified
+    /// operation.
+    PermissionDenied = 7,
+    /// The request does not have valid authentication credentials for the
+    /// operation.
+    Unauthenticated = 16,
+    /// Some resource has been exhausted.
+    ResourceExhausted = 8,
+    /// Operation was rejected because the system is not in a state
+    /// required for the operation's execution.
+    FailedPrecondition = 9,
+    /// The operation was aborted, typically due to a concurrency issue
+    /// like sequencer check failures, transaction aborts, etc.
+    Aborted = 10,
+    /// Operation tried to iterate past the valid input range.
+    OutOfRange = 11,
+    /// Operation is not implemented or not supported/enabled in this service.
+    Unimplemented = 12,
+    /// Internal errors.
+    Internal = 13,
+    /// The service is currently unavailable.
+    Unavailable = 14,
+    /// Unrecoverable data loss or corruption.
+    DataLoss = 15,
+    /// An extra enum entry to prevent people from writing code that
+    /// fails to compile when a new code is added.
+    DoNotUseReservedForFutureExpansionUseDefaultInSwitchInstead = 20,
+}
+impl Code {
+    /// String value of the enum field names used in the ProtoBuf definition.
+    ///
+    /// The values are not transformed in any way and thus are considered stable
+    /// (if the ProtoBuf definition does not change) and safe for programmatic use.
+    pub fn as_str_name(&self) -> &'static str {
+        match self {
+            Code::Ok => "OK",
+            Code::Cancelled => "CANCELLED",
+            Code::Unknown => "UNKNOWN",
+            Code::InvalidArgument => "INVALID_ARGUMENT",
+            Code::DeadlineExceeded => "DEADLINE_EXCEEDED",
+            Code::NotFound => "NOT_FOUND",
+            Code::AlreadyExists => "ALREADY_EXISTS",
+            Code::PermissionDenied => "PERMISSION_DENIED",
+            Code::Unauthenticated => "UNAUTHENTICATED",
+            Code::ResourceExhausted => "RESOURCE_EXHAUSTED",
+            Code::FailedPrecondition => "FAILED_PRECONDITION",
+            Code::Aborted => "ABORTED",
+            Code::OutOfRange => "OUT_OF_RANGE",
+            Code::Unimplemented => "UNIMPLEMENTED",
+            Code::Internal => "INTERNAL",
+            Code::Unavailable => "UNAVAILABLE",
+            Code::DataLoss => "DATA_LOSS",
+            Code::DoNotUseReservedForFutureExpansionUseDefaultInSwitchInstead => {
+                "DO_NOT_USE_RESERVED_FOR_FUTURE_EXPANSION_USE_DEFAULT_IN_SWITCH_INSTEAD_"
+            }
+        }
+    }
+    /// Creates an enum from field names used in the ProtoBuf definition.
+    pub fn from_str_name(value: &str) -> ::core::option::Option<Self> {
+        match value {
+            "OK" => Some(Self::Ok),
+            "CANCELLED" => Some(Self::Cancelled),
+            "UNKNOWN" => Some(Self::Unknown),
+            "INVALID_ARGUMENT" => Some(Self::InvalidArgument),
+            "DEADLINE_EXCEEDED" => Some(Self::DeadlineExceeded),
+            "NOT_FOUND" => Some(Self::NotFound),
+            "ALREADY_EXISTS" => Some(Self::AlreadyExists),
+            "PERMISSION_DENIED" => Some(Self::PermissionDenied),
+            "UNAUTHENTICATED" => Some(Self::Unauthenticated),
+            "RESOURCE_EXHAUSTED" => Some(Self::ResourceExhausted),
+            "FAILED_PRECONDITION" => Some(Self::FailedPrecondition),
+            "ABORTED" => Some(Self::Aborted),
+            "OUT_OF_RANGE" => Some(Self::OutOfRange),
+            "UNIMPLEMENTED" => Some(Self::Unimplemented),
+            "INTERNAL" => Some(Self::Internal),
+            "UNAVAILABLE" => Some(Self::Unavailable),
+            "DATA_LOSS" => Some(Self::DataLoss),
+            "DO_NOT_USE_RESERVED_FOR_FUTURE_EXPANSION_USE_DEFAULT_IN_SWITCH_INSTEAD_" => {
+                Some(Self::DoNotUseReservedForFutureExpansionUseDefaultInSwitchInstead)
+            }
+            _ => None,
+        }
+    }
+}
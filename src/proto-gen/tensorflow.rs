@@ -0,0 +1,230 @@
+/// Dimensions of a tensor.
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct TensorShapeProto {
+    /// Dimensions of the tensor, such as {"input", 30}, {"output", 40}
+    /// for a 30 x 40 2D tensor.
+    #[prost(message, repeated, tag = "2")]
+    pub dim: ::prost::alloc::vec::Vec<tensor_shape_proto::Dim>,
+    /// If true, the number of dimensions in the shape is unknown.
+    #[prost(bool, tag = "3")]
+    pub unknown_rank: bool,
+}
+/// Nested message and enum types in `TensorShapeProto`.
+pub mod tensor_shape_proto {
+    /// One dimension of the tensor.
+    #[allow(clippy::derive_partial_eq_without_eq)]
+    #[derive(Clone, PartialEq, ::prost::Message)]
+    pub struct Dim {
+        /// Size of the tensor in that dimension.
+        /// This value must be >= -1, but values of -1 are reserved for "unknown"
+        /// shapes (values of -1 mean "unknown" dimension).
+        #[prost(int64, tag = "1")]
+        pub size: i64,
+        /// Optional name of the tensor dimension.
+        #[prost(string, tag = "2")]
+        pub name: ::prost::alloc::string::String,
+    }
+}
+/// (== suppress_warning documentation-presence ==)
+/// LINT.IfChange
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+#[repr(i32)]
+pub enum DataType {
+    /// Not a legal value for DataType.  Used to indicate a DataType field
+    /// has not been set.
+    DtInvalid = 0,
+    /// Data types that all computation devices are expected to be
+    /// capable to support.
+    DtFloat = 1,
+    DtDouble = 2,
+    DtInt32 = 3,
+    DtUint8 = 4,
+    DtInt16 = 5,
+    DtInt8 = 6,
+    DtString = 7,
+    /// Single-precision complex
+    DtComplex64 = 8,
+    DtInt64 = 9,
+    DtBool = 10,
+    /// Quantized int8
+    DtQint8 = 11,
+    /// Quantized uint8
+    DtQuint8 = 12,
+    /// Quantized int32
+    DtQint32 = 13,
+    /// Float32 truncated to 16 bits.
+    DtBfloat16 = 14,
+    /// Quantized int16
+    DtQint16 = 15,
+    /// Quantized uint16
+    DtQuint16 = 16,
+    DtUint16 = 17,
+    /// Double-precision complex
+    DtComplex128 = 18,
+    DtHalf = 19,
+    DtResource = 20,
+    /// Arbitrary C++ data types
+    DtVariant = 21,
+    DtUint32 = 22,
+    DtUint64 = 23,
+}
+impl DataType {
+    /// String value of the enum field names used in the ProtoBuf definition.
+    ///
+    /// The values are not transformed in any way and thus are considered stable
+    /// (if the ProtoBuf definition does not change) and safe for programmatic use.
+    pub fn as_str_name(&self) -> &'static str {
+        match self {
+            DataType::DtInvalid => "DT_INVALID",
+            DataType::DtFloat => "DT_FLOAT",
+            DataType::DtDouble => "DT_DOUBLE",
+            DataType::DtInt32 => "DT_INT32",
+            DataType::DtUint8 => "DT_UINT8",
+            DataType::DtInt16 => "DT_INT16",
+            DataType::DtInt8 => "DT_INT8",
+            DataType::DtString => "DT_STRING",
+            DataType::DtComplex64 => "DT_COMPLEX64",
+            DataType::DtInt64 => "DT_INT64",
+            DataType::DtBool => "DT_BOOL",
+            DataType::DtQint8 => "DT_QINT8",
+            DataType::DtQuint8 => "DT_QUINT8",
+            DataType::DtQint32 => "DT_QINT32",
+            DataType::DtBfloat16 => "DT_BFLOAT16",
+            DataType::DtQint16 => "DT_QINT16",
+            DataType::DtQuint16 => "DT_QUINT16",
+            DataType::DtUint16 => "DT_UINT16",
+            DataType::DtComplex128 => "DT_COMPLEX128",
+            DataType::DtHalf => "DT_HALF",
+            DataType::DtResource => "DT_RESOURCE",
+            DataType::DtVariant => "DT_VARIANT",
+            DataType::DtUint32 => "DT_UINT32",
+            DataType::DtUint64 => "DT_UINT64",
+        }
+    }
+    /// Creates an enum from field names used in the ProtoBuf definition.
+    pub fn from_str_name(value: &str) -> ::core::option::Option<Self> {
+        match value {
+            "DT_INVALID" => Some(Self::DtInvalid),
+            "DT_FLOAT" => Some(Self::DtFloat),
+            "DT_DOUBLE" => Some(Self::DtDouble),
+            "DT_INT32" => Some(Self::DtInt32),
+            "DT_UINT8" => Some(Self::DtUint8),
+            "DT_INT16" => Some(Self::DtInt16),
+            "DT_INT8" => Some(Self::DtInt8),
+            "DT_STRING" => Some(Self::DtString),
+            "DT_COMPLEX64" => Some(Self::DtComplex64),
+            "DT_INT64" => Some(Self::DtInt64),
+            "DT_BOOL" => Some(Self::DtBool),
+            "DT_QINT8" => Some(Self::DtQint8),
+            "DT_QUINT8" => Some(Self::DtQuint8),
+            "DT_QINT32" => Some(Self::DtQint32),
+            "DT_BFLOAT16" => Some(Self::DtBfloat16),
+            "DT_QINT16" => Some(Self::DtQint16),
+            "DT_QUINT16" => Some(Self::DtQuint16),
+            "DT_UINT16" => Some(Self::DtUint16),
+            "DT_COMPLEX128" => Some(Self::DtComplex128),
+            "DT_HALF" => Some(Self::DtHalf),
+            "DT_RESOURCE" => Some(Self::DtResource),
+            "DT_VARIANT" => Some(Self::DtVariant),
+            "DT_UINT32" => Some(Self::DtUint32),
+            "DT_UINT64" => Some(Self::DtUint64),
+            _ => None,
+        }
+    }
+}
+/// Information about a Tensor necessary for feeding or retrieval.
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct TensorInfo {
+    #[prost(enumeration = "DataType", tag = "2")]
+    pub dtype: i32,
+    /// The static shape should be recorded here, to the extent that it can
+    /// be known in advance.  In the case of a SparseTensor, this field describes
+    /// the logical shape of the represented tensor (aka dense_shape).
+    #[prost(message, optional, tag = "3")]
+    pub tensor_shape: ::core::option::Option<TensorShapeProto>,
+    #[prost(oneof = "tensor_info::Encoding", tags = "1")]
+    pub encoding: ::core::option::Option<tensor_info::Encoding>,
+}
+/// Nested message and enum types in `TensorInfo`.
+pub mod tensor_info {
+    #[allow(clippy::derive_partial_eq_without_eq)]
+    #[derive(Clone, PartialEq, ::prost::Oneof)]
+    pub enum Encoding {
+        /// For dense `Tensor`s, the name of the tensor in the graph.
+        #[prost(string, tag = "1")]
+        Name(::prost::alloc::string::String),
+    }
+}
+/// SignatureDef defines the signature of a computation supported by a TensorFlow
+/// graph.
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct SignatureDef {
+    /// Named input parameters.
+    #[prost(map = "string, message", tag = "1")]
+    pub inputs: ::std::collections::HashMap<::prost::alloc::string::String, TensorInfo>,
+    /// Named output parameters.
+    #[prost(map = "string, message", tag = "2")]
+    pub outputs: ::std::collections::HashMap<::prost::alloc::string::String, TensorInfo>,
+    /// Extensible method_name information enabling third-party users to mark a
+    /// SignatureDef as supporting a particular method.
+    #[prost(string, tag = "3")]
+    pub method_name: ::prost::alloc::string::String,
+}
+/// Protocol buffer representing a tensor.
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct TensorProto {
+    /// Data type of the tensor.
+    #[prost(enumeration = "DataType", tag = "1")]
+    pub dtype: i32,
+    /// Shape of the tensor.
+    #[prost(message, optional, tag = "2")]
+    pub tensor_shape: ::core::option::Option<TensorShapeProto>,
+    /// Version number.
+    #[prost(int32, tag = "3")]
+    pub version_number: i32,
+    /// Serialized raw tensor content from either Tensor::AsProtoTensorContent or
+    /// memcpy in tensorflow::grpc::EncodeTensorToByteBuffer. This representation
+    /// can be used for all tensor types.
+    #[prost(bytes = "vec", tag = "4")]
+    pub tensor_content: ::prost::alloc::vec::Vec<u8>,
+    /// DT_HALF, DT_BFLOAT16. Note that since protobuf has no int16 type, we'll
+    /// have some pointless zero padding for each value here.
+    #[prost(int32, repeated, tag = "13")]
+    pub half_val: ::prost::alloc::vec::Vec<i32>,
+    /// DT_FLOAT.
+    #[prost(float, repeated, tag = "5")]
+    pub float_val: ::prost::alloc::vec::Vec<f32>,
+    /// DT_DOUBLE.
+    #[prost(double, repeated, tag = "6")]
+    pub double_val: ::prost::alloc::vec::Vec<f64>,
+    /// DT_INT32, DT_INT16, DT_UINT16, DT_INT8, DT_UINT8.
+    #[prost(int32, repeated, tag = "7")]
+    pub int_val: ::prost::alloc::vec::Vec<i32>,
+    /// DT_STRING
+    #[prost(bytes = "vec", repeated, tag = "8")]
+    pub string_val: ::prost::alloc::vec::Vec<::prost::alloc::vec::Vec<u8>>,
+    /// DT_COMPLEX64. scomplex_val(2*i) and scomplex_val(2*i+1) are real
+    /// and imaginary parts of i-th single precision complex.
+    #[prost(float, repeated, tag = "9")]
+    pub scomplex_val: ::prost::alloc::vec::Vec<f32>,
+    /// DT_INT64
+    #[prost(int64, repeated, tag = "10")]
+    pub int64_val: ::prost::alloc::vec::Vec<i64>,
+    /// DT_BOOL
+    #[prost(bool, repeated, tag = "11")]
+    pub bool_val: ::prost::alloc::vec::Vec<bool>,
+    /// DT_COMPLEX128. dcomplex_val(2*i) and dcomplex_val(2*i+1) are real
+    /// and imaginary parts of i-th double precision complex.
+    #[prost(double, repeated, tag = "12")]
+    pub dcomplex_val: ::prost::alloc::vec::Vec<f64>,
+    /// DT_UINT32
+    #[prost(uint32, repeated, tag = "16")]
+    pub uint32_val: ::prost::alloc::vec::Vec<u32>,
+    /// DT_UINT64
+    #[prost(uint64, repeated, tag = "17")]
+    pub uint64_val: ::prost::alloc::vec::Vec<u64>,
+}
@@ -0,0 +1,939 @@
+/// Metadata for an inference request such as the model name and version.
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ModelSpec {
+    /// Required servable name.
+    #[prost(string, tag = "1")]
+    pub name: ::prost::alloc::string::String,
+    /// A named signature to evaluate. If unspecified, the default signature will
+    /// be used.
+    #[prost(string, tag = "3")]
+    pub signature_name: ::prost::alloc::string::String,
+    /// Optional choice of which version of the model to use.
+    ///
+    /// Expected to be left unset in the common case. Should be specified when
+    /// there is a strong version consistency requirement (e.g. when the model
+    /// signature changes across versions and requests need to be
+    /// version-specific).
+    ///
+    /// When left unspecified, the system will serve the best available version.
+    /// This is typically the latest version, though during version transitions,
+    /// notably when serving on a fleet of instances, may be either the previous or
+    /// new version.
+    #[prost(oneof = "model_spec::VersionChoice", tags = "2, 4")]
+    pub version_choice: ::core::option::Option<model_spec::VersionChoice>,
+}
+/// Nested message and enum types in `ModelSpec`.
+pub mod model_spec {
+    /// Optional choice of which version of the model to use.
+    ///
+    /// Expected to be left unset in the common case. Should be specified when
+    /// there is a strong version consistency requirement (e.g. when the model
+    /// signature changes across versions and requests need to be
+    /// version-specific).
+    ///
+    /// When left unspecified, the system will serve the best available version.
+    /// This is typically the latest version, though during version transitions,
+    /// notably when serving on a fleet of instances, may be either the previous or
+    /// new version.
+    #[allow(clippy::derive_partial_eq_without_eq)]
+    #[derive(Clone, PartialEq, ::prost::Oneof)]
+    pub enum VersionChoice {
+        /// Use this specific version number.
+        #[prost(message, tag = "2")]
+        Version(i64),
+        /// Use the version associated with the given label.
+        #[prost(string, tag = "4")]
+        VersionLabel(::prost::alloc::string::String),
+    }
+}
+/// Message returned for "signature_def" field.
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct SignatureDefMap {
+    #[prost(map = "string, message", tag = "1")]
+    pub signature_def: ::std::collections::HashMap<
+        ::prost::alloc::string::String,
+        super::SignatureDef,
+    >,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct GetModelMetadataRequest {
+    /// Model Specification indicating which model we are querying for metadata.
+    /// If version is not specified, will use the latest (numerical) version.
+    #[prost(message, optional, tag = "1")]
+    pub model_spec: ::core::option::Option<ModelSpec>,
+    /// Metadata fields to get. Currently supported: "signature_def".
+    #[prost(string, repeated, tag = "2")]
+    pub metadata_field: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct GetModelMetadataResponse {
+    /// Model Specification indicating which model this metadata belongs to.
+    #[prost(message, optional, tag = "1")]
+    pub model_spec: ::core::option::Option<ModelSpec>,
+    /// Map of metadata field name to metadata field. The options for metadata
+    /// field name are listed in GetModelMetadataRequest. Currently supported:
+    /// "signature_def".
+    #[prost(map = "string, message", tag = "2")]
+    pub metadata: ::std::collections::HashMap<
+        ::prost::alloc::string::String,
+        ::prost_types::Any,
+    >,
+}
+/// PredictRequest specifies which TensorFlow model to run, as well as
+/// how inputs are mapped to tensors and how outputs are filtered before
+/// returning to user.
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct PredictRequest {
+    /// Model Specification. If version is not specified, will use the latest
+    /// (numerical) version.
+    #[prost(message, optional, tag = "1")]
+    pub model_spec: ::core::option::Option<ModelSpec>,
+    /// Input tensors.
+    /// Names of input tensor are alias names. The mapping from aliases to real
+    /// input tensor names is stored in the SavedModel export as a prediction
+    /// SignatureDef under the 'inputs' field.
+    #[prost(map = "string, message", tag = "2")]
+    pub inputs: ::std::collections::HashMap<
+        ::prost::alloc::string::String,
+        super::TensorProto,
+    >,
+    /// Output filter.
+    /// Names specified are alias names. The mapping from aliases to real output
+    /// tensor names is stored in the SavedModel export as a prediction
+    /// SignatureDef under the 'outputs' field.
+    /// Only tensors specified here will be run/fetched and returned, with the
+    /// exception that when none is specified, all tensors specified in the
+    /// named signature will be run/fetched and returned.
+    #[prost(string, repeated, tag = "3")]
+    pub output_filter: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
+}
+/// Response for PredictRequest on successful run.
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct PredictResponse {
+    /// Effective Model Specification used to process PredictRequest.
+    #[prost(message, optional, tag = "2")]
+    pub model_spec: ::core::option::Option<ModelSpec>,
+    /// Output tensors.
+    #[prost(map = "string, message", tag = "1")]
+    pub outputs: ::std::collections::HashMap<
+        ::prost::alloc::string::String,
+        super::TensorProto,
+    >,
+}
+/// Generated client implementations.
+pub mod prediction_service_client {
+    #![allow(unused_variables, dead_code, missing_docs, clippy::let_unit_value)]
+    use tonic::codegen::*;
+    use tonic::codegen::http::Uri;
+    /// open source marker; do not remove
+    /// PredictionService provides access to machine-learned models loaded by
+    /// model_servers.
+    #[derive(Debug, Clone)]
+    pub struct PredictionServiceClient<T> {
+        inner: tonic::client::Grpc<T>,
+    }
+    impl PredictionServiceClient<tonic::transport::Channel> {
+        /// Attempt to create a new client by connecting to a given endpoint.
+        pub async fn connect<D>(dst: D) -> Result<Self, tonic::transport::Error>
+        where
+            D: TryInto<tonic::transport::Endpoint>,
+            D::Error: Into<StdError>,
+        {
+            let conn = tonic::transport::Endpoint::new(dst)?.connect().await?;
+            Ok(Self::new(conn))
+        }
+    }
+    impl<T> PredictionServiceClient<T>
+    where
+        T: tonic::client::GrpcService<tonic::body::BoxBody>,
+        T::Error: Into<StdError>,
+        T::ResponseBody: Body<Data = Bytes> + Send + 'static,
+        <T::ResponseBody as Body>::Error: Into<StdError> + Send,
+    {
+        pub fn new(inner: T) -> Self {
+            let inner = tonic::client::Grpc::new(inner);
+            Self { inner }
+        }
+        pub fn with_origin(inner: T, origin: Uri) -> Self {
+            let inner = tonic::client::Grpc::with_origin(inner, origin);
+            Self { inner }
+        }
+        pub fn with_interceptor<F>(
+            inner: T,
+            interceptor: F,
+        ) -> PredictionServiceClient<InterceptedService<T, F>>
+        where
+            F: tonic::service::Interceptor,
+            T::ResponseBody: Default,
+            T: tonic::codegen::Service<
+                http::Request<tonic::body::BoxBody>,
+                Response = http::Response<
+                    <T as tonic::client::GrpcService<tonic::body::BoxBody>>::ResponseBody,
+                >,
+            >,
+            <T as tonic::codegen::Service<
+                http::Request<tonic::body::BoxBody>,
+            >>::Error: Into<StdError> + Send + Sync,
+        {
+            PredictionServiceClient::new(InterceptedService::new(inner, interceptor))
+        }
+        /// Compress requests with the given encoding.
+        ///
+        /// This requires the server to support it otherwise it might respond with an
+        /// error.
+        #[must_use]
+        pub fn send_compressed(mut self, encoding: CompressionEncoding) -> Self {
+            self.inner = self.inner.send_compressed(encoding);
+            self
+        }
+        /// Enable decompressing responses.
+        #[must_use]
+        pub fn accept_compressed(mut self, encoding: CompressionEncoding) -> Self {
+            self.inner = self.inner.accept_compressed(encoding);
+            self
+        }
+        /// Limits the maximum size of a decoded message.
+        ///
+        /// Default: `4MB`
+        #[must_use]
+        pub fn max_decoding_message_size(mut self, limit: usize) -> Self {
+            self.inner = self.inner.max_decoding_message_size(limit);
+            self
+        }
+        /// Limits the maximum size of an encoded message.
+        ///
+        /// Default: `usize::MAX`
+        #[must_use]
+        pub fn max_encoding_message_size(mut self, limit: usize) -> Self {
+            self.inner = self.inner.max_encoding_message_size(limit);
+            self
+        }
+        /// Predict -- provides access to loaded TensorFlow model.
+        pub async fn predict(
+            &mut self,
+            request: impl tonic::IntoRequest<super::PredictRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::PredictResponse>,
+            tonic::Status,
+        > {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::new(
+                        tonic::Code::Unknown,
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/tensorflow.serving.PredictionService/Predict",
+            );
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(
+                    GrpcMethod::new("tensorflow.serving.PredictionService", "Predict"),
+                );
+            self.inner.unary(req, path, codec).await
+        }
+        /// GetModelMetadata - provides access to metadata for loaded models.
+        pub async fn get_model_metadata(
+            &mut self,
+            request: impl tonic::IntoRequest<super::GetModelMetadataRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::GetModelMetadataResponse>,
+            tonic::Status,
+        > {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::new(
+                        tonic::Code::Unknown,
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/tensorflow.serving.PredictionService/GetModelMetadata",
+            );
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(
+                    GrpcMethod::new(
+                        "tensorflow.serving.PredictionService",
+                        "GetModelMetadata",
+                    ),
+                );
+            self.inner.unary(req, path, codec).await
+        }
+    }
+}
+/// Generated server implementations.
+pub mod prediction_service_server {
+    #![allow(unused_variables, dead_code, missing_docs, clippy::let_unit_value)]
+    use tonic::codegen::*;
+    /// Generated trait containing gRPC methods that should be implemented for use with PredictionServiceServer.
+    #[async_trait]
+    pub trait PredictionService: Send + Sync + 'static {
+        /// Predict -- provides access to loaded TensorFlow model.
+        async fn predict(
+            &self,
+            request: tonic::Request<super::PredictRequest>,
+        ) -> std::result::Result<tonic::Response<super::PredictResponse>, tonic::Status>;
+        /// GetModelMetadata - provides access to metadata for loaded models.
+        async fn get_model_metadata(
+            &self,
+            request: tonic::Request<super::GetModelMetadataRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::GetModelMetadataResponse>,
+            tonic::Status,
+        >;
+    }
+    /// open source marker; do not remove
+    /// PredictionService provides access to machine-learned models loaded by
+    /// model_servers.
+    #[derive(Debug)]
+    pub struct PredictionServiceServer<T: PredictionService> {
+        inner: _Inner<T>,
+        accept_compression_encodings: EnabledCompressionEncodings,
+        send_compression_encodings: EnabledCompressionEncodings,
+        max_decoding_message_size: Option<usize>,
+        max_encoding_message_size: Option<usize>,
+    }
+    struct _Inner<T>(Arc<T>);
+    impl<T: PredictionService> PredictionServiceServer<T> {
+        pub fn new(inner: T) -> Self {
+            Self::from_arc(Arc::new(inner))
+        }
+        pub fn from_arc(inner: Arc<T>) -> Self {
+            let inner = _Inner(inner);
+            Self {
+                inner,
+                accept_compression_encodings: Default::default(),
+                send_compression_encodings: Default::default(),
+                max_decoding_message_size: None,
+                max_encoding_message_size: None,
+            }
+        }
+        pub fn with_interceptor<F>(
+            inner: T,
+            interceptor: F,
+        ) -> InterceptedService<Self, F>
+        where
+            F: tonic::service::Interceptor,
+        {
+            InterceptedService::new(Self::new(inner), interceptor)
+        }
+        /// Enable decompressing requests with the given encoding.
+        #[must_use]
+        pub fn accept_compressed(mut self, encoding: CompressionEncoding) -> Self {
+            self.accept_compression_encodings.enable(encoding);
+            self
+        }
+        /// Compress responses with the given encoding, if the client supports it.
+        #[must_use]
+        pub fn send_compressed(mut self, encoding: CompressionEncoding) -> Self {
+            self.send_compression_encodings.enable(encoding);
+            self
+        }
+        /// Limits the maximum size of a decoded message.
+        ///
+        /// Default: `4MB`
+        #[must_use]
+        pub fn max_decoding_message_size(mut self, limit: usize) -> Self {
+            self.max_decoding_message_size = Some(limit);
+            self
+        }
+        /// Limits the maximum size of an encoded message.
+        ///
+        /// Default: `usize::MAX`
+        #[must_use]
+        pub fn max_encoding_message_size(mut self, limit: usize) -> Self {
+            self.max_encoding_message_size = Some(limit);
+            self
+        }
+    }
+    impl<T, B> tonic::codegen::Service<http::Request<B>> for PredictionServiceServer<T>
+    where
+        T: PredictionService,
+        B: Body + Send + 'static,
+        B::Error: Into<StdError> + Send + 'static,
+    {
+        type Response = http::Response<tonic::body::BoxBody>;
+        type Error = std::convert::Infallible;
+        type Future = BoxFuture<Self::Response, Self::Error>;
+        fn poll_ready(
+            &mut self,
+            _cx: &mut Context<'_>,
+        ) -> Poll<std::result::Result<(), Self::Error>> {
+            Poll::Ready(Ok(()))
+        }
+        fn call(&mut self, req: http::Request<B>) -> Self::Future {
+            let inner = self.inner.clone();
+            match req.uri().path() {
+                "/tensorflow.serving.PredictionService/Predict" => {
+                    #[allow(non_camel_case_types)]
+                    struct PredictSvc<T: PredictionService>(pub Arc<T>);
+                    impl<
+                        T: PredictionService,
+                    > tonic::server::UnaryService<super::PredictRequest>
+                    for PredictSvc<T> {
+                        type Response = super::PredictResponse;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::PredictRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as PredictionService>::predict(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = PredictSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/tensorflow.serving.PredictionService/GetModelMetadata" => {
+                    #[allow(non_camel_case_types)]
+                    struct GetModelMetadataSvc<T: PredictionService>(pub Arc<T>);
+                    impl<
+                        T: PredictionService,
+                    > tonic::server::UnaryService<super::GetModelMetadataRequest>
+                    for GetModelMetadataSvc<T> {
+                        type Response = super::GetModelMetadataResponse;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::GetModelMetadataRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as PredictionService>::get_model_metadata(
+                                        &inner,
+                                        request,
+                                    )
+                                    .await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = GetModelMetadataSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                _ => {
+                    Box::pin(async move {
+                        Ok(
+                            http::Response::builder()
+                                .status(200)
+                                .header("grpc-status", "12")
+                                .header("content-type", "application/grpc")
+                                .body(empty_body())
+                                .unwrap(),
+                        )
+                    })
+                }
+            }
+        }
+    }
+    impl<T: PredictionService> Clone for PredictionServiceServer<T> {
+        fn clone(&self) -> Self {
+            let inner = self.inner.clone();
+            Self {
+                inner,
+                accept_compression_encodings: self.accept_compression_encodings,
+                send_compression_encodings: self.send_compression_encodings,
+                max_decoding_message_size: self.max_decoding_message_size,
+                max_encoding_message_size: self.max_encoding_message_size,
+            }
+        }
+    }
+    impl<T: PredictionService> Clone for _Inner<T> {
+        fn clone(&self) -> Self {
+            Self(Arc::clone(&self.0))
+        }
+    }
+    impl<T: std::fmt::Debug> std::fmt::Debug for _Inner<T> {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "{:?}", self.0)
+        }
+    }
+    impl<T: PredictionService> tonic::server::NamedService
+    for PredictionServiceServer<T> {
+        const NAME: &'static str = "tensorflow.serving.PredictionService";
+    }
+}
+/// Status that corresponds to Status in
+/// third_party/tensorflow/core/lib/core/status.h.
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct StatusProto {
+    /// Error code.
+    #[prost(enumeration = "super::error::Code", tag = "1")]
+    pub error_code: i32,
+    /// Error message. Will only be set if an error was encountered.
+    #[prost(string, tag = "2")]
+    pub error_message: ::prost::alloc::string::String,
+}
+/// GetModelStatusRequest contains a ModelSpec indicating the model for which
+/// to get status.
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct GetModelStatusRequest {
+    /// Model Specification. If version is not specified, information about all
+    /// versions of the model will be returned. If a version is specified, the
+    /// status of only that version will be returned.
+    #[prost(message, optional, tag = "1")]
+    pub model_spec: ::core::option::Option<ModelSpec>,
+}
+/// Version number, state, and status for a single version of a model.
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ModelVersionStatus {
+    /// Model version.
+    #[prost(int64, tag = "1")]
+    pub version: i64,
+    /// Model state.
+    #[prost(enumeration = "model_version_status::State", tag = "2")]
+    pub state: i32,
+    /// Model status.
+    #[prost(message, optional, tag = "3")]
+    pub status: ::core::option::Option<StatusProto>,
+}
+/// Nested message and enum types in `ModelVersionStatus`.
+pub mod model_version_status {
+    /// States that map to ManagerState enum in
+    /// tensorflow_serving/core/servable_state.h
+    #[derive(
+        Clone,
+        Copy,
+        Debug,
+        PartialEq,
+        Eq,
+        Hash,
+        PartialOrd,
+        Ord,
+        ::prost::Enumeration
+    )]
+    #[repr(i32)]
+    pub enum State {
+        /// Default value.
+        Unknown = 0,
+        /// The manager is tracking this servable, but has not initiated any action
+        /// pertaining to it.
+        Start = 10,
+        /// The manager has decided to load this servable. In particular, checks
+        /// around resource availability and other aspects have passed, and the
+        /// manager is about to invoke the loader's Load() method.
+        Loading = 20,
+        /// The manager has successfully loaded this servable and made it available
+        /// for serving (i.e. GetServableHandle(id) will succeed). To avoid races,
+        /// this state is not reported until *after* the servable is made
+        /// available.
+        Available = 30,
+        /// The manager has decided to make this servable unavailable, and unload
+        /// it. To avoid races, this state is reported *before* the servable is
+        /// made unavailable.
+        Unloading = 40,
+        /// This servable has reached the end of its journey in the manager. Either
+        /// it loaded and ultimately unloaded successfully, or it hit an error at
+        /// some point in its lifecycle.
+        End = 50,
+    }
+    impl State {
+        /// String value of the enum field names used in the ProtoBuf definition.
+        ///
+        /// The values are not transformed in any way and thus are considered stable
+        /// (if the ProtoBuf definition does not change) and safe for programmatic use.
+        pub fn as_str_name(&self) -> &'static str {
+            match self {
+                State::Unknown => "UNKNOWN",
+                State::Start => "START",
+                State::Loading => "LOADING",
+                State::Available => "AVAILABLE",
+                State::Unloading => "UNLOADING",
+                State::End => "END",
+            }
+        }
+        /// Creates an enum from field names used in the ProtoBuf definition.
+        pub fn from_str_name(value: &str) -> ::core::option::Option<Self> {
+            match value {
+                "UNKNOWN" => Some(Self::Unknown),
+                "START" => Some(Self::Start),
+                "LOADING" => Some(Self::Loading),
+                "AVAILABLE" => Some(Self::Available),
+                "UNLOADING" => Some(Self::Unloading),
+                "END" => Some(Self::End),
+                _ => None,
+            }
+        }
+    }
+}
+/// Response for ModelStatusRequest on successful run.
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct GetModelStatusResponse {
+    /// Version number and status information for applicable model version(s).
+    #[prost(message, repeated, tag = "1")]
+    pub model_version_status: ::prost::alloc::vec::Vec<ModelVersionStatus>,
+}
+/// Generated client implementations.
+pub mod model_service_client {
+    #![allow(unused_variables, dead_code, missing_docs, clippy::let_unit_value)]
+    use tonic::codegen::*;
+    use tonic::codegen::http::Uri;
+    /// ModelService provides methods to query and update the state of the server,
+    /// e.g. which models/versions are being served.
+    #[derive(Debug, Clone)]
+    pub struct ModelServiceClient<T> {
+        inner: tonic::client::Grpc<T>,
+    }
+    impl ModelServiceClient<tonic::transport::Channel> {
+        /// Attempt to create a new client by connecting to a given endpoint.
+        pub async fn connect<D>(dst: D) -> Result<Self, tonic::transport::Error>
+        where
+            D: TryInto<tonic::transport::Endpoint>,
+            D::Error: Into<StdError>,
+        {
+            let conn = tonic::transport::Endpoint::new(dst)?.connect().await?;
+            Ok(Self::new(conn))
+        }
+    }
+    impl<T> ModelServiceClient<T>
+    where
+        T: tonic::client::GrpcService<tonic::body::BoxBody>,
+        T::Error: Into<StdError>,
+        T::ResponseBody: Body<Data = Bytes> + Send + 'static,
+        <T::ResponseBody as Body>::Error: Into<StdError> + Send,
+    {
+        pub fn new(inner: T) -> Self {
+            let inner = tonic::client::Grpc::new(inner);
+            Self { inner }
+        }
+        pub fn with_origin(inner: T, origin: Uri) -> Self {
+            let inner = tonic::client::Grpc::with_origin(inner, origin);
+            Self { inner }
+        }
+        pub fn with_interceptor<F>(
+            inner: T,
+            interceptor: F,
+        ) -> ModelServiceClient<InterceptedService<T, F>>
+        where
+            F: tonic::service::Interceptor,
+            T::ResponseBody: Default,
+            T: tonic::codegen::Service<
+                http::Request<tonic::body::BoxBody>,
+                Response = http::Response<
+                    <T as tonic::client::GrpcService<tonic::body::BoxBody>>::ResponseBody,
+                >,
+            >,
+            <T as tonic::codegen::Service<
+                http::Request<tonic::body::BoxBody>,
+            >>::Error: Into<StdError> + Send + Sync,
+        {
+            ModelServiceClient::new(InterceptedService::new(inner, interceptor))
+        }
+        /// Compress requests with the given encoding.
+        ///
+        /// This requires the server to support it otherwise it might respond with an
+        /// error.
+        #[must_use]
+        pub fn send_compressed(mut self, encoding: CompressionEncoding) -> Self {
+            self.inner = self.inner.send_compressed(encoding);
+            self
+        }
+        /// Enable decompressing responses.
+        #[must_use]
+        pub fn accept_compressed(mut self, encoding: CompressionEncoding) -> Self {
+            self.inner = self.inner.accept_compressed(encoding);
+            self
+        }
+        /// Limits the maximum size of a decoded message.
+        ///
+        /// Default: `4MB`
+        #[must_use]
+        pub fn max_decoding_message_size(mut self, limit: usize) -> Self {
+            self.inner = self.inner.max_decoding_message_size(limit);
+            self
+        }
+        /// Limits the maximum size of an encoded message.
+        ///
+        /// Default: `usize::MAX`
+        #[must_use]
+        pub fn max_encoding_message_size(mut self, limit: usize) -> Self {
+            self.inner = self.inner.max_encoding_message_size(limit);
+            self
+        }
+        /// Gets status of model. If the ModelSpec in the request does not specify
+        /// version, information about all versions of the model will be returned. If
+        /// the ModelSpec in the request does specify a version, the status of only
+        /// that version will be returned.
+        pub async fn get_model_status(
+            &mut self,
+            request: impl tonic::IntoRequest<super::GetModelStatusRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::GetModelStatusResponse>,
+            tonic::Status,
+        > {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::new(
+                        tonic::Code::Unknown,
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/tensorflow.serving.ModelService/GetModelStatus",
+            );
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(
+                    GrpcMethod::new("tensorflow.serving.ModelService", "GetModelStatus"),
+                );
+            self.inner.unary(req, path, codec).await
+        }
+    }
+}
+/// Generated server implementations.
+pub mod model_service_server {
+    #![allow(unused_variables, dead_code, missing_docs, clippy::let_unit_value)]
+    use tonic::codegen::*;
+    /// Generated trait containing gRPC methods that should be implemented for use with ModelServiceServer.
+    #[async_trait]
+    pub trait ModelService: Send + Sync + 'static {
+        /// Gets status of model. If the ModelSpec in the request does not specify
+        /// version, information about all versions of the model will be returned. If
+        /// the ModelSpec in the request does specify a version, the status of only
+        /// that version will be returned.
+        async fn get_model_status(
+            &self,
+            request: tonic::Request<super::GetModelStatusRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::GetModelStatusResponse>,
+            tonic::Status,
+        >;
+    }
+    /// ModelService provides methods to query and update the state of the server,
+    /// e.g. which models/versions are being served.
+    #[derive(Debug)]
+    pub struct ModelServiceServer<T: ModelService> {
+        inner: _Inner<T>,
+        accept_compression_encodings: EnabledCompressionEncodings,
+        send_compression_encodings: EnabledCompressionEncodings,
+        max_decoding_message_size: Option<usize>,
+        max_encoding_message_size: Option<usize>,
+    }
+    struct _Inner<T>(Arc<T>);
+    impl<T: ModelService> ModelServiceServer<T> {
+        pub fn new(inner: T) -> Self {
+            Self::from_arc(Arc::new(inner))
+        }
+        pub fn from_arc(inner: Arc<T>) -> Self {
+            let inner = _Inner(inner);
+            Self {
+                inner,
+                accept_compression_encodings: Default::default(),
+                send_compression_encodings: Default::default(),
+                max_decoding_message_size: None,
+                max_encoding_message_size: None,
+            }
+        }
+        pub fn with_interceptor<F>(
+            inner: T,
+            interceptor: F,
+        ) -> InterceptedService<Self, F>
+        where
+            F: tonic::service::Interceptor,
+        {
+            InterceptedService::new(Self::new(inner), interceptor)
+        }
+        /// Enable decompressing requests with the given encoding.
+        #[must_use]
+        pub fn accept_compressed(mut self, encoding: CompressionEncoding) -> Self {
+            self.accept_compression_encodings.enable(encoding);
+            self
+        }
+        /// Compress responses with the given encoding, if the client supports it.
+        #[must_use]
+        pub fn send_compressed(mut self, encoding: CompressionEncoding) -> Self {
+            self.send_compression_encodings.enable(encoding);
+            self
+        }
+        /// Limits the maximum size of a decoded message.
+        ///
+        /// Default: `4MB`
+        #[must_use]
+        pub fn max_decoding_message_size(mut self, limit: usize) -> Self {
+            self.max_decoding_message_size = Some(limit);
+            self
+        }
+        /// Limits the maximum size of an encoded message.
+        ///
+        /// Default: `usize::MAX`
+        #[must_use]
+        pub fn max_encoding_message_size(mut self, limit: usize) -> Self {
+            self.max_encoding_message_size = Some(limit);
+            self
+        }
+    }
+    impl<T, B> tonic::codegen::Service<http::Request<B>> for ModelServiceServer<T>
+    where
+        T: ModelService,
+        B: Body + Send + 'static,
+        B::Error: Into<StdError> + Send + 'static,
+    {
+        type Response = http::Response<tonic::body::BoxBody>;
+        type Error = std::convert::Infallible;
+        type Future = BoxFuture<Self::Response, Self::Error>;
+        fn poll_ready(
+            &mut self,
+            _cx: &mut Context<'_>,
+        ) -> Poll<std::result::Result<(), Self::Error>> {
+            Poll::Ready(Ok(()))
+        }
+        fn call(&mut self, req: http::Request<B>) -> Self::Future {
+            let inner = self.inner.clone();
+            match req.uri().path() {
+                "/tensorflow.serving.ModelService/GetModelStatus" => {
+                    #[allow(non_camel_case_types)]
+                    struct GetModelStatusSvc<T: ModelService>(pub Arc<T>);
+                    impl<
+                        T: ModelService,
+                    > tonic::server::UnaryService<super::GetModelStatusRequest>
+                    for GetModelStatusSvc<T> {
+                        type Response = super::GetModelStatusResponse;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::GetModelStatusRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as ModelService>::get_model_status(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = GetModelStatusSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                _ => {
+                    Box::pin(async move {
+                        Ok(
+                            http::Response::builder()
+                                .status(200)
+                                .header("grpc-status", "12")
+                                .header("content-type", "application/grpc")
+                                .body(empty_body())
+                                .unwrap(),
+                        )
+                    })
+                }
+            }
+        }
+    }
+    impl<T: ModelService> Clone for ModelServiceServer<T> {
+        fn clone(&self) -> Self {
+            let inner = self.inner.clone();
+            Self {
+                inner,
+                accept_compression_encodings: self.accept_compression_encodings,
+                send_compression_encodings: self.send_compression_encodings,
+                max_decoding_message_size: self.max_decoding_message_size,
+                max_encoding_message_size: self.max_encoding_message_size,
+            }
+        }
+    }
+    impl<T: ModelService> Clone for _Inner<T> {
+        fn clone(&self) -> Self {
+            Self(Arc::clone(&self.0))
+        }
+    }
+    impl<T: std::fmt::Debug> std::fmt::Debug for _Inner<T> {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "{:?}", self.0)
+        }
+    }
+    impl<T: ModelService> tonic::server::NamedService for ModelServiceServer<T> {
+        const NAME: &'static str = "tensorflow.serving.ModelService";
+    }
+}
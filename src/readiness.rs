@@ -0,0 +1,475 @@
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tokio::task::JoinHandle;
+use tokio::time::{Instant, MissedTickBehavior};
+use tonic::Status;
+use tonic_health::server::HealthReporter;
+use tonic_health::ServingStatus;
+
+use crate::breaker::{BreakerOptions, BreakerState, CircuitBreaker, CircuitBreakers};
+use crate::config::Model;
+use crate::inference::InferenceBackend;
+use crate::registry::ModelRegistry;
+
+// 模型可用性检查的参数，可以在config.yaml的readiness部分配置
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct ReadinessOptions {
+    // 是否检查模型的状态，关闭后启动时不等待，也不在后台检查
+    pub enabled: bool,
+    // 启动时等待所有模型可用的最长时间（毫秒）
+    pub startup_timeout_ms: u64,
+    // 启动时两次检查之间的间隔（毫秒）
+    pub retry_interval_ms: u64,
+    // 服务运行期间在后台检查模型状态的间隔（毫秒），0表示不检查
+    pub poll_interval_ms: u64,
+}
+
+impl Default for ReadinessOptions {
+    fn default() -> Self {
+        ReadinessOptions {
+            enabled: true,
+            startup_timeout_ms: 60_000,
+            retry_interval_ms: 1_000,
+            poll_interval_ms: 10_000,
+        }
+    }
+}
+
+// 记录当前不可用的模型及原因，请求在转发给推理服务之前会先检查这里
+#[derive(Clone, Debug, Default)]
+pub struct ModelReadiness {
+    unavailable: Arc<RwLock<HashMap<String, String>>>,
+    // grpc.health.v1.Health服务的状态，每个模型对应一个同名的服务
+    health: Option<HealthReporter>,
+    // 每个模型的熔断器，没有开启时为None
+    breakers: Option<Arc<CircuitBreakers>>,
+}
+
+impl ModelReadiness {
+    pub fn new() -> Self {
+        ModelReadiness::default()
+    }
+
+    // 把模型的可用性同步到gRPC健康检查服务
+    pub fn with_health(mut self, health: HealthReporter) -> Self {
+        self.health = Some(health);
+        self
+    }
+
+    // 为每个模型启用熔断器
+    pub fn with_breakers(mut self, options: BreakerOptions) -> Self {
+        if options.enabled {
+            self.breakers = Some(Arc::new(CircuitBreakers::new(options)));
+        }
+        self
+    }
+
+    // 模型的熔断器，调用推理服务之前需要先获取许可
+    pub fn breaker(&self, model: &Model) -> Option<Arc<CircuitBreaker>> {
+        Some(self.breakers.as_ref()?.get(model))
+    }
+
+    // 模型的熔断器状态，没有开启熔断器时总是closed
+    pub fn breaker_state(&self, model_name: &str) -> BreakerState {
+        match &self.breakers {
+            Some(breakers) => breakers.state(model_name),
+            None => BreakerState::Closed,
+        }
+    }
+
+    // 更新每个模型的健康状态，所有模型都可用时整体状态("")才是SERVING
+    // 熔断器没有恢复到closed的模型只在它自己的状态中为NOT_SERVING，不影响整体状态，
+    // 否则负载均衡会摘除整个实例，熔断器收不到试探请求，无法恢复
+    pub async fn report_health(&self, models: &[Model]) {
+        let mut health = match &self.health {
+            Some(health) => health.clone(),
+            None => return,
+        };
+
+        let serving = |available: bool| match available {
+            true => ServingStatus::Serving,
+            false => ServingStatus::NotServing,
+        };
+        let statuses: Vec<(String, ServingStatus)> = {
+            let unavailable = self.unavailable.read().unwrap();
+            let all_serving = unavailable.is_empty();
+            models
+                .iter()
+                .map(|model| {
+                    let available = !unavailable.contains_key(&model.name)
+                        && self.breaker_state(&model.name) == BreakerState::Closed;
+                    (model.name.clone(), serving(available))
+                })
+                .chain([(String::new(), serving(all_serving))])
+                .collect()
+        };
+
+        for (service_name, status) in statuses {
+            health.set_service_status(service_name, status).await;
+        }
+    }
+
+    // 标记模型可用，返回模型之前是否不可用
+    pub fn mark_available(&self, model_name: &str) -> bool {
+        self.unavailable
+            .write()
+            .unwrap()
+            .remove(model_name)
+            .is_some()
+    }
+
+    // 标记模型不可用并记录原因，返回模型之前是否可用
+    pub fn mark_unavailable(&self, model_name: &str, reason: String) -> bool {
+        self.unavailable
+            .write()
+            .unwrap()
+            .insert(model_name.to_string(), reason)
+            .is_none()
+    }
+
+    // 模型不可用时返回UNAVAILABLE，调用方不再把请求转发给推理服务
+    pub fn unavailable(&self, model_name: &str) -> Option<Status> {
+        self.unavailable
+            .read()
+            .unwrap()
+            .get(model_name)
+            .map(|reason| {
+                Status::unavailable(format!("Model {} is not available: {}", model_name, reason))
+            })
+    }
+
+    // 模型从配置中删除后，不再记录它的可用性、熔断器和健康状态
+    pub async fn forget(&self, model_names: &[String]) {
+        if let Some(breakers) = &self.breakers {
+            breakers.forget(model_names);
+        }
+        {
+            let mut unavailable = self.unavailable.write().unwrap();
+            for name in model_names {
+                unavailable.remove(name);
+            }
+        }
+        if let Some(health) = &self.health {
+            let mut health = health.clone();
+            for name in model_names {
+                health.clear_service_status(name).await;
+            }
+        }
+    }
+
+    // 所有不可用模型的名称和原因，按名称排序
+    pub fn summary(&self) -> Vec<(String, String)> {
+        let mut models: Vec<(String, String)> = self
+            .unavailable
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(name, reason)| (name.clone(), reason.clone()))
+            .collect();
+        models.sort();
+        models
+    }
+}
+
+// 检查一次所有模型的状态并更新readiness，返回不可用的模型数量
+pub async fn check_models(
+    backend: &dyn InferenceBackend,
+    models: &[Model],
+    readiness: &ModelReadiness,
+) -> usize {
+    let mut unavailable = 0;
+    for model in models {
+        match backend.model_status(model).await {
+            Ok(()) => {
+                if readiness.mark_available(&model.name) {
+                    info!(
+                        "model {} version {} is available",
+                        model.name, model.version
+                    );
+                }
+                let state = readiness.breaker_state(&model.name);
+                if state != BreakerState::Closed {
+                    info!(
+                        "model {} version {} is available but its circuit breaker is {}",
+                        model.name, model.version, state
+                    );
+                }
+            }
+            Err(status) => {
+                unavailable += 1;
+                if readiness.mark_unavailable(&model.name, status.message().to_string()) {
+                    warn!(
+                        "model {} version {} is unavailable: {}",
+                        model.name,
+                        model.version,
+                        status.message()
+                    );
+                }
+            }
+        }
+    }
+    readiness.report_health(models).await;
+    unavailable
+}
+
+// 启动时等待所有模型可用，超过startup_timeout_ms后返回仍不可用的模型
+pub async fn wait_until_ready(
+    backend: &dyn InferenceBackend,
+    models: &[Model],
+    readiness: &ModelReadiness,
+    options: &ReadinessOptions,
+) -> Result<(), Vec<(String, String)>> {
+    let deadline = Instant::now() + Duration::from_millis(options.startup_timeout_ms);
+    let retry_interval = Duration::from_millis(options.retry_interval_ms);
+
+    loop {
+        if check_models(backend, models, readiness).await == 0 {
+            return Ok(());
+        }
+        if Instant::now() + retry_interval > deadline {
+            return Err(readiness.summary());
+        }
+        tokio::time::sleep(retry_interval).await;
+    }
+}
+
+// 在后台定期检查模型状态，每次检查都使用最新的模型表，需要在tokio运行时中调用
+pub fn spawn_poller(
+    registry: ModelRegistry,
+    readiness: ModelReadiness,
+    poll_interval: Duration,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval_at(Instant::now() + poll_interval, poll_interval);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        loop {
+            interval.tick().await;
+            let models = registry.snapshot().models();
+            check_models(registry.backend().as_ref(), &models, &readiness).await;
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::inference::fake::FakeBackend;
+    use tokio::net::TcpListener;
+    use tokio_stream::wrappers::TcpListenerStream;
+    use tonic::transport::{Channel, Server};
+    use tonic::Code;
+    use tonic_health::pb::health_check_response::ServingStatus as ProtoStatus;
+    use tonic_health::pb::health_client::HealthClient;
+    use tonic_health::pb::HealthCheckRequest;
+
+    fn test_models() -> Vec<Model> {
+        ["foo", "bar"]
+            .iter()
+            .map(|name| Model {
+                name: name.to_string(),
+                version: 1,
+                input_name: "input".to_string(),
+                ..Model::default()
+            })
+            .collect()
+    }
+
+    fn test_options(startup_timeout_ms: u64) -> ReadinessOptions {
+        ReadinessOptions {
+            startup_timeout_ms,
+            retry_interval_ms: 10,
+            ..ReadinessOptions::default()
+        }
+    }
+
+    // 测试所有模型可用时立即完成启动检查
+    #[tokio::test]
+    async fn test_wait_until_ready() {
+        let backend = FakeBackend::new();
+        let readiness = ModelReadiness::new();
+
+        wait_until_ready(&backend, &test_models(), &readiness, &test_options(1_000))
+            .await
+            .unwrap();
+        assert!(readiness.unavailable("foo").is_none());
+        assert!(readiness.unavailable("bar").is_none());
+    }
+
+    // 测试超过等待时间后返回仍不可用的模型
+    #[tokio::test]
+    async fn test_wait_until_ready_timeout() {
+        let backend = FakeBackend::new().failing("bar");
+        let readiness = ModelReadiness::new();
+
+        let unavailable = wait_until_ready(&backend, &test_models(), &readiness, &test_options(50))
+            .await
+            .unwrap_err();
+        assert_eq!(
+            unavailable,
+            vec![("bar".to_string(), "model bar is broken".to_string())]
+        );
+        assert!(readiness.unavailable("foo").is_none());
+        assert_eq!(
+            readiness.unavailable("bar").unwrap().code(),
+            Code::Unavailable
+        );
+    }
+
+    // 测试模型在等待时间内变为可用时启动检查成功
+    #[tokio::test]
+    async fn test_wait_until_ready_recovers() {
+        let backend = Arc::new(FakeBackend::new().failing("bar"));
+        let readiness = ModelReadiness::new();
+
+        let recover = Arc::clone(&backend);
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(30)).await;
+            recover.set_failing("bar", false);
+        });
+
+        wait_until_ready(
+            backend.as_ref(),
+            &test_models(),
+            &readiness,
+            &test_options(5_000),
+        )
+        .await
+        .unwrap();
+        assert!(readiness.summary().is_empty());
+    }
+
+    // 测试后台检查会标记不可用的模型，并在模型恢复后重新标记为可用
+    #[tokio::test]
+    async fn test_poller_tracks_availability() {
+        let backend = Arc::new(FakeBackend::new());
+        let models = test_models()
+            .into_iter()
+            .map(|model| (model.name.clone(), model))
+            .collect();
+        let registry = ModelRegistry::new(models, backend.clone());
+        let readiness = ModelReadiness::new();
+        let poller = spawn_poller(registry, readiness.clone(), Duration::from_millis(10));
+
+        backend.set_failing("foo", true);
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(
+            readiness.unavailable("foo").unwrap().message(),
+            "Model foo is not available: model foo is broken"
+        );
+        assert!(readiness.unavailable("bar").is_none());
+
+        backend.set_failing("foo", false);
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(readiness.unavailable("foo").is_none());
+        poller.abort();
+    }
+
+    // 在随机端口上启动健康检查服务，并返回连接到该服务的客户端
+    async fn start_health_service() -> (HealthReporter, HealthClient<Channel>) {
+        let (reporter, service) = tonic_health::server::health_reporter();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(
+            Server::builder()
+                .add_service(service)
+                .serve_with_incoming(TcpListenerStream::new(listener)),
+        );
+
+        let channel = Channel::from_shared(format!("http://{}", addr))
+            .unwrap()
+            .connect()
+            .await
+            .unwrap();
+        let client = HealthClient::new(channel);
+        (reporter, client)
+    }
+
+    async fn health_status(client: &mut HealthClient<Channel>, service: &str) -> ProtoStatus {
+        let request = HealthCheckRequest {
+            service: service.to_string(),
+        };
+        let response = client.check(request).await.unwrap().into_inner();
+        ProtoStatus::try_from(response.status).unwrap()
+    }
+
+    // 测试健康检查服务的状态随模型的可用性在SERVING和NOT_SERVING之间切换
+    #[tokio::test]
+    async fn test_health_follows_model_status() {
+        let (reporter, mut client) = start_health_service().await;
+        let backend = FakeBackend::new().failing("bar");
+        let readiness = ModelReadiness::new().with_health(reporter);
+        let models = test_models();
+
+        check_models(&backend, &models, &readiness).await;
+        assert_eq!(
+            health_status(&mut client, "foo").await,
+            ProtoStatus::Serving
+        );
+        assert_eq!(
+            health_status(&mut client, "bar").await,
+            ProtoStatus::NotServing
+        );
+        assert_eq!(
+            health_status(&mut client, "").await,
+            ProtoStatus::NotServing
+        );
+
+        backend.set_failing("bar", false);
+        check_models(&backend, &models, &readiness).await;
+        assert_eq!(
+            health_status(&mut client, "bar").await,
+            ProtoStatus::Serving
+        );
+        assert_eq!(health_status(&mut client, "").await, ProtoStatus::Serving);
+
+        // 未配置的模型返回NOT_FOUND
+        let status = client
+            .check(HealthCheckRequest {
+                service: "unknown".to_string(),
+            })
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), Code::NotFound);
+    }
+
+    // 测试熔断器打开的模型在健康检查中为NOT_SERVING，整体状态仍然是SERVING
+    #[tokio::test]
+    async fn test_health_follows_circuit_breaker() {
+        let (reporter, mut client) = start_health_service().await;
+        let readiness = ModelReadiness::new()
+            .with_health(reporter)
+            .with_breakers(BreakerOptions {
+                window_size: 1,
+                min_calls: 1,
+                ..BreakerOptions::default()
+            });
+        let models = test_models();
+
+        check_models(&FakeBackend::new(), &models, &readiness).await;
+        assert_eq!(
+            health_status(&mut client, "foo").await,
+            ProtoStatus::Serving
+        );
+
+        let call = readiness.breaker(&models[0]).unwrap().acquire().unwrap();
+        call.finish(&Err::<(), _>(Status::unavailable("model foo is broken")));
+        assert_eq!(readiness.breaker_state("foo"), BreakerState::Open);
+
+        // 模型状态检查成功，但熔断器仍然打开
+        check_models(&FakeBackend::new(), &models, &readiness).await;
+        assert!(readiness.unavailable("foo").is_none());
+        assert_eq!(
+            health_status(&mut client, "foo").await,
+            ProtoStatus::NotServing
+        );
+        assert_eq!(
+            health_status(&mut client, "bar").await,
+            ProtoStatus::Serving
+        );
+        assert_eq!(health_status(&mut client, "").await, ProtoStatus::Serving);
+    }
+}
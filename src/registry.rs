@@ -0,0 +1,177 @@
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+
+use crate::batching::Batcher;
+use crate::config::Model;
+use crate::inference::InferenceBackend;
+
+// 一份模型配置及其批处理队列，热加载时整体替换
+#[derive(Default)]
+pub struct ModelTable {
+    models: HashMap<String, Model>,
+    // 配置了batch参数的模型对应的批处理队列
+    batchers: HashMap<String, Batcher>,
+}
+
+impl ModelTable {
+    pub fn get(&self, model_name: &str) -> Option<&Model> {
+        self.models.get(model_name)
+    }
+
+    pub fn batcher(&self, model_name: &str) -> Option<&Batcher> {
+        self.batchers.get(model_name)
+    }
+
+    pub fn models(&self) -> Vec<Model> {
+        self.models.values().cloned().collect()
+    }
+}
+
+// 热加载后模型的变化，用于记录日志和更新模型的可用性
+#[derive(Debug, Default, PartialEq)]
+pub struct ModelChanges {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    pub changed: Vec<String>,
+}
+
+impl ModelChanges {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+// 当前生效的模型表，请求开始时取一份快照，进行中的请求不受替换的影响
+#[derive(Clone)]
+pub struct ModelRegistry {
+    current: Arc<RwLock<Arc<ModelTable>>>,
+    backend: Arc<dyn InferenceBackend>,
+}
+
+impl ModelRegistry {
+    // 为配置了batch参数的模型启动批处理队列，需要在tokio运行时中调用
+    pub fn new(models: HashMap<String, Model>, backend: Arc<dyn InferenceBackend>) -> Self {
+        let registry = ModelRegistry {
+            current: Arc::new(RwLock::new(Arc::new(ModelTable::default()))),
+            backend,
+        };
+        registry.update(models);
+        registry
+    }
+
+    pub fn backend(&self) -> &Arc<dyn InferenceBackend> {
+        &self.backend
+    }
+
+    pub fn snapshot(&self) -> Arc<ModelTable> {
+        Arc::clone(&self.current.read().unwrap())
+    }
+
+    // 用新的模型配置替换当前的模型表
+    // 配置没有变化的模型继续使用原来的批处理队列，被删除或修改的模型的队列在处理完已有的请求后退出
+    pub fn update(&self, models: HashMap<String, Model>) -> ModelChanges {
+        let old = self.snapshot();
+        let mut changes = ModelChanges::default();
+        let mut batchers = HashMap::new();
+
+        for (name, model) in &models {
+            match old.models.get(name) {
+                None => changes.added.push(name.clone()),
+                Some(old_model) if old_model != model => changes.changed.push(name.clone()),
+                Some(_) => {
+                    if let Some(batcher) = old.batchers.get(name) {
+                        batchers.insert(name.clone(), batcher.clone());
+                    }
+                    continue;
+                }
+            }
+
+            if let Some(options) = model.batch.clone() {
+                let batcher = Batcher::spawn(Arc::clone(&self.backend), model.clone(), options);
+                batchers.insert(name.clone(), batcher);
+            }
+        }
+        changes.removed = old
+            .models
+            .keys()
+            .filter(|name| !models.contains_key(*name))
+            .cloned()
+            .collect();
+        changes.added.sort();
+        changes.changed.sort();
+        changes.removed.sort();
+
+        *self.current.write().unwrap() = Arc::new(ModelTable { models, batchers });
+        changes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::batching::BatchOptions;
+    use crate::inference::fake::FakeBackend;
+
+    fn model(name: &str, version: u32) -> (String, Model) {
+        let model = Model {
+            name: name.to_string(),
+            version,
+            input_name: "input".to_string(),
+            batch: Some(BatchOptions::default()),
+            ..Model::default()
+        };
+        (name.to_string(), model)
+    }
+
+    // 测试替换模型表时正确识别新增、删除和修改的模型，旧的快照不受影响
+    #[tokio::test]
+    async fn test_update_models() {
+        let registry = ModelRegistry::new(
+            HashMap::from([model("foo", 1), model("bar", 1)]),
+            Arc::new(FakeBackend::new()),
+        );
+        let before = registry.snapshot();
+
+        let changes = registry.update(HashMap::from([
+            model("foo", 1),
+            model("bar", 2),
+            model("baz", 1),
+        ]));
+        assert_eq!(
+            changes,
+            ModelChanges {
+                added: vec!["baz".to_string()],
+                removed: vec![],
+                changed: vec!["bar".to_string()],
+            }
+        );
+
+        let after = registry.snapshot();
+        assert_eq!(before.get("bar").unwrap().version, 1);
+        assert!(before.get("baz").is_none());
+        assert_eq!(after.get("bar").unwrap().version, 2);
+        assert!(after.batcher("baz").is_some());
+
+        let changes = registry.update(HashMap::from([model("baz", 1)]));
+        assert_eq!(changes.removed, vec!["bar".to_string(), "foo".to_string()]);
+        assert!(registry.snapshot().get("foo").is_none());
+
+        assert!(registry.update(HashMap::from([model("baz", 1)])).is_empty());
+    }
+
+    // 测试替换模型表后，使用旧批处理队列的请求仍然能完成
+    #[tokio::test]
+    async fn test_old_batcher_drains_after_update() {
+        let backend = Arc::new(FakeBackend::new());
+        let registry = ModelRegistry::new(HashMap::from([model("foo", 1)]), backend.clone());
+        let batcher = registry.snapshot().batcher("foo").unwrap().clone();
+
+        registry.update(HashMap::from([model("foo", 2)]));
+        let prediction = batcher.predict(vec![0; 3]).await.unwrap();
+        assert_eq!(prediction, vec![3.0, 1.0]);
+
+        let batcher = registry.snapshot().batcher("foo").unwrap().clone();
+        let prediction = batcher.predict(vec![0; 3]).await.unwrap();
+        assert_eq!(prediction, vec![3.0, 2.0]);
+    }
+}
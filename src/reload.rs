@@ -0,0 +1,299 @@
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use tokio::signal::unix::{signal, Signal, SignalKind};
+use tokio::task::JoinHandle;
+use tokio::time::MissedTickBehavior;
+
+use crate::cache::EmbeddingCache;
+use crate::config::{read_config_from_path, Model};
+use crate::readiness::{check_models, ModelReadiness};
+use crate::registry::{ModelChanges, ModelRegistry};
+use crate::tf_serving::router::ModelRouter;
+
+// 配置文件热加载的参数，可以在config.yaml的reload部分配置
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct ReloadOptions {
+    // 是否在配置文件变化时重新加载，关闭后仍然可以通过SIGHUP触发
+    pub watch: bool,
+    // 检查配置文件是否变化的间隔（毫秒）
+    pub poll_interval_ms: u64,
+}
+
+impl Default for ReloadOptions {
+    fn default() -> Self {
+        ReloadOptions {
+            watch: true,
+            poll_interval_ms: 2_000,
+        }
+    }
+}
+
+// 重新读取配置文件并替换模型表，新的配置无效时保留原来的模型表
+#[derive(Clone)]
+pub struct Reloader {
+    path: String,
+    registry: ModelRegistry,
+    readiness: ModelReadiness,
+    // 是否在加载后立即检查新增和修改的模型的状态
+    check_status: bool,
+    // 删除的模型和修改了版本的模型的旧结果从缓存中清除
+    cache: Option<Arc<EmbeddingCache>>,
+    // 删除的模型的推理服务从路由中释放
+    router: Option<Arc<ModelRouter>>,
+}
+
+impl Reloader {
+    pub fn new(path: &str, registry: ModelRegistry, readiness: ModelReadiness) -> Self {
+        Reloader {
+            path: path.to_string(),
+            registry,
+            readiness,
+            check_status: true,
+            cache: None,
+            router: None,
+        }
+    }
+
+    pub fn with_status_check(mut self, check_status: bool) -> Self {
+        self.check_status = check_status;
+        self
+    }
+
+    // 与服务共用预测结果缓存
+    pub fn with_cache(mut self, cache: Arc<EmbeddingCache>) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    // 与服务共用按模型选择TensorFlow Serving实例的路由
+    pub fn with_router(mut self, router: Arc<ModelRouter>) -> Self {
+        self.router = Some(router);
+        self
+    }
+
+    // 使用与启动时相同的校验规则读取配置文件，并原子地替换模型表
+    pub async fn reload(&self) -> Result<ModelChanges, Box<dyn Error>> {
+        let models = read_config_from_path(&self.path)?;
+        if models.is_empty() {
+            return Err(format!("Cannot find any model info from {}", self.path).into());
+        }
+
+        let changes = self.registry.update(models);
+        self.readiness.forget(&changes.removed).await;
+
+        let table = self.registry.snapshot();
+        if let Some(cache) = &self.cache {
+            cache.forget(&changes.removed);
+            for model in changes.changed.iter().filter_map(|name| table.get(name)) {
+                cache.set_version(model);
+            }
+            cache.retain(&table.models()).await;
+        }
+        if let Some(router) = &self.router {
+            router.retain(&table.models());
+        }
+        if self.check_status {
+            // 新增和修改的模型立即检查一次，不可用的模型在恢复前直接返回UNAVAILABLE
+            let checked: Vec<Model> = changes
+                .added
+                .iter()
+                .chain(&changes.changed)
+                .filter_map(|name| table.get(name).cloned())
+                .collect();
+            check_models(self.registry.backend().as_ref(), &checked, &self.readiness).await;
+        }
+        self.readiness.report_health(&table.models()).await;
+
+        Ok(changes)
+    }
+
+    async fn reload_and_log(&self) {
+        match self.reload().await {
+            Ok(changes) if changes.is_empty() => {
+                info!("config {} reloaded, no model changed", self.path)
+            }
+            Ok(changes) => info!(
+                "config {} reloaded, added: {:?}, removed: {:?}, changed: {:?}",
+                self.path, changes.added, changes.removed, changes.changed
+            ),
+            Err(e) => error!(
+                "cannot reload config {}, keep the current models: {}",
+                self.path, e
+            ),
+        }
+    }
+
+    // 在后台监视配置文件的变化和SIGHUP信号，需要在tokio运行时中调用
+    pub fn spawn(self, options: &ReloadOptions) -> JoinHandle<()> {
+        // 在返回之前注册信号处理，保证之后收到的SIGHUP不会终止进程
+        let mut hangup = match signal(SignalKind::hangup()) {
+            Ok(hangup) => Some(hangup),
+            Err(e) => {
+                warn!("cannot listen for SIGHUP: {}", e);
+                None
+            }
+        };
+        let poll_interval = match options.watch && options.poll_interval_ms > 0 {
+            true => Some(Duration::from_millis(options.poll_interval_ms)),
+            false => None,
+        };
+
+        tokio::spawn(async move {
+            let mut last_modified = modified(&self.path);
+            let mut interval =
+                tokio::time::interval(poll_interval.unwrap_or(Duration::from_secs(3600)));
+            interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+            loop {
+                tokio::select! {
+                    _ = interval.tick(), if poll_interval.is_some() => {
+                        let current = modified(&self.path);
+                        if current.is_none() || current == last_modified {
+                            continue;
+                        }
+                        last_modified = current;
+                        info!("config {} changed, reloading", self.path);
+                    }
+                    Some(()) = recv_hangup(&mut hangup) => {
+                        last_modified = modified(&self.path);
+                        info!("received SIGHUP, reloading config {}", self.path);
+                    }
+                }
+                self.reload_and_log().await;
+            }
+        })
+    }
+}
+
+// 没有注册信号处理时永远等待
+async fn recv_hangup(hangup: &mut Option<Signal>) -> Option<()> {
+    match hangup {
+        Some(hangup) => hangup.recv().await,
+        None => std::future::pending().await,
+    }
+}
+
+// 使用修改时间和文件大小判断配置文件是否变化
+fn modified(path: &str) -> Option<(SystemTime, u64)> {
+    let metadata = std::fs::metadata(path).ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::inference::fake::FakeBackend;
+    use std::sync::Arc;
+    use tempfile::{tempdir, TempDir};
+    use tonic::Code;
+
+    const ONE_MODEL: &str = "models:\n  - name: foo\n    version: 1\n    input_name: input\n";
+    const TWO_MODELS: &str = "models:\n  - name: foo\n    version: 2\n    input_name: input\n  - name: bar\n    version: 1\n    input_name: input\n";
+
+    // 在临时目录中写入配置文件，并根据它创建模型表
+    fn setup(backend: Arc<FakeBackend>) -> (TempDir, String, ModelRegistry, ModelReadiness) {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("config.yaml");
+        std::fs::write(&path, ONE_MODEL).unwrap();
+        let path = path.to_str().unwrap().to_string();
+
+        let models = read_config_from_path(&path).unwrap();
+        let registry = ModelRegistry::new(models, backend);
+        (dir, path, registry, ModelReadiness::new())
+    }
+
+    // 测试重新加载后新增和修改的模型立即生效，并检查新模型的状态
+    #[tokio::test]
+    async fn test_reload_config() {
+        let backend = Arc::new(FakeBackend::new().failing("bar"));
+        let (_dir, path, registry, readiness) = setup(backend);
+        let reloader = Reloader::new(&path, registry.clone(), readiness.clone());
+
+        std::fs::write(&path, TWO_MODELS).unwrap();
+        let changes = reloader.reload().await.unwrap();
+        assert_eq!(changes.added, vec!["bar".to_string()]);
+        assert_eq!(changes.changed, vec!["foo".to_string()]);
+
+        let table = registry.snapshot();
+        assert_eq!(table.get("foo").unwrap().version, 2);
+        assert!(table.get("bar").is_some());
+        assert_eq!(
+            readiness.unavailable("bar").unwrap().code(),
+            Code::Unavailable
+        );
+
+        // 删除的模型不再记录可用性
+        std::fs::write(&path, ONE_MODEL).unwrap();
+        let changes = reloader.reload().await.unwrap();
+        assert_eq!(changes.removed, vec!["bar".to_string()]);
+        assert!(readiness.summary().is_empty());
+    }
+
+    // 测试新的配置无效时保留原来的模型表
+    #[tokio::test]
+    async fn test_reload_invalid_config_keeps_models() {
+        let (_dir, path, registry, readiness) = setup(Arc::new(FakeBackend::new()));
+        let reloader = Reloader::new(&path, registry.clone(), readiness);
+
+        let duplicate = "models:\n  - name: foo\n    version: 3\n    input_name: input\n  - name: foo\n    version: 4\n    input_name: input\n";
+        std::fs::write(&path, duplicate).unwrap();
+        let err = reloader.reload().await.unwrap_err();
+        assert_eq!(err.to_string(), "Duplicate model name: foo");
+
+        std::fs::write(&path, "models: []\n").unwrap();
+        assert!(reloader.reload().await.is_err());
+
+        std::fs::write(&path, "This is not valid YAML.").unwrap();
+        assert!(reloader.reload().await.is_err());
+
+        assert_eq!(registry.snapshot().get("foo").unwrap().version, 1);
+    }
+
+    // 测试配置文件变化后自动重新加载
+    #[tokio::test]
+    async fn test_reload_on_file_change() {
+        let (_dir, path, registry, readiness) = setup(Arc::new(FakeBackend::new()));
+        let options = ReloadOptions {
+            watch: true,
+            poll_interval_ms: 10,
+        };
+        let watcher = Reloader::new(&path, registry.clone(), readiness).spawn(&options);
+
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        std::fs::write(&path, TWO_MODELS).unwrap();
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        assert_eq!(registry.snapshot().get("foo").unwrap().version, 2);
+        assert!(registry.snapshot().get("bar").is_some());
+        watcher.abort();
+    }
+
+    // 测试收到SIGHUP后重新加载
+    #[tokio::test]
+    async fn test_reload_on_sighup() {
+        let (_dir, path, registry, readiness) = setup(Arc::new(FakeBackend::new()));
+        let options = ReloadOptions {
+            watch: false,
+            ..ReloadOptions::default()
+        };
+        let watcher = Reloader::new(&path, registry.clone(), readiness).spawn(&options);
+
+        std::fs::write(&path, TWO_MODELS).unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(registry.snapshot().get("foo").unwrap().version, 1);
+
+        let status = std::process::Command::new("kill")
+            .args(["-HUP", &std::process::id().to_string()])
+            .status()
+            .unwrap();
+        assert!(status.success());
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        assert_eq!(registry.snapshot().get("foo").unwrap().version, 2);
+        watcher.abort();
+    }
+}
@@ -0,0 +1,325 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{mpsc, OwnedSemaphorePermit, Semaphore};
+use tokio::task;
+use tokio::time::Instant;
+use tonic::metadata::MetadataMap;
+use tonic::Status;
+
+use crate::pb::image_prediction_pb::image_vector_response::Result as ImageResult;
+use crate::pb::image_prediction_pb::{Error, ImageVectorResponse};
+
+// 客户端在请求的metadata中通过这个键选择响应的顺序
+pub const RESPONSE_ORDER_KEY: &str = "x-response-order";
+
+// 按请求到达的顺序返回结果的参数，可以在config.yaml的reorder部分配置
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct ReorderOptions {
+    // 每个流中已读取但还没有按顺序发送的图片数量的上限，达到上限后暂停读取
+    pub max_buffered: usize,
+    // 一张图片从读取开始超过这个时间（毫秒）还没有结果时，返回DEADLINE_EXCEEDED并跳过它
+    pub straggler_timeout_ms: u64,
+}
+
+impl Default for ReorderOptions {
+    fn default() -> Self {
+        ReorderOptions {
+            max_buffered: 256,
+            straggler_timeout_ms: 30_000,
+        }
+    }
+}
+
+// 响应的发送顺序
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ResponseOrder {
+    // 按完成的顺序发送，默认
+    Completion,
+    // 按请求到达的顺序发送
+    Arrival,
+}
+
+impl ResponseOrder {
+    // 读取metadata中的x-response-order：completion或in-order
+    pub fn from_metadata(metadata: &MetadataMap) -> Result<Self, String> {
+        let value = match metadata.get(RESPONSE_ORDER_KEY) {
+            Some(value) => value.to_str().unwrap_or_default(),
+            None => return Ok(ResponseOrder::Completion),
+        };
+        match value {
+            "completion" => Ok(ResponseOrder::Completion),
+            "in-order" => Ok(ResponseOrder::Arrival),
+            _ => Err(format!(
+                "Unknown {} {:?}, expected completion or in-order",
+                RESPONSE_ORDER_KEY, value
+            )),
+        }
+    }
+}
+
+// 分发图片的一端：按到达顺序登记每张图片的id，预测结果发送到completions
+pub struct Reorderer {
+    slots: Arc<Semaphore>,
+    order: mpsc::UnboundedSender<(i32, OwnedSemaphorePermit)>,
+    completions: mpsc::Sender<Result<ImageVectorResponse, Status>>,
+}
+
+impl Reorderer {
+    // 在后台把completions中的结果按登记的顺序发送到out，需要在tokio运行时中调用
+    pub fn spawn(
+        options: &ReorderOptions,
+        out: mpsc::Sender<Result<ImageVectorResponse, Status>>,
+    ) -> Self {
+        let (order, order_rx) = mpsc::unbounded_channel();
+        let (completions, completions_rx) = mpsc::channel(options.max_buffered.max(1));
+        let timeout = Duration::from_millis(options.straggler_timeout_ms);
+        task::spawn(run_reorder(order_rx, completions_rx, out, timeout));
+
+        Reorderer {
+            slots: Arc::new(Semaphore::new(options.max_buffered.max(1))),
+            order,
+            completions,
+        }
+    }
+
+    // 登记下一张图片的id，缓冲区已满时等待前面的结果发送出去
+    pub async fn register(&self, id: i32) {
+        // 信号量不会被关闭
+        let slot = Arc::clone(&self.slots).acquire_owned().await.unwrap();
+        let _ = self.order.send((id, slot));
+    }
+
+    // 预测结果发送到这里，而不是直接发送到响应流
+    pub fn completions(&self) -> mpsc::Sender<Result<ImageVectorResponse, Status>> {
+        self.completions.clone()
+    }
+}
+
+// 等待发送的一张图片，slot在结果发送或被跳过后释放
+struct Waiting {
+    id: i32,
+    deadline: Instant,
+    _slot: OwnedSemaphorePermit,
+}
+
+async fn run_reorder(
+    mut order_rx: mpsc::UnboundedReceiver<(i32, OwnedSemaphorePermit)>,
+    mut completions_rx: mpsc::Receiver<Result<ImageVectorResponse, Status>>,
+    out: mpsc::Sender<Result<ImageVectorResponse, Status>>,
+    timeout: Duration,
+) {
+    let mut order: VecDeque<Waiting> = VecDeque::new();
+    // 已完成但前面还有图片没有完成的结果，同一个id可能出现多次
+    let mut ready: HashMap<i32, VecDeque<ImageVectorResponse>> = HashMap::new();
+    // 因为超时被跳过的id，它们之后到达的结果被丢弃
+    let mut skipped: HashMap<i32, usize> = HashMap::new();
+    let mut order_open = true;
+
+    loop {
+        // 发送队首已经完成的结果
+        while let Some(head) = order.front() {
+            let response = match ready.get_mut(&head.id).and_then(|r| r.pop_front()) {
+                Some(response) => response,
+                None => break,
+            };
+            if out.send(Ok(response)).await.is_err() {
+                return;
+            }
+            order.pop_front();
+        }
+        let deadline = order.front().map(|head| head.deadline);
+
+        tokio::select! {
+            registered = order_rx.recv(), if order_open => match registered {
+                Some((id, slot)) => order.push_back(Waiting {
+                    id,
+                    deadline: Instant::now() + timeout,
+                    _slot: slot,
+                }),
+                None => order_open = false,
+            },
+            completed = completions_rx.recv() => match completed {
+                Some(Ok(response)) => {
+                    if let Some(count) = skipped.get_mut(&response.id) {
+                        *count -= 1;
+                        if *count == 0 {
+                            skipped.remove(&response.id);
+                        }
+                        continue;
+                    }
+                    ready.entry(response.id).or_default().push_back(response);
+                }
+                // 读取请求失败时立即结束响应流
+                Some(Err(status)) => {
+                    let _ = out.send(Err(status)).await;
+                    return;
+                }
+                // 所有图片都已完成，剩下的登记都已经在通道中
+                None => {
+                    while let Ok((id, slot)) = order_rx.try_recv() {
+                        order.push_back(Waiting { id, deadline: Instant::now(), _slot: slot });
+                    }
+                    for head in order {
+                        if let Some(response) = ready.get_mut(&head.id).and_then(|r| r.pop_front()) {
+                            if out.send(Ok(response)).await.is_err() {
+                                return;
+                            }
+                        }
+                    }
+                    return;
+                }
+            },
+            // 客户端断开后放弃所有等待中的结果，让预测任务随之取消
+            _ = out.closed() => return,
+            _ = tokio::time::sleep_until(deadline.unwrap_or_else(Instant::now)), if deadline.is_some() => {
+                // 队首的图片超时，返回错误并继续发送后面的结果
+                let head = order.pop_front().unwrap();
+                *skipped.entry(head.id).or_default() += 1;
+                let response = straggler_response(head.id, timeout);
+                if out.send(Ok(response)).await.is_err() {
+                    return;
+                }
+            }
+        }
+    }
+}
+
+fn straggler_response(id: i32, timeout: Duration) -> ImageVectorResponse {
+    ImageVectorResponse {
+        id,
+        result: Some(ImageResult::Error(Error {
+            code: tonic::Code::DeadlineExceeded as i32,
+            message: format!(
+                "Result of id {} was not ready within {} ms in in-order mode",
+                id,
+                timeout.as_millis()
+            ),
+        })),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pb::image_prediction_pb::ImageVector;
+    use tonic::Code;
+
+    fn response(id: i32) -> ImageVectorResponse {
+        ImageVectorResponse {
+            id,
+            result: Some(ImageResult::ImageVector(ImageVector {
+                values: vec![id as f32],
+            })),
+        }
+    }
+
+    fn options(max_buffered: usize, straggler_timeout_ms: u64) -> ReorderOptions {
+        ReorderOptions {
+            max_buffered,
+            straggler_timeout_ms,
+        }
+    }
+
+    // 接收out中的所有响应，返回id和错误码
+    async fn collect(
+        mut rx: mpsc::Receiver<Result<ImageVectorResponse, Status>>,
+    ) -> Vec<(i32, i32)> {
+        let mut received = vec![];
+        while let Some(response) = rx.recv().await {
+            let response = response.unwrap();
+            let code = match response.error() {
+                Some(e) => e.code,
+                None => Code::Ok as i32,
+            };
+            received.push((response.id, code));
+        }
+        received
+    }
+
+    // 测试乱序完成的结果按登记的顺序发送，重复的id按各自的顺序对应
+    #[tokio::test]
+    async fn test_reorder_by_arrival() {
+        let (out, rx) = mpsc::channel(16);
+        let reorderer = Reorderer::spawn(&options(16, 10_000), out);
+        for id in [3, 1, 2, 1] {
+            reorderer.register(id).await;
+        }
+
+        let completions = reorderer.completions();
+        drop(reorderer);
+        for id in [1, 2, 1, 3] {
+            completions.send(Ok(response(id))).await.unwrap();
+        }
+        drop(completions);
+
+        let ok = Code::Ok as i32;
+        assert_eq!(collect(rx).await, vec![(3, ok), (1, ok), (2, ok), (1, ok)]);
+    }
+
+    // 测试队首的图片超时后返回DEADLINE_EXCEEDED，之后到达的结果被丢弃
+    #[tokio::test]
+    async fn test_straggler_timeout() {
+        let (out, rx) = mpsc::channel(16);
+        let reorderer = Reorderer::spawn(&options(16, 50), out);
+        reorderer.register(1).await;
+        reorderer.register(2).await;
+
+        let completions = reorderer.completions();
+        drop(reorderer);
+        completions.send(Ok(response(2))).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        completions.send(Ok(response(1))).await.unwrap();
+        drop(completions);
+
+        assert_eq!(
+            collect(rx).await,
+            vec![(1, Code::DeadlineExceeded as i32), (2, Code::Ok as i32)]
+        );
+    }
+
+    // 测试缓冲区已满时登记新的图片需要等待队首的结果发送出去
+    #[tokio::test]
+    async fn test_bounded_buffer() {
+        let (out, mut rx) = mpsc::channel(16);
+        let reorderer = Reorderer::spawn(&options(2, 10_000), out);
+        reorderer.register(1).await;
+        reorderer.register(2).await;
+
+        let completions = reorderer.completions();
+        completions.send(Ok(response(2))).await.unwrap();
+        let blocked = tokio::time::timeout(Duration::from_millis(50), reorderer.register(3)).await;
+        assert!(blocked.is_err());
+
+        completions.send(Ok(response(1))).await.unwrap();
+        assert_eq!(rx.recv().await.unwrap().unwrap().id, 1);
+        assert_eq!(rx.recv().await.unwrap().unwrap().id, 2);
+        let registered =
+            tokio::time::timeout(Duration::from_millis(50), reorderer.register(3)).await;
+        assert!(registered.is_ok());
+    }
+
+    // 测试从metadata中读取响应的顺序
+    #[test]
+    fn test_response_order_from_metadata() {
+        let mut metadata = MetadataMap::new();
+        assert_eq!(
+            ResponseOrder::from_metadata(&metadata).unwrap(),
+            ResponseOrder::Completion
+        );
+
+        metadata.insert(RESPONSE_ORDER_KEY, "in-order".parse().unwrap());
+        assert_eq!(
+            ResponseOrder::from_metadata(&metadata).unwrap(),
+            ResponseOrder::Arrival
+        );
+
+        metadata.insert(RESPONSE_ORDER_KEY, "random".parse().unwrap());
+        assert_eq!(
+            ResponseOrder::from_metadata(&metadata).unwrap_err(),
+            r#"Unknown x-response-order "random", expected completion or in-order"#
+        );
+    }
+}
@@ -1,115 +1,1619 @@
 use super::pb::image_prediction_pb;
 
-use super::tf_serving::predict_service::predict as tf_predict;
-use base64_simd::URL_SAFE;
+use crate::inference::InferenceBackend;
 use image_prediction_pb::image_prediction_server::ImagePrediction;
-use image_prediction_pb::{ImagePredictionRequest, ImageVectorResponse};
+use image_prediction_pb::image_vector_response::Result as ImageResult;
+use image_prediction_pb::{
+    Error, ImageBatchRequest, ImagePredictionRequest, ImageVector, ImageVectorResponse,
+};
 use log::{debug, error};
+use opentelemetry::trace::FutureExt;
+use opentelemetry::Context;
 use std::collections::HashMap;
 use std::sync::Arc;
+use tokio::sync::{mpsc, OwnedSemaphorePermit, Semaphore};
 use tokio::task;
 use tokio::time::Instant;
 use tokio_stream::wrappers::ReceiverStream;
-use tokio_stream::StreamExt;
+use tokio_stream::{Stream, StreamExt};
 use tonic::{Request, Response, Status};
 
+use crate::batching::Batcher;
+use crate::cache::{cached, EmbeddingCache};
+use crate::coalesce::SingleFlight;
 use crate::config::Model;
+use crate::deadline::{self, cancelled, deadline_exceeded};
+use crate::limits::{ConcurrencyLimiter, ConcurrencyOptions};
+use crate::metrics::{metrics, MeteredStream};
+use crate::readiness::ModelReadiness;
+use crate::registry::ModelRegistry;
+use crate::reorder::{ReorderOptions, Reorderer, ResponseOrder};
+use crate::telemetry::{image_context, record_result, server_context};
+use crate::transcode::transcode_for;
+use crate::validation::{self, ValidationOptions};
 
 // This is the service that implements the ImagePrediction trait
+#[derive(Clone)]
 pub struct ImagePredictionService {
-    // Add a field to store the available model names
-    pub models: Arc<HashMap<String, Model>>,
-    pub tf_serving_url: Arc<String>,
+    // 当前生效的模型及其批处理队列，配置热加载时会被整体替换
+    pub registry: ModelRegistry,
+    // 后台检查得到的模型可用性，不可用的模型直接返回UNAVAILABLE
+    pub readiness: ModelReadiness,
+    // 全局和每个模型同时进行的预测数量的限制
+    pub limiter: Arc<ConcurrencyLimiter>,
+    // 按到达顺序返回结果时重排缓冲区的参数
+    pub reorder: ReorderOptions,
+    // 以图片内容为键的预测结果缓存，命中时不再调用推理服务
+    pub cache: Option<Arc<EmbeddingCache>>,
+    // 同时进行的相同请求只调用一次推理服务
+    pub flights: Option<Arc<SingleFlight>>,
+    // 转发给推理服务之前校验图片的默认限制，模型可以有自己的限制
+    pub validation: Option<ValidationOptions>,
 }
+
+impl ImagePredictionService {
+    // 为配置了batch参数的模型启动批处理队列，需要在tokio运行时中调用
+    pub fn new(models: HashMap<String, Model>, backend: Arc<dyn InferenceBackend>) -> Self {
+        ImagePredictionService {
+            registry: ModelRegistry::new(models, backend),
+            readiness: ModelReadiness::new(),
+            limiter: Arc::new(ConcurrencyLimiter::default()),
+            reorder: ReorderOptions::default(),
+            cache: None,
+            flights: None,
+            validation: None,
+        }
+    }
+
+    // 使用配置中的重排缓冲区参数
+    pub fn with_reorder(mut self, options: ReorderOptions) -> Self {
+        self.reorder = options;
+        self
+    }
+
+    // 使用配置中的并发限制
+    pub fn with_concurrency(mut self, options: ConcurrencyOptions) -> Self {
+        self.limiter = Arc::new(ConcurrencyLimiter::new(options));
+        self
+    }
+
+    // 使用预测结果缓存，与配置热加载共用
+    pub fn with_cache(mut self, cache: Arc<EmbeddingCache>) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    // 合并同时进行的相同请求
+    pub fn with_coalescing(mut self) -> Self {
+        self.flights = Some(Arc::new(SingleFlight::new()));
+        self
+    }
+
+    // 校验图片的格式、大小和尺寸，不合法的图片直接返回INVALID_ARGUMENT
+    pub fn with_validation(mut self, options: ValidationOptions) -> Self {
+        self.validation = Some(options);
+        self
+    }
+
+    // 使用后台检查维护的模型可用性
+    pub fn with_readiness(mut self, readiness: ModelReadiness) -> Self {
+        self.readiness = readiness;
+        self
+    }
+
+    // 根据请求中的模型名称查找模型配置和批处理队列，每次查找都使用最新的模型表
+    fn lookup_model(&self, model_name: &str) -> Option<(Model, Option<Batcher>)> {
+        let table = self.registry.snapshot();
+        let model = table.get(model_name)?.clone();
+        Some((model, table.batcher(model_name).cloned()))
+    }
+
+    // 按模型的限制校验图片，返回不合法的原因
+    fn invalid_image(&self, model: &Model, image: &[u8]) -> Option<Status> {
+        let options = validation::options_for(model, self.validation.as_ref())?;
+        validation::validate(image, options)
+            .err()
+            .map(Status::invalid_argument)
+    }
+
+    // 对单张图片进行预测，模型查找、批处理和错误映射在所有RPC之间共用
+    // 超过截止时间时放弃对推理服务的调用，返回DEADLINE_EXCEEDED
+    async fn predict_request(
+        &self,
+        image_request: ImagePredictionRequest,
+        deadline: Option<Instant>,
+    ) -> Result<Vec<f32>, Status> {
+        let res_id = image_request.id;
+        let (req_model, batcher) = self
+            .lookup_model(&image_request.model)
+            .ok_or_else(|| unknown_model(&image_request.model))?;
+        let timer = metrics().start_request(&req_model.name);
+        let cx = Context::current();
+        let invalid = self.invalid_image(&req_model, &image_request.image);
+        let prediction = cached(
+            self.cache.as_deref(),
+            self.flights.as_deref(),
+            &req_model,
+            image_request.image,
+            |image| {
+                predict_limited(
+                    self.registry.backend().as_ref(),
+                    &self.limiter,
+                    batcher.as_ref(),
+                    &req_model,
+                    &self.readiness,
+                    self.validation.as_ref(),
+                    ImagePredictionRequest {
+                        image,
+                        ..image_request
+                    },
+                )
+            },
+        );
+        let result = match invalid {
+            Some(status) => Err(status),
+            None => tokio::select! {
+                biased;
+                _ = deadline::sleep_until(deadline) => Err(deadline_exceeded(res_id)),
+                result = prediction => result,
+            },
+        };
+        timer.finish(&result);
+        record_result(&cx, &result);
+        result
+    }
+
+    // 在独立的任务中预测一张图片，并把带有id的结果发送到响应流中
+    // 每张图片都有一个子span，父span为整个流的span
+    // stream_permit在响应被放入响应流后释放，客户端读取响应较慢时也会暂停读取请求
+    // 超过截止时间或客户端断开时放弃对推理服务的调用
+    fn spawn_prediction(
+        &self,
+        image_request: ImagePredictionRequest,
+        tx: mpsc::Sender<Result<ImageVectorResponse, Status>>,
+        stream_cx: &Context,
+        stream_permit: Option<OwnedSemaphorePermit>,
+        deadline: Option<Instant>,
+    ) {
+        let res_id = image_request.id;
+        let cx = image_context(
+            stream_cx,
+            &image_request.model,
+            res_id,
+            image_request.image.len(),
+        );
+
+        // Check if the model name is in the field of the service
+        let (req_model, batcher) = match self.lookup_model(&image_request.model) {
+            Some(found) => found,
+            None => {
+                // 未知模型的错误作为该id的响应返回
+                let status = unknown_model(&image_request.model);
+                record_result(&cx, &Err::<(), _>(status.clone()));
+                send_error(res_id, status, tx);
+                return;
+            }
+        };
+
+        let timer = metrics().start_request(&req_model.name);
+        let invalid = self.invalid_image(&req_model, &image_request.image);
+
+        // clone the data before the async block
+        let backend = Arc::clone(self.registry.backend());
+        let limiter = Arc::clone(&self.limiter);
+        let cache = self.cache.clone();
+        let flights = self.flights.clone();
+        let readiness = self.readiness.clone();
+        let validation = self.validation.clone();
+
+        task::spawn(
+            async move {
+                let prediction = cached(
+                    cache.as_deref(),
+                    flights.as_deref(),
+                    &req_model,
+                    image_request.image,
+                    |image| {
+                        predict_limited(
+                            backend.as_ref(),
+                            &limiter,
+                            batcher.as_ref(),
+                            &req_model,
+                            &readiness,
+                            validation.as_ref(),
+                            ImagePredictionRequest {
+                                image,
+                                ..image_request
+                            },
+                        )
+                    },
+                );
+                // 放弃的调用随prediction一起被释放，对应的HTTP请求会被中断
+                // 不合法的图片不调用推理服务，错误作为该id的响应返回
+                let result = match invalid {
+                    Some(status) => Err(status),
+                    None => tokio::select! {
+                        biased;
+                        _ = deadline::sleep_until(deadline) => Err(deadline_exceeded(res_id)),
+                        _ = tx.closed() => Err(cancelled()),
+                        result = prediction => result,
+                    },
+                };
+                timer.finish(&result);
+                record_result(&Context::current(), &result);
+                if tx.is_closed() {
+                    return;
+                }
+
+                // 单张图片的失败作为该id的错误响应返回，不会中断整个流
+                if let Err(err) = tx.send(Ok(image_response(res_id, result))).await {
+                    error!("Error sending response: {:?}", err);
+                }
+                drop(stream_permit);
+            }
+            .with_context(cx),
+        );
+    }
+
+    // 逐个读取流中的图片并分发给独立的任务，与响应的发送同时进行
+    // 读取请求失败时把错误状态发送到响应流中，结束整个流
+    // 按到达顺序返回时，结果先经过重排缓冲区再发送到响应流
+    async fn dispatch_stream<S>(
+        self,
+        mut stream: S,
+        tx: mpsc::Sender<Result<ImageVectorResponse, Status>>,
+        cx: Context,
+        order: ResponseOrder,
+        deadline: Option<Instant>,
+    ) where
+        S: Stream<Item = Result<ImagePredictionRequest, Status>> + Unpin,
+    {
+        let reorderer = match order {
+            ResponseOrder::Arrival => Some(Reorderer::spawn(&self.reorder, tx.clone())),
+            ResponseOrder::Completion => None,
+        };
+        let tx = match &reorderer {
+            Some(reorderer) => reorderer.completions(),
+            None => tx,
+        };
+
+        let stream_limit = self.limiter.stream_limit();
+        loop {
+            // 达到流的并发上限时先等待已有的预测完成，再读取下一张图片
+            let permit = acquire_stream_permit(&stream_limit).await;
+            // 客户端断开后不再读取
+            let next = tokio::select! {
+                next = stream.next() => next,
+                _ = tx.closed() => break,
+            };
+            match next {
+                Some(Ok(image_request)) => {
+                    if let Some(reorderer) = &reorderer {
+                        reorderer.register(image_request.id).await;
+                    }
+                    self.spawn_prediction(image_request, tx.clone(), &cx, permit, deadline)
+                }
+                Some(Err(status)) => {
+                    let _ = tx.send(Err(status)).await;
+                    break;
+                }
+                None => break,
+            }
+        }
+    }
+}
+
 #[tonic::async_trait]
 impl ImagePrediction for ImagePredictionService {
-    type PredictStream = ReceiverStream<Result<ImageVectorResponse, Status>>;
+    type PredictStream = MeteredStream<ReceiverStream<Result<ImageVectorResponse, Status>>>;
+    type PredictBatchStream = MeteredStream<ReceiverStream<Result<ImageVectorResponse, Status>>>;
 
     async fn predict(
         &self,
         request: Request<tonic::Streaming<ImagePredictionRequest>>,
     ) -> Result<Response<Self::PredictStream>, Status> {
+        // 流在响应全部发送或客户端断开后才被视为关闭
+        let order =
+            ResponseOrder::from_metadata(request.metadata()).map_err(Status::invalid_argument)?;
+        let deadline = deadline::from_request(&request);
+        let stream_guard = metrics().start_stream("Predict");
+        let cx = server_context(request.metadata(), "Predict");
+
         // 创建一个多生产者单消费者通道，用于发送响应
-        let (tx, rx) = tokio::sync::mpsc::channel(1024);
+        let (tx, rx) = mpsc::channel(1024);
 
         // Get the stream of image requests from the client
-        let mut stream = request.into_inner();
-
-        while let Some(image_request) = stream.next().await {
-            let tx = tx.clone();
-            let image_request = image_request?;
-
-            // Get the model name from the image request
-            let model_name = image_request.model;
-
-            let models = Arc::clone(&self.models);
-            // Check if the model name is in the field of the service
-            let req_model = match models.get(&model_name) {
-                Some(o) => o,
-                None => {
-                    // Return an error status with a message
-                    let err = Status::invalid_argument(format!(
-                        "The model name {} does not exist",
-                        model_name
-                    ));
-                    let _ = tx.send(Err(err)).await;
-                    continue;
-                }
+        let stream = request.into_inner();
+
+        // 在后台读取请求并分发，每张图片的结果在完成后立即发送，不需要等客户端结束发送
+        task::spawn(
+            self.clone()
+                .dispatch_stream(stream, tx, cx, order, deadline),
+        );
+
+        // 返回带有响应结果的流式响应对象
+        Ok(Response::new(MeteredStream::new(
+            ReceiverStream::new(rx),
+            stream_guard,
+        )))
+    }
+
+    // 单张图片的预测，失败时直接返回对应的gRPC状态
+    async fn predict_one(
+        &self,
+        request: Request<ImagePredictionRequest>,
+    ) -> Result<Response<ImageVectorResponse>, Status> {
+        let deadline = deadline::from_request(&request);
+        let cx = server_context(request.metadata(), "PredictOne");
+        let image_request = request.into_inner();
+        let res_id = image_request.id;
+        let cx = image_context(&cx, &image_request.model, res_id, image_request.image.len());
+        // 推理服务错误在metadata中的内部标记不发送给客户端
+        let values = self
+            .predict_request(image_request, deadline)
+            .with_context(cx)
+            .await
+            .map_err(|status| Status::new(status.code(), status.message()))?;
+
+        Ok(Response::new(image_response(res_id, Ok(values))))
+    }
+
+    // 一次请求中包含多张图片，每张图片的结果在完成后立即发送
+    async fn predict_batch(
+        &self,
+        request: Request<ImageBatchRequest>,
+    ) -> Result<Response<Self::PredictBatchStream>, Status> {
+        let order =
+            ResponseOrder::from_metadata(request.metadata()).map_err(Status::invalid_argument)?;
+        let deadline = deadline::from_request(&request);
+        let stream_guard = metrics().start_stream("PredictBatch");
+        let cx = server_context(request.metadata(), "PredictBatch");
+        let requests = request.into_inner().requests;
+        let (tx, rx) = mpsc::channel(requests.len().max(1));
+
+        // 在后台按流的并发上限逐个分发，已完成的结果立即发送
+        let stream = tokio_stream::iter(requests.into_iter().map(Ok));
+        task::spawn(
+            self.clone()
+                .dispatch_stream(stream, tx, cx, order, deadline),
+        );
+
+        Ok(Response::new(MeteredStream::new(
+            ReceiverStream::new(rx),
+            stream_guard,
+        )))
+    }
+}
+
+// 不限制流的并发数量时不需要等待
+async fn acquire_stream_permit(limit: &Option<Arc<Semaphore>>) -> Option<OwnedSemaphorePermit> {
+    match limit {
+        // 信号量不会被关闭
+        Some(semaphore) => Some(Arc::clone(semaphore).acquire_owned().await.unwrap()),
+        None => None,
+    }
+}
+
+// 在并发限制内对单张图片进行预测，已知不可用的模型不再转发给推理服务
+// 模型的熔断器打开时直接返回UNAVAILABLE，否则把预测结果记录到熔断器中
+// validation为全局的图片校验参数，格式转换时用来限制解码的尺寸
+async fn predict_limited(
+    backend: &dyn InferenceBackend,
+    limiter: &ConcurrencyLimiter,
+    batcher: Option<&Batcher>,
+    req_model: &Model,
+    readiness: &ModelReadiness,
+    validation: Option<&ValidationOptions>,
+    image_request: ImagePredictionRequest,
+) -> Result<Vec<f32>, Status> {
+    if let Some(status) = readiness.unavailable(&req_model.name) {
+        return Err(status);
+    }
+    let call = match readiness.breaker(req_model) {
+        Some(breaker) => Some(breaker.acquire().map_err(Status::unavailable)?),
+        None => None,
+    };
+    let _permit = limiter.acquire(req_model).await?;
+    // 格式转换占用该模型的并发名额，失败时不计入熔断器
+    let image_request = ImagePredictionRequest {
+        image: transcode_for(req_model, validation, image_request.image).await?,
+        ..image_request
+    };
+    let result = predict_image(backend, batcher, req_model, image_request).await;
+    if let Some(call) = call {
+        call.finish(&result);
+    }
+    result
+}
+
+// 在独立的任务中把错误作为该id的响应发送
+fn send_error(id: i32, err: Status, tx: mpsc::Sender<Result<ImageVectorResponse, Status>>) {
+    task::spawn(async move {
+        let _ = tx.send(Ok(image_response(id, Err(err)))).await;
+    });
+}
+
+fn unknown_model(model_name: &str) -> Status {
+    Status::invalid_argument(format!("The model name {} does not exist", model_name))
+}
+
+// 根据单张图片的预测结果构造响应，错误会以Error消息的形式附带在对应的id上
+fn image_response(id: i32, result: Result<Vec<f32>, Status>) -> ImageVectorResponse {
+    let result = match result {
+        Ok(values) => ImageResult::ImageVector(ImageVector { values }),
+        Err(status) => ImageResult::Error(Error {
+            code: status.code() as i32,
+            message: status.message().to_string(),
+        }),
+    };
+    ImageVectorResponse {
+        id,
+        result: Some(result),
+    }
+}
+
+// 对单张图片进行预测，推理服务的错误会被转换为对应的gRPC状态
+// 配置了批处理的模型会把图片交给批处理队列，与其他请求合并后发送
+async fn predict_image(
+    backend: &dyn InferenceBackend,
+    batcher: Option<&Batcher>,
+    req_model: &Model,
+    image_request: ImagePredictionRequest,
+) -> Result<Vec<f32>, Status> {
+    // record start time
+    let start_time = Instant::now(); // 记录开始时间
+                                     // Get the image data from the image request
+    let image_data = image_request.image;
+
+    // Get the id from the image request
+    let res_id = image_request.id;
+
+    let image_len = image_data.len();
+
+    // send prection request to tensorflow serving
+    let img_vector = match batcher {
+        Some(batcher) => batcher.predict(image_data).await?,
+        None => backend.predict(req_model, &image_data).await?,
+    };
+
+    let img_vector = match img_vector {
+        v if !v.is_empty() => v,
+        _ => {
+            return Err(Status::internal(format!(
+                "Empty prediction from model {} version {}",
+                req_model.name, req_model.version
+            )))
+        }
+    };
+
+    let elapsed_time = Instant::now().duration_since(start_time).as_secs_f32();
+    debug!(
+        "recv image_data len: {}\t order id : {} \t executed in: {:.2} s",
+        image_len, res_id, elapsed_time,
+    );
+
+    Ok(img_vector)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::batching::BatchOptions;
+    use crate::breaker::{BreakerOptions, BreakerState};
+    use crate::cache::CacheOptions;
+    use crate::inference::fake::FakeBackend;
+    use crate::pb::image_prediction_pb::image_prediction_client::ImagePredictionClient;
+    use crate::pb::image_prediction_pb::image_prediction_server::ImagePredictionServer;
+    use crate::tf_serving::backend::new_backend;
+    use crate::tf_serving::client::ClientOptions;
+    use crate::transcode::{transcode, TranscodeOptions};
+    use crate::validation::tests::encode_image;
+    use crate::validation::ImageFormat;
+    use image::io::Limits;
+    use image::ImageOutputFormat;
+    use mockito::{mock, Matcher};
+    use std::time::Duration;
+    use tokio::net::TcpListener;
+    use tokio_stream::wrappers::TcpListenerStream;
+    use tonic::transport::Server;
+    use tonic::Code;
+
+    fn test_client(url: &str) -> Arc<dyn InferenceBackend> {
+        new_backend(url, &ClientOptions::default()).unwrap()
+    }
+
+    fn test_model() -> Model {
+        model_named("foo")
+    }
+
+    fn model_named(name: &str) -> Model {
+        Model {
+            name: name.to_string(),
+            version: 1,
+            input_name: "b64_input_bytes".to_string(),
+            ..Model::default()
+        }
+    }
+
+    fn test_request(id: i32) -> ImagePredictionRequest {
+        request_for("foo", id)
+    }
+
+    fn request_for(model: &str, id: i32) -> ImagePredictionRequest {
+        ImagePredictionRequest {
+            image: vec![1, 2, 3],
+            model: model.to_string(),
+            id,
+        }
+    }
+
+    // 在随机端口上启动gRPC服务，并返回连接到该服务的客户端
+    async fn start_service(
+        service: ImagePredictionService,
+    ) -> ImagePredictionClient<tonic::transport::Channel> {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(
+            Server::builder()
+                .layer(tonic::service::interceptor(deadline::RecordDeadline))
+                .add_service(ImagePredictionServer::new(service))
+                .serve_with_incoming(TcpListenerStream::new(listener)),
+        );
+
+        ImagePredictionClient::connect(format!("http://{}", addr))
+            .await
+            .unwrap()
+    }
+
+    // 测试TensorFlow Serving返回正常结果时，响应中包含向量和id
+    #[tokio::test]
+    async fn test_predict_image_success() {
+        let _m = mock("POST", "/models/foo/versions/1:predict")
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(r#"{"predictions": [[0.1, 0.2]]}"#)
+            .create();
+
+        let resp = predict_image(
+            test_client(&mockito::server_url()).as_ref(),
+            None,
+            &test_model(),
+            test_request(7),
+        )
+        .await
+        .unwrap();
+        assert_eq!(resp, vec![0.1, 0.2]);
+    }
+
+    // 测试TensorFlow Serving返回空的预测结果时，被视为错误
+    #[tokio::test]
+    async fn test_predict_image_empty_prediction() {
+        let _m = mock("POST", "/models/foo/versions/1:predict")
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(r#"{"predictions": []}"#)
+            .create();
+
+        let status = predict_image(
+            test_client(&mockito::server_url()).as_ref(),
+            None,
+            &test_model(),
+            test_request(1),
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(status.code(), Code::Internal);
+    }
+
+    // 测试TensorFlow Serving返回各种错误状态码时，被映射为对应的gRPC状态码
+    #[tokio::test]
+    async fn test_predict_image_error_status() {
+        let cases = [
+            (400, Code::InvalidArgument),
+            (404, Code::Unavailable),
+            (500, Code::Internal),
+            (503, Code::Unavailable),
+            (504, Code::DeadlineExceeded),
+        ];
+        for (http_status, code) in cases {
+            let _m = mock("POST", "/models/foo/versions/1:predict")
+                .with_status(http_status)
+                .with_body("error")
+                .create();
+
+            let status = predict_image(
+                test_client(&mockito::server_url()).as_ref(),
+                None,
+                &test_model(),
+                test_request(1),
+            )
+            .await
+            .unwrap_err();
+            assert_eq!(status.code(), code, "http status {}", http_status);
+        }
+    }
+
+    // 测试TensorFlow Serving返回无效的JSON数据时，被映射为INTERNAL
+    #[tokio::test]
+    async fn test_predict_image_malformed_payload() {
+        let _m = mock("POST", "/models/foo/versions/1:predict")
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(r#"{"predictions": [[0.1, 0.2]"#)
+            .create();
+
+        let status = predict_image(
+            test_client(&mockito::server_url()).as_ref(),
+            None,
+            &test_model(),
+            test_request(1),
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(status.code(), Code::Internal);
+    }
+
+    // 测试无法连接TensorFlow Serving时，被映射为UNAVAILABLE
+    #[tokio::test]
+    async fn test_predict_image_connection_refused() {
+        // 绑定后立即释放端口，保证该端口上没有服务监听
+        let addr = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+
+        let status = predict_image(
+            test_client(&format!("http://{}", addr)).as_ref(),
+            None,
+            &test_model(),
+            test_request(1),
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(status.code(), Code::Unavailable);
+    }
+
+    // 测试错误结果被转换为带有id的Error响应
+    #[test]
+    fn test_image_response() {
+        let resp = image_response(42, Err(Status::unavailable("boom")));
+        assert_eq!(resp.id, 42);
+        assert!(resp.vector().is_empty());
+        assert_eq!(
+            resp.error(),
+            Some(Error {
+                code: Code::Unavailable as i32,
+                message: "boom".to_string(),
+            })
+        );
+
+        let resp = image_response(43, Ok(vec![0.5]));
+        assert_eq!(resp.id, 43);
+        assert_eq!(resp.vector(), vec![0.5]);
+        assert_eq!(resp.error(), None);
+    }
+
+    // 测试流中某些图片失败时，其余图片仍然能正常返回结果
+    #[tokio::test]
+    async fn test_predict_stream_keeps_alive_on_errors() {
+        let _m1 = mock("POST", "/models/foo/versions/1:predict")
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(r#"{"predictions": [[0.1, 0.2]]}"#)
+            .create();
+        let _m2 = mock("POST", "/models/bar/versions/1:predict")
+            .with_status(503)
+            .with_body("Service Unavailable")
+            .create();
+
+        let models = HashMap::from([
+            ("foo".to_string(), model_named("foo")),
+            ("bar".to_string(), model_named("bar")),
+        ]);
+        let mut client = start_service(ImagePredictionService::new(
+            models,
+            test_client(&mockito::server_url()),
+        ))
+        .await;
+
+        let requests = vec![
+            request_for("unknown", 1),
+            request_for("foo", 2),
+            request_for("bar", 3),
+            request_for("foo", 4),
+        ];
+        let mut responses: Vec<ImageVectorResponse> = client
+            .predict(tokio_stream::iter(requests))
+            .await
+            .unwrap()
+            .into_inner()
+            .map(|r| r.unwrap())
+            .collect()
+            .await;
+        responses.sort_by_key(|r| r.id);
+
+        let codes: Vec<Option<i32>> = responses
+            .iter()
+            .map(|r| r.error().as_ref().map(|e| e.code))
+            .collect();
+        assert_eq!(
+            codes,
+            vec![
+                Some(Code::InvalidArgument as i32),
+                None,
+                Some(Code::Unavailable as i32),
+                None
+            ]
+        );
+    }
+
+    // 收集流中所有的响应并按id排序
+    async fn collect_responses(
+        client: &mut ImagePredictionClient<tonic::transport::Channel>,
+        requests: Vec<ImagePredictionRequest>,
+    ) -> Vec<ImageVectorResponse> {
+        let mut responses: Vec<ImageVectorResponse> = client
+            .predict(tokio_stream::iter(requests))
+            .await
+            .unwrap()
+            .into_inner()
+            .map(|r| r.unwrap())
+            .collect()
+            .await;
+        responses.sort_by_key(|r| r.id);
+        responses
+    }
+
+    // 测试使用推理服务的测试替身时，每个id得到确定的向量，失败的模型返回对应的错误
+    #[tokio::test]
+    async fn test_predict_stream_with_fake_backend() {
+        let models = HashMap::from([
+            ("foo".to_string(), model_named("foo")),
+            ("bar".to_string(), model_named("bar")),
+        ]);
+        let backend = Arc::new(FakeBackend::new().failing("bar"));
+        let mut client = start_service(ImagePredictionService::new(models, backend)).await;
+
+        let requests: Vec<ImagePredictionRequest> = (0..20)
+            .map(|id| ImagePredictionRequest {
+                image: vec![0; id as usize + 1],
+                model: if id % 5 == 4 { "bar" } else { "foo" }.to_string(),
+                id,
+            })
+            .collect();
+        let responses = collect_responses(&mut client, requests).await;
+
+        assert_eq!(responses.len(), 20);
+        for (id, resp) in responses.iter().enumerate() {
+            assert_eq!(resp.id, id as i32);
+            if id % 5 == 4 {
+                assert_eq!(
+                    resp.error(),
+                    Some(Error {
+                        code: Code::Unavailable as i32,
+                        message: "model bar is broken".to_string(),
+                    })
+                );
+            } else {
+                assert_eq!(
+                    resp.vector(),
+                    FakeBackend::expected(&model_named("foo"), &vec![0; id + 1])
+                );
+            }
+        }
+    }
+
+    // 测试配置了批处理的模型会把流中的图片合并为更少的调用
+    #[tokio::test]
+    async fn test_predict_stream_batching_with_fake_backend() {
+        let model = Model {
+            batch: Some(BatchOptions {
+                max_size: 8,
+                max_delay_ms: 50,
+            }),
+            ..model_named("foo")
+        };
+        let backend = Arc::new(FakeBackend::new());
+        let mut client = start_service(ImagePredictionService::new(
+            HashMap::from([("foo".to_string(), model)]),
+            backend.clone(),
+        ))
+        .await;
+
+        let requests = (0..16).map(|id| request_for("foo", id)).collect();
+        let responses = collect_responses(&mut client, requests).await;
+
+        assert_eq!(responses.len(), 16);
+        assert_eq!(backend.images(), 16);
+        assert!(backend.calls() < 16, "calls: {}", backend.calls());
+    }
+
+    // 测试单张图片的RPC：成功时返回向量，失败时返回对应的gRPC状态
+    #[tokio::test]
+    async fn test_predict_one() {
+        let models = HashMap::from([
+            ("foo".to_string(), model_named("foo")),
+            ("bar".to_string(), model_named("bar")),
+        ]);
+        let backend = Arc::new(FakeBackend::new().failing("bar"));
+        let mut client = start_service(ImagePredictionService::new(models, backend)).await;
+
+        let resp = client
+            .predict_one(request_for("foo", 5))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(resp.id, 5);
+        assert_eq!(
+            resp.vector(),
+            FakeBackend::expected(&model_named("foo"), &[1, 2, 3])
+        );
+
+        let status = client.predict_one(request_for("bar", 6)).await.unwrap_err();
+        assert_eq!(status.code(), Code::Unavailable);
+
+        let status = client
+            .predict_one(request_for("unknown", 7))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), Code::InvalidArgument);
+    }
+
+    // 测试多张图片的RPC：每张图片都有一个带id的响应，错误附带在对应的id上
+    #[tokio::test]
+    async fn test_predict_batch() {
+        let models = HashMap::from([
+            ("foo".to_string(), model_named("foo")),
+            ("bar".to_string(), model_named("bar")),
+        ]);
+        let backend = Arc::new(FakeBackend::new().failing("bar"));
+        let mut client = start_service(ImagePredictionService::new(models, backend)).await;
+
+        let request = ImageBatchRequest {
+            requests: vec![
+                request_for("foo", 1),
+                request_for("bar", 2),
+                request_for("unknown", 3),
+                request_for("foo", 4),
+            ],
+        };
+        let mut responses: Vec<ImageVectorResponse> = client
+            .predict_batch(request)
+            .await
+            .unwrap()
+            .into_inner()
+            .map(|r| r.unwrap())
+            .collect()
+            .await;
+        responses.sort_by_key(|r| r.id);
+
+        let codes: Vec<Option<i32>> = responses
+            .iter()
+            .map(|r| r.error().as_ref().map(|e| e.code))
+            .collect();
+        assert_eq!(
+            codes,
+            vec![
+                None,
+                Some(Code::Unavailable as i32),
+                Some(Code::InvalidArgument as i32),
+                None
+            ]
+        );
+
+        // 空的请求立即结束响应流
+        let responses: Vec<_> = client
+            .predict_batch(ImageBatchRequest { requests: vec![] })
+            .await
+            .unwrap()
+            .into_inner()
+            .collect()
+            .await;
+        assert!(responses.is_empty());
+    }
+
+    // 测试被标记为不可用的模型直接返回UNAVAILABLE，不会调用推理服务
+    #[tokio::test]
+    async fn test_predict_unavailable_model_fails_fast() {
+        let models = HashMap::from([
+            ("foo".to_string(), model_named("foo")),
+            ("bar".to_string(), model_named("bar")),
+        ]);
+        let backend = Arc::new(FakeBackend::new());
+        let readiness = ModelReadiness::new();
+        readiness.mark_unavailable("bar", "state LOADING".to_string());
+        let mut client = start_service(
+            ImagePredictionService::new(models, backend.clone()).with_readiness(readiness),
+        )
+        .await;
+
+        let status = client.predict_one(request_for("bar", 1)).await.unwrap_err();
+        assert_eq!(status.code(), Code::Unavailable);
+        assert_eq!(
+            status.message(),
+            "Model bar is not available: state LOADING"
+        );
+
+        let responses = collect_responses(
+            &mut client,
+            vec![request_for("bar", 2), request_for("foo", 3)],
+        )
+        .await;
+        assert_eq!(
+            responses[0].error(),
+            Some(Error {
+                code: Code::Unavailable as i32,
+                message: "Model bar is not available: state LOADING".to_string(),
+            })
+        );
+        assert_eq!(responses[1].error(), None);
+        assert_eq!(backend.calls(), 1);
+    }
+
+    // 测试模型连续失败后熔断，之后的请求直接返回UNAVAILABLE，不影响其他模型
+    #[tokio::test]
+    async fn test_circuit_breaker_short_circuits() {
+        let models = HashMap::from([
+            ("foo".to_string(), model_named("foo")),
+            ("bar".to_string(), model_named("bar")),
+        ]);
+        let backend = Arc::new(FakeBackend::new().failing("bar"));
+        let readiness = ModelReadiness::new().with_breakers(BreakerOptions {
+            window_size: 4,
+            min_calls: 4,
+            open_ms: 60_000,
+            ..BreakerOptions::default()
+        });
+        let mut client = start_service(
+            ImagePredictionService::new(models, backend.clone()).with_readiness(readiness.clone()),
+        )
+        .await;
+
+        for id in 0..4 {
+            let status = client
+                .predict_one(request_for("bar", id))
+                .await
+                .unwrap_err();
+            assert_eq!(status.message(), "model bar is broken");
+        }
+        assert_eq!(backend.calls(), 4);
+        assert_eq!(readiness.breaker_state("bar"), BreakerState::Open);
+
+        let status = client.predict_one(request_for("bar", 4)).await.unwrap_err();
+        assert_eq!(status.code(), Code::Unavailable);
+        assert_eq!(status.message(), "Circuit breaker for model bar is open");
+
+        let responses = collect_responses(
+            &mut client,
+            vec![request_for("bar", 5), request_for("foo", 6)],
+        )
+        .await;
+        assert_eq!(
+            responses[0].error(),
+            Some(Error {
+                code: Code::Unavailable as i32,
+                message: "Circuit breaker for model bar is open".to_string(),
+            })
+        );
+        assert_eq!(responses[1].error(), None);
+        assert_eq!(backend.calls(), 5);
+        assert_eq!(readiness.breaker_state("foo"), BreakerState::Closed);
+    }
+
+    // 测试替换模型表后，新的请求使用新的模型配置
+    #[tokio::test]
+    async fn test_predict_after_registry_update() {
+        let backend = Arc::new(FakeBackend::new());
+        let service = ImagePredictionService::new(
+            HashMap::from([("foo".to_string(), model_named("foo"))]),
+            backend,
+        );
+        let registry = service.registry.clone();
+        let mut client = start_service(service).await;
+
+        client.predict_one(request_for("foo", 1)).await.unwrap();
+
+        let baz = Model {
+            version: 2,
+            ..model_named("baz")
+        };
+        registry.update(HashMap::from([("baz".to_string(), baz.clone())]));
+
+        let status = client.predict_one(request_for("foo", 2)).await.unwrap_err();
+        assert_eq!(status.code(), Code::InvalidArgument);
+
+        let resp = client
+            .predict_one(request_for("baz", 3))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(resp.vector(), FakeBackend::expected(&baz, &[1, 2, 3]));
+    }
+
+    // 测试重复提交的图片使用缓存的结果，模型的版本变化后重新调用推理服务
+    #[tokio::test]
+    async fn test_predict_uses_cache() {
+        let backend = Arc::new(FakeBackend::new());
+        let service = ImagePredictionService::new(
+            HashMap::from([("cached".to_string(), model_named("cached"))]),
+            backend.clone(),
+        )
+        .with_cache(Arc::new(EmbeddingCache::new(&CacheOptions::default())));
+        let registry = service.registry.clone();
+        let mut client = start_service(service).await;
+
+        let first = client
+            .predict_one(request_for("cached", 1))
+            .await
+            .unwrap()
+            .into_inner();
+        let requests = (2..6).map(|id| request_for("cached", id)).collect();
+        let responses = collect_responses(&mut client, requests).await;
+        assert_eq!(responses.len(), 4);
+        for resp in responses {
+            assert_eq!(resp.vector(), first.vector());
+        }
+        assert_eq!(backend.calls(), 1);
+
+        let text = metrics().encode();
+        assert!(
+            text.contains(r#"image_prediction_cache_lookups_total{model="cached",result="hit"} 4"#)
+        );
+        assert!(text
+            .contains(r#"image_prediction_cache_lookups_total{model="cached",result="miss"} 1"#));
+
+        let v2 = Model {
+            version: 2,
+            ..model_named("cached")
+        };
+        registry.update(HashMap::from([("cached".to_string(), v2.clone())]));
+        let resp = client
+            .predict_one(request_for("cached", 6))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(resp.vector(), FakeBackend::expected(&v2, &[1, 2, 3]));
+        assert_eq!(backend.calls(), 2);
+    }
+
+    // 测试多个流同时发送同一张图片时只调用一次推理服务，所有请求得到相同的结果
+    #[tokio::test]
+    async fn test_predict_coalesces_identical_requests() {
+        let backend = Arc::new(FakeBackend::new().with_delay(Duration::from_millis(200)));
+        let service = ImagePredictionService::new(
+            HashMap::from([("coalesced".to_string(), model_named("coalesced"))]),
+            backend.clone(),
+        )
+        .with_coalescing();
+        let client = start_service(service).await;
+
+        let streams: Vec<_> = (0..4)
+            .map(|stream| {
+                let mut client = client.clone();
+                tokio::spawn(async move {
+                    let requests = (0..3).map(|id| request_for("coalesced", stream * 3 + id));
+                    collect_responses(&mut client, requests.collect()).await
+                })
+            })
+            .collect();
+        let expected = FakeBackend::expected(&model_named("coalesced"), &[1, 2, 3]);
+        for stream in streams {
+            let responses = stream.await.unwrap();
+            assert_eq!(responses.len(), 3);
+            for resp in responses {
+                assert_eq!(resp.vector(), expected);
             }
-            .clone();
-
-            // clone the data before the async block
-            let tf_serving_url = Arc::clone(&self.tf_serving_url);
-
-            task::spawn(async move {
-                // record start time
-                let start_time = Instant::now(); // 记录开始时间
-                                                 // Get the image data from the image request
-                let image_data = image_request.image;
-
-                // Get the id from the image request
-                let res_id = image_request.id;
-
-                // Encode image data with base64
-                let img_base64 = URL_SAFE.encode_to_string(&image_data[..]);
-
-                // send prection request to tensorflow serving
-                let binding = req_model.version.to_string();
-                let img_vector = tf_predict(
-                    &tf_serving_url,
-                    &req_model.name,
-                    &binding,
-                    &req_model.input_name,
-                    &img_base64,
+        }
+        assert_eq!(backend.calls(), 1);
+        let text = metrics().encode();
+        assert!(text.contains(r#"image_prediction_coalesced_total{model="coalesced"} 11"#));
+    }
+
+    // 测试不合法的图片作为该id的INVALID_ARGUMENT错误返回，不调用推理服务，模型可以有自己的限制
+    #[tokio::test]
+    async fn test_predict_rejects_invalid_images() {
+        let small = Model {
+            validation: Some(ValidationOptions {
+                max_width: 16,
+                ..ValidationOptions::default()
+            }),
+            ..model_named("validated_small")
+        };
+        let models = HashMap::from([
+            ("validated".to_string(), model_named("validated")),
+            ("validated_small".to_string(), small),
+        ]);
+        let backend = Arc::new(FakeBackend::new());
+        let service = ImagePredictionService::new(models, backend.clone())
+            .with_validation(ValidationOptions::default());
+        let mut client = start_service(service).await;
+
+        let png = encode_image(32, 8, ImageOutputFormat::Png);
+        let image = |model: &str, id: i32, image: &[u8]| ImagePredictionRequest {
+            image: image.to_vec(),
+            model: model.to_string(),
+            id,
+        };
+        let requests = vec![
+            image("validated", 1, &png),
+            image("validated", 2, b"not an image"),
+            image("validated", 3, &png[..png.len() - 10]),
+            image("validated_small", 4, &png),
+        ];
+        let responses = collect_responses(&mut client, requests).await;
+        assert_eq!(
+            responses[0].vector(),
+            FakeBackend::expected(&model_named("validated"), &png)
+        );
+        let invalid = |message: &str| {
+            Some(Error {
+                code: Code::InvalidArgument as i32,
+                message: message.to_string(),
+            })
+        };
+        assert_eq!(
+            responses[1].error(),
+            invalid("Unrecognized image format, expected JPEG, PNG, GIF, WebP, BMP or TIFF")
+        );
+        assert_eq!(responses[2].error(), invalid("Truncated PNG image"));
+        assert_eq!(
+            responses[3].error(),
+            invalid("Image is 32x8 pixels, larger than the limit of 16x10000")
+        );
+        assert_eq!(backend.calls(), 1);
+
+        let status = client
+            .predict_one(image("validated", 5, b""))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), Code::InvalidArgument);
+        assert_eq!(status.message(), "Empty image");
+
+        let text = metrics().encode();
+        assert!(text.contains(
+            r#"image_prediction_failures_total{code="InvalidArgument",model="validated"} 3"#
+        ));
+    }
+
+    // 测试模型无法解码的格式在转发之前被转换，可以解码的格式原样转发
+    #[tokio::test]
+    async fn test_predict_transcodes_images() {
+        let model = Model {
+            transcode: Some(TranscodeOptions::default()),
+            ..model_named("transcoded")
+        };
+        let models = HashMap::from([("transcoded".to_string(), model.clone())]);
+        let backend = Arc::new(FakeBackend::new());
+        let service = ImagePredictionService::new(models, backend.clone())
+            .with_validation(ValidationOptions::default());
+        let mut client = start_service(service).await;
+
+        let bmp = encode_image(6, 4, ImageOutputFormat::Bmp);
+        let png = encode_image(6, 4, ImageOutputFormat::Png);
+        let requests = [(1, &bmp), (2, &png)]
+            .into_iter()
+            .map(|(id, image)| ImagePredictionRequest {
+                image: image.clone(),
+                model: "transcoded".to_string(),
+                id,
+            })
+            .collect();
+        let responses = collect_responses(&mut client, requests).await;
+        let jpeg = transcode(
+            &bmp,
+            ImageFormat::Bmp,
+            &TranscodeOptions::default(),
+            Limits::default(),
+        )
+        .unwrap();
+        assert_eq!(responses[0].vector(), FakeBackend::expected(&model, &jpeg));
+        assert_eq!(responses[1].vector(), FakeBackend::expected(&model, &png));
+
+        let text = metrics().encode();
+        assert!(text.contains(
+            r#"image_prediction_latency_seconds_count{model="transcoded",stage="transcode"} 1"#
+        ));
+    }
+
+    // 测试每张图片的结果和流的数量被记录到指标中
+    #[tokio::test]
+    async fn test_predict_records_metrics() {
+        let models = HashMap::from([
+            ("metered".to_string(), model_named("metered")),
+            ("metered_broken".to_string(), model_named("metered_broken")),
+        ]);
+        let backend = Arc::new(FakeBackend::new().failing("metered_broken"));
+        let mut client = start_service(ImagePredictionService::new(models, backend)).await;
+
+        let requests = vec![
+            request_for("metered", 1),
+            request_for("metered", 2),
+            request_for("metered_broken", 3),
+        ];
+        let responses = collect_responses(&mut client, requests).await;
+        assert_eq!(responses.len(), 3);
+
+        let text = metrics().encode();
+        assert!(text.contains(r#"image_prediction_requests_total{model="metered"} 2"#));
+        assert!(text.contains(r#"image_prediction_successes_total{model="metered"} 2"#));
+        assert!(text.contains(
+            r#"image_prediction_failures_total{code="Unavailable",model="metered_broken"} 1"#
+        ));
+        assert!(text.contains(r#"image_prediction_in_flight{model="metered"} 0"#));
+        assert!(text.contains(
+            r#"image_prediction_latency_seconds_count{model="metered",stage="total"} 2"#
+        ));
+        assert!(text.contains(r#"image_prediction_streams_total{rpc="Predict"}"#));
+    }
+
+    // 测试请求中的traceparent经过每张图片的任务和批处理队列传递到发往TensorFlow Serving的HTTP请求头
+    #[tokio::test]
+    async fn test_trace_context_propagated_to_tf_serving() {
+        let trace_id = "0af7651916cd43dd8448eb211c80319c";
+        let traced = |path: &str, calls: usize| {
+            mock("POST", path)
+                .match_header(
+                    "traceparent",
+                    Matcher::Regex(format!("^00-{}-[0-9a-f]{{16}}-01$", trace_id)),
                 )
+                .with_status(200)
+                .with_header("content-type", "application/json")
+                .with_body(r#"{"predictions": [[0.1, 0.2]]}"#)
+                .expect(calls)
+                .create()
+        };
+        let m = traced("/models/traced/versions/1:predict", 3);
+        let batched = traced("/models/traced_batched/versions/1:predict", 1);
+
+        let models = HashMap::from([
+            ("traced".to_string(), model_named("traced")),
+            (
+                "traced_batched".to_string(),
+                Model {
+                    batch: Some(BatchOptions::default()),
+                    ..model_named("traced_batched")
+                },
+            ),
+        ]);
+        let mut client = start_service(ImagePredictionService::new(
+            models,
+            test_client(&mockito::server_url()),
+        ))
+        .await;
+        let traceparent = format!("00-{}-b7ad6b7169203331-01", trace_id);
+
+        let requests = vec![request_for("traced", 1), request_for("traced", 2)];
+        let mut request = Request::new(tokio_stream::iter(requests));
+        request
+            .metadata_mut()
+            .insert("traceparent", traceparent.parse().unwrap());
+        let responses: Vec<ImageVectorResponse> = client
+            .predict(request)
+            .await
+            .unwrap()
+            .into_inner()
+            .map(|r| r.unwrap())
+            .collect()
+            .await;
+        assert!(responses.iter().all(|r| r.error().is_none()));
+
+        let mut request = Request::new(request_for("traced", 3));
+        request
+            .metadata_mut()
+            .insert("traceparent", traceparent.parse().unwrap());
+        client.predict_one(request).await.unwrap();
+
+        let mut request = Request::new(request_for("traced_batched", 4));
+        request
+            .metadata_mut()
+            .insert("traceparent", traceparent.parse().unwrap());
+        client.predict_one(request).await.unwrap();
+
+        m.assert();
+        batched.assert();
+    }
+
+    fn concurrency(global: usize, per_stream: usize, shed_load: bool) -> ConcurrencyOptions {
+        ConcurrencyOptions {
+            max_in_flight: global,
+            max_in_flight_per_model: 0,
+            max_in_flight_per_stream: per_stream,
+            shed_load,
+        }
+    }
+
+    fn result_codes(responses: &[ImageVectorResponse]) -> Vec<i32> {
+        responses
+            .iter()
+            .map(|r| r.error().as_ref().map_or(Code::Ok as i32, |e| e.code))
+            .collect()
+    }
+
+    // 测试全局和每个模型的并发上限：同时进行的推理调用不超过上限，所有图片最终都完成
+    #[tokio::test]
+    async fn test_concurrency_limits_hold() {
+        let backend = Arc::new(FakeBackend::new().with_delay(Duration::from_millis(20)));
+        let models = HashMap::from([("foo".to_string(), model_named("foo"))]);
+        let mut client = start_service(
+            ImagePredictionService::new(models, backend.clone())
+                .with_concurrency(concurrency(3, 0, false)),
+        )
+        .await;
+
+        let requests = (0..20).map(|id| request_for("foo", id)).collect();
+        let responses = collect_responses(&mut client, requests).await;
+        assert_eq!(result_codes(&responses), vec![Code::Ok as i32; 20]);
+        assert!(backend.max_in_flight() <= 3, "{}", backend.max_in_flight());
+        assert!(backend.max_in_flight() > 1, "{}", backend.max_in_flight());
+
+        // 模型配置中的max_in_flight比全局上限更严格
+        let backend = Arc::new(FakeBackend::new().with_delay(Duration::from_millis(20)));
+        let bar = Model {
+            max_in_flight: Some(2),
+            ..model_named("bar")
+        };
+        let mut client = start_service(
+            ImagePredictionService::new(HashMap::from([("bar".to_string(), bar)]), backend.clone())
+                .with_concurrency(concurrency(8, 0, false)),
+        )
+        .await;
+        let requests = (0..10).map(|id| request_for("bar", id)).collect();
+        let responses = collect_responses(&mut client, requests).await;
+        assert_eq!(result_codes(&responses), vec![Code::Ok as i32; 10]);
+        assert!(backend.max_in_flight() <= 2, "{}", backend.max_in_flight());
+    }
+
+    // 测试每个流的并发上限：达到上限后暂停读取流中的图片，其他流不受影响
+    #[tokio::test]
+    async fn test_stream_limit_holds() {
+        let backend = Arc::new(FakeBackend::new().with_delay(Duration::from_millis(20)));
+        let models = HashMap::from([("foo".to_string(), model_named("foo"))]);
+        let mut client = start_service(
+            ImagePredictionService::new(models, backend.clone())
+                .with_concurrency(concurrency(0, 2, false)),
+        )
+        .await;
+
+        let requests = (0..10).map(|id| request_for("foo", id)).collect();
+        let responses = collect_responses(&mut client, requests).await;
+        assert_eq!(result_codes(&responses), vec![Code::Ok as i32; 10]);
+        assert!(backend.max_in_flight() <= 2, "{}", backend.max_in_flight());
+
+        let request = ImageBatchRequest {
+            requests: (0..10).map(|id| request_for("foo", id)).collect(),
+        };
+        let responses: Vec<ImageVectorResponse> = client
+            .predict_batch(request)
+            .await
+            .unwrap()
+            .into_inner()
+            .map(|r| r.unwrap())
+            .collect()
+            .await;
+        assert_eq!(responses.len(), 10);
+        assert!(backend.max_in_flight() <= 2, "{}", backend.max_in_flight());
+    }
+
+    // 测试开启shed_load后，超过上限的图片立即返回RESOURCE_EXHAUSTED
+    #[tokio::test]
+    async fn test_shed_load_returns_resource_exhausted() {
+        let backend = Arc::new(FakeBackend::new().with_delay(Duration::from_millis(200)));
+        let models = HashMap::from([("foo".to_string(), model_named("foo"))]);
+        let mut client = start_service(
+            ImagePredictionService::new(models, backend.clone())
+                .with_concurrency(concurrency(1, 0, true)),
+        )
+        .await;
+
+        let request = ImageBatchRequest {
+            requests: (0..5).map(|id| request_for("foo", id)).collect(),
+        };
+        let mut responses: Vec<ImageVectorResponse> = client
+            .predict_batch(request)
+            .await
+            .unwrap()
+            .into_inner()
+            .map(|r| r.unwrap())
+            .collect()
+            .await;
+        responses.sort_by_key(|r| r.id);
+
+        let mut codes = result_codes(&responses);
+        codes.sort();
+        assert_eq!(
+            codes,
+            vec![
+                Code::Ok as i32,
+                Code::ResourceExhausted as i32,
+                Code::ResourceExhausted as i32,
+                Code::ResourceExhausted as i32,
+                Code::ResourceExhausted as i32,
+            ]
+        );
+        assert_eq!(backend.calls(), 1);
+    }
+
+    // 测试在同一个双向流中交替发送请求和接收响应：客户端不结束发送也能收到之前图片的结果
+    #[tokio::test]
+    async fn test_predict_interleaves_requests_and_responses() {
+        let models = HashMap::from([("foo".to_string(), model_named("foo"))]);
+        let mut client = start_service(ImagePredictionService::new(
+            models,
+            Arc::new(FakeBackend::new()),
+        ))
+        .await;
+
+        let (requests, rx) = mpsc::channel(1);
+        let responses = client.predict(ReceiverStream::new(rx));
+        let mut responses = tokio::time::timeout(Duration::from_secs(5), responses)
+            .await
+            .expect("the response stream did not start before the client finished sending")
+            .unwrap()
+            .into_inner();
+
+        for id in 0..5 {
+            requests.send(request_for("foo", id)).await.unwrap();
+            let response = tokio::time::timeout(Duration::from_secs(5), responses.message())
                 .await
+                .expect("no response before the client finished sending")
+                .unwrap()
                 .unwrap();
+            assert_eq!(response.id, id);
+        }
 
-                let img_vector = img_vector.get(0).unwrap_or(&vec![]).clone();
+        // 客户端结束发送后，响应流在所有结果发送完后结束
+        drop(requests);
+        assert!(responses.message().await.unwrap().is_none());
+    }
 
-                let resp = ImageVectorResponse {
-                    vector: img_vector, // use img_vector if it's Ok, otherwise use a empty value
-                    id: res_id,
-                };
+    // 测试x-response-order为in-order时结果按请求到达的顺序返回，超时的图片返回DEADLINE_EXCEEDED
+    #[tokio::test]
+    async fn test_predict_in_order() {
+        // 长度为1的图片最慢，长度为2的图片超过重排的超时时间
+        let backend = Arc::new(
+            FakeBackend::new()
+                .with_image_delay(1, Duration::from_millis(100))
+                .with_image_delay(2, Duration::from_millis(1000)),
+        );
+        let models = HashMap::from([("foo".to_string(), model_named("foo"))]);
+        let service = ImagePredictionService::new(models, backend).with_reorder(ReorderOptions {
+            max_buffered: 4,
+            straggler_timeout_ms: 300,
+        });
+        let mut client = start_service(service).await;
 
-                let elapsed_time = Instant::now().duration_since(start_time).as_secs_f32();
-                debug!(
-                    "recv image_data len: {}\t order id : {} \t encode base64 len: {} \t executed in: {:.2} s",
-                    image_data.len(),
-                    res_id,
-                    img_base64.len(),
-                    elapsed_time,
-                );
+        let requests: Vec<ImagePredictionRequest> = [1, 3, 2, 4, 5, 1, 6, 7]
+            .iter()
+            .enumerate()
+            .map(|(id, len)| ImagePredictionRequest {
+                image: vec![0; *len],
+                model: "foo".to_string(),
+                id: id as i32,
+            })
+            .collect();
+        let mut request = Request::new(tokio_stream::iter(requests));
+        request
+            .metadata_mut()
+            .insert("x-response-order", "in-order".parse().unwrap());
+        let responses: Vec<ImageVectorResponse> = client
+            .predict(request)
+            .await
+            .unwrap()
+            .into_inner()
+            .map(|r| r.unwrap())
+            .collect()
+            .await;
 
-                if let Err(err) = tx.send(Ok(resp)).await {
-                    error!("Error sending response: {:?}", err);
-                }
-            });
+        let ids: Vec<i32> = responses.iter().map(|r| r.id).collect();
+        assert_eq!(ids, (0..8).collect::<Vec<_>>());
+        let mut codes = vec![Code::Ok as i32; 8];
+        codes[2] = Code::DeadlineExceeded as i32;
+        assert_eq!(result_codes(&responses), codes);
+
+        // 未知的顺序直接返回INVALID_ARGUMENT
+        let mut request = Request::new(ImageBatchRequest { requests: vec![] });
+        request
+            .metadata_mut()
+            .insert("x-response-order", "random".parse().unwrap());
+        let status = client.predict_batch(request).await.unwrap_err();
+        assert_eq!(status.code(), Code::InvalidArgument);
+    }
+
+    // 测试请求中的grpc-timeout：超过截止时间的图片返回DEADLINE_EXCEEDED，不再等待推理服务
+    #[tokio::test]
+    async fn test_predict_deadline_exceeded() {
+        let backend = Arc::new(FakeBackend::new().with_image_delay(2, Duration::from_secs(5)));
+        let models = HashMap::from([
+            ("foo".to_string(), model_named("foo")),
+            ("deadline_one".to_string(), model_named("deadline_one")),
+        ]);
+        let mut client = start_service(ImagePredictionService::new(models, backend.clone())).await;
+
+        let requests = vec![
+            ImagePredictionRequest {
+                image: vec![0; 1],
+                model: "foo".to_string(),
+                id: 1,
+            },
+            ImagePredictionRequest {
+                image: vec![0; 2],
+                model: "foo".to_string(),
+                id: 2,
+            },
+        ];
+        let mut request = Request::new(tokio_stream::iter(requests));
+        request.set_timeout(Duration::from_millis(200));
+        let start = Instant::now();
+        let mut responses: Vec<ImageVectorResponse> = client
+            .predict(request)
+            .await
+            .unwrap()
+            .into_inner()
+            .map(|r| r.unwrap())
+            .collect()
+            .await;
+        responses.sort_by_key(|r| r.id);
+
+        assert!(start.elapsed() < Duration::from_secs(2));
+        assert_eq!(
+            result_codes(&responses),
+            vec![Code::Ok as i32, Code::DeadlineExceeded as i32]
+        );
+        assert_eq!(
+            responses[1].error(),
+            Some(Error {
+                code: Code::DeadlineExceeded as i32,
+                message: "Deadline exceeded before the prediction of id 2 finished".to_string(),
+            })
+        );
+        // 超时的调用被取消
+        assert_eq!(backend.in_flight(), 0);
+
+        let mut request = Request::new(ImagePredictionRequest {
+            image: vec![0; 2],
+            model: "deadline_one".to_string(),
+            id: 3,
+        });
+        request.set_timeout(Duration::from_millis(100));
+        let status = client.predict_one(request).await.unwrap_err();
+        // 一元调用在tonic的超时之前返回DEADLINE_EXCEEDED，推理调用同样被取消
+        assert_eq!(status.code(), Code::DeadlineExceeded);
+        assert_eq!(
+            status.message(),
+            "Deadline exceeded before the prediction of id 3 finished"
+        );
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(backend.in_flight(), 0);
+        assert!(metrics().encode().contains(
+            r#"image_prediction_failures_total{code="DeadlineExceeded",model="deadline_one"} 1"#
+        ));
+    }
+
+    // 测试客户端断开后正在进行的推理调用被取消，并记录为CANCELLED
+    #[tokio::test]
+    async fn test_predict_cancelled_by_client() {
+        let backend = Arc::new(FakeBackend::new().with_delay(Duration::from_secs(5)));
+        let models = HashMap::from([("cancelled".to_string(), model_named("cancelled"))]);
+        let mut client = start_service(ImagePredictionService::new(models, backend.clone())).await;
+
+        let (requests, rx) = mpsc::channel(4);
+        let responses = client
+            .predict(ReceiverStream::new(rx))
+            .await
+            .unwrap()
+            .into_inner();
+        for id in 0..3 {
+            requests.send(request_for("cancelled", id)).await.unwrap();
         }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(backend.in_flight(), 3);
 
-        // 返回带有响应结果的流式响应对象
-        Ok(Response::new(tokio_stream::wrappers::ReceiverStream::new(
-            rx,
-        )))
+        drop(responses);
+        drop(requests);
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert_eq!(backend.in_flight(), 0);
+        assert!(metrics()
+            .encode()
+            .contains(r#"image_prediction_failures_total{code="Cancelled",model="cancelled"} 3"#));
     }
 }
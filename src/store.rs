@@ -0,0 +1,632 @@
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+use tokio::task;
+
+use crate::cache::CacheKey;
+use crate::config::Model;
+
+// 每个文件开头的标识和格式版本
+const MAGIC: &[u8; 4] = b"IPSV";
+const FORMAT_VERSION: u32 = 1;
+// 标识、格式版本、向量长度和向量内容的SHA-256摘要
+const HEADER_LEN: usize = 4 + 4 + 4 + 32;
+// 写入中的文件，写完后重命名，启动时删除残留的临时文件
+const TMP_SUFFIX: &str = ".tmp";
+// 存储目录的标记文件，没有标记文件的非空目录不会被当作存储目录，避免误删其他文件
+const MARKER: &str = "VECTOR_STORE";
+// 超过上限后淘汰到上限的这个比例，避免每次写入都要淘汰
+const COMPACT_RATIO: f64 = 0.9;
+
+// 磁盘上的预测结果存储的参数，可以在config.yaml的store部分配置
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct StoreOptions {
+    pub enabled: bool,
+    // 存储目录，只能用于存放预测结果
+    pub path: String,
+    // 所有文件最多占用的字节数，超过后淘汰最久没有使用的结果
+    pub max_bytes: u64,
+}
+
+impl Default for StoreOptions {
+    fn default() -> Self {
+        StoreOptions {
+            enabled: false,
+            path: "vector_store".to_string(),
+            max_bytes: 10 * 1024 * 1024 * 1024,
+        }
+    }
+}
+
+// 一个预测结果文件的大小和最近一次使用的时间
+struct Entry {
+    size: u64,
+    last_used: SystemTime,
+}
+
+#[derive(Default)]
+struct Index {
+    entries: HashMap<CacheKey, Entry>,
+    total_bytes: u64,
+}
+
+impl Index {
+    fn insert(&mut self, key: CacheKey, entry: Entry) {
+        self.total_bytes += entry.size;
+        if let Some(old) = self.entries.insert(key, entry) {
+            self.total_bytes -= old.size;
+        }
+    }
+
+    fn remove(&mut self, key: &CacheKey) {
+        if let Some(old) = self.entries.remove(key) {
+            self.total_bytes -= old.size;
+        }
+    }
+}
+
+// 启动时扫描存储目录的结果
+#[derive(Debug, Default, PartialEq)]
+pub struct ScanSummary {
+    pub entries: usize,
+    pub bytes: u64,
+    // 被删除的损坏文件和残留的临时文件
+    pub removed: usize,
+}
+
+// 以内容为地址的预测结果存储，每个结果一个文件：<模型>/<版本>/<摘要前两位>/<摘要>
+// 服务重启后仍然可以使用之前的预测结果，文件的完整性在启动和读取时检查
+pub struct VectorStore {
+    root: PathBuf,
+    max_bytes: u64,
+    index: Mutex<Index>,
+}
+
+impl VectorStore {
+    // 打开存储目录，删除损坏的文件并重建索引，超过上限时淘汰旧的结果
+    pub fn open(options: &StoreOptions) -> io::Result<(Self, ScanSummary)> {
+        let root = PathBuf::from(&options.path);
+        fs::create_dir_all(&root)?;
+        let marker = root.join(MARKER);
+        if !marker.exists() {
+            if fs::read_dir(&root)?.next().is_some() {
+                return Err(io::Error::other(format!(
+                    "{} is not empty and is not a vector store",
+                    options.path
+                )));
+            }
+            File::create(&marker)?;
+        }
+
+        let store = VectorStore {
+            root,
+            max_bytes: options.max_bytes,
+            index: Mutex::new(Index::default()),
+        };
+        let mut summary = ScanSummary::default();
+        store.scan(&mut summary)?;
+        {
+            let index = store.index.lock().unwrap();
+            summary.entries = index.entries.len();
+            summary.bytes = index.total_bytes;
+        }
+        store.compact();
+        Ok((store, summary))
+    }
+
+    fn path(&self, key: &CacheKey) -> PathBuf {
+        let digest = to_hex(&key.digest);
+        self.root
+            .join(encode_name(&key.model_name))
+            .join(key.version.to_string())
+            .join(&digest[..2])
+            .join(digest)
+    }
+
+    // 查找预测结果，损坏的文件被删除并视为没有找到
+    pub async fn get(self: &Arc<Self>, key: &CacheKey) -> Option<Vec<f32>> {
+        if !self.index.lock().unwrap().entries.contains_key(key) {
+            return None;
+        }
+        let store = Arc::clone(self);
+        let key = key.clone();
+        task::spawn_blocking(move || store.read(&key))
+            .await
+            .unwrap_or(None)
+    }
+
+    fn read(&self, key: &CacheKey) -> Option<Vec<f32>> {
+        let path = self.path(key);
+        let result = match fs::read(&path) {
+            Ok(bytes) => decode(&bytes),
+            // 刚被淘汰的结果
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                self.index.lock().unwrap().remove(key);
+                return None;
+            }
+            Err(e) => Err(e.to_string()),
+        };
+        match result {
+            Ok(values) => {
+                if let Some(entry) = self.index.lock().unwrap().entries.get_mut(key) {
+                    entry.last_used = SystemTime::now();
+                }
+                Some(values)
+            }
+            Err(e) => {
+                warn!("removing corrupted vector {}: {}", path.display(), e);
+                self.index.lock().unwrap().remove(key);
+                let _ = fs::remove_file(&path);
+                None
+            }
+        }
+    }
+
+    // 保存预测结果，先写入临时文件再重命名，写入一半时退出不会留下损坏的文件
+    pub async fn put(self: &Arc<Self>, key: CacheKey, values: Vec<f32>) {
+        let store = Arc::clone(self);
+        let written = task::spawn_blocking(move || {
+            let path = store.path(&key);
+            store.write(&path, &values).map_err(|e| (path, e))?;
+            store.index.lock().unwrap().insert(
+                key,
+                Entry {
+                    size: (HEADER_LEN + values.len() * 4) as u64,
+                    last_used: SystemTime::now(),
+                },
+            );
+            store.compact();
+            Ok::<(), (PathBuf, io::Error)>(())
+        })
+        .await;
+        if let Ok(Err((path, e))) = written {
+            warn!("cannot write vector {}: {}", path.display(), e);
+        }
+    }
+
+    fn write(&self, path: &Path, values: &[f32]) -> io::Result<()> {
+        fs::create_dir_all(path.parent().unwrap())?;
+        let tmp = path.with_extension(format!("{}{}", fastrand::u32(..), TMP_SUFFIX));
+        let written = File::create(&tmp).and_then(|mut file| file.write_all(&encode(values)));
+        match written.and_then(|_| fs::rename(&tmp, path)) {
+            Ok(()) => Ok(()),
+            Err(e) => {
+                let _ = fs::remove_file(&tmp);
+                Err(e)
+            }
+        }
+    }
+
+    // 超过上限时淘汰最久没有使用的结果，直到总大小低于上限的COMPACT_RATIO
+    fn compact(&self) {
+        let victims: Vec<CacheKey> = {
+            let mut index = self.index.lock().unwrap();
+            if index.total_bytes <= self.max_bytes {
+                return;
+            }
+            let target = (self.max_bytes as f64 * COMPACT_RATIO) as u64;
+            let mut by_age: Vec<(&CacheKey, &Entry)> = index.entries.iter().collect();
+            by_age.sort_by_key(|(_, entry)| entry.last_used);
+            let mut total = index.total_bytes;
+            let victims: Vec<CacheKey> = by_age
+                .into_iter()
+                .take_while(|(_, entry)| {
+                    let over = total > target;
+                    total -= entry.size;
+                    over
+                })
+                .map(|(key, _)| key.clone())
+                .collect();
+            for key in &victims {
+                index.remove(key);
+            }
+            victims
+        };
+        info!(
+            "vector store {} is over {} bytes, evicting {} vectors",
+            self.root.display(),
+            self.max_bytes,
+            victims.len()
+        );
+        for key in &victims {
+            let _ = fs::remove_file(self.path(key));
+        }
+    }
+
+    // 删除配置中已经没有的模型和版本的结果，启动时和配置热加载后调用
+    pub async fn retain(self: &Arc<Self>, models: &[Model]) {
+        let current: HashMap<String, u32> = models
+            .iter()
+            .map(|model| (model.name.clone(), model.version))
+            .collect();
+        let store = Arc::clone(self);
+        let removed = task::spawn_blocking(move || store.retain_versions(&current)).await;
+        if let Ok(Err(e)) = removed {
+            warn!("cannot remove stale vectors: {}", e);
+        }
+    }
+
+    fn retain_versions(&self, current: &HashMap<String, u32>) -> io::Result<()> {
+        self.index
+            .lock()
+            .unwrap()
+            .entries
+            .retain(|key, _| current.get(&key.model_name) == Some(&key.version));
+        self.recount();
+
+        for model_dir in fs::read_dir(&self.root)? {
+            let model_dir = model_dir?.path();
+            let name = match file_name(&model_dir).and_then(decode_name) {
+                Some(name) if model_dir.is_dir() => name,
+                _ => continue,
+            };
+            let version = match current.get(&name) {
+                Some(version) => version.to_string(),
+                None => {
+                    info!("removing vectors of model {}", name);
+                    fs::remove_dir_all(&model_dir)?;
+                    continue;
+                }
+            };
+            for version_dir in fs::read_dir(&model_dir)? {
+                let version_dir = version_dir?.path();
+                match file_name(&version_dir) {
+                    Some(v) if v != version && v.parse::<u32>().is_ok() => {
+                        info!("removing vectors of model {} version {}", name, v);
+                        fs::remove_dir_all(&version_dir)?;
+                    }
+                    _ => {}
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn recount(&self) {
+        let mut index = self.index.lock().unwrap();
+        index.total_bytes = index.entries.values().map(|entry| entry.size).sum();
+    }
+
+    // 遍历<模型>/<版本>/<摘要前两位>/<摘要>，检查每个文件的头部和长度
+    // 不符合这个结构的文件不属于存储，只跳过不删除
+    fn scan(&self, summary: &mut ScanSummary) -> io::Result<()> {
+        let mut index = self.index.lock().unwrap();
+        for model_dir in subdirs(&self.root)? {
+            let Some(model_name) = file_name(&model_dir).and_then(decode_name) else {
+                continue;
+            };
+            for version_dir in subdirs(&model_dir)? {
+                let Some(version) = file_name(&version_dir).and_then(|v| v.parse().ok()) else {
+                    continue;
+                };
+                for shard_dir in subdirs(&version_dir)? {
+                    for file in fs::read_dir(&shard_dir)? {
+                        let path = file?.path();
+                        let Some(name) = file_name(&path) else {
+                            continue;
+                        };
+                        if name.ends_with(TMP_SUFFIX) {
+                            fs::remove_file(&path)?;
+                            summary.removed += 1;
+                            continue;
+                        }
+                        let Some(digest) = from_hex(name) else {
+                            debug!("skipping unknown file {}", path.display());
+                            continue;
+                        };
+                        match check_file(&path) {
+                            Ok(entry) => {
+                                let key = CacheKey {
+                                    digest,
+                                    model_name: model_name.clone(),
+                                    version,
+                                };
+                                index.insert(key, entry);
+                            }
+                            Err(e) => {
+                                warn!("removing corrupted vector {}: {}", path.display(), e);
+                                fs::remove_file(&path)?;
+                                summary.removed += 1;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+// 头部的格式正确并且文件长度与向量长度一致，内容的摘要在读取时检查
+fn check_file(path: &Path) -> Result<Entry, String> {
+    let mut file = File::open(path).map_err(|e| e.to_string())?;
+    let metadata = file.metadata().map_err(|e| e.to_string())?;
+    let mut header = [0; HEADER_LEN];
+    file.read_exact(&mut header)
+        .map_err(|_| "truncated header".to_string())?;
+    let len = check_header(&header)?;
+    if metadata.len() != (HEADER_LEN + len * 4) as u64 {
+        return Err(format!(
+            "expected {} bytes, found {}",
+            HEADER_LEN + len * 4,
+            metadata.len()
+        ));
+    }
+    Ok(Entry {
+        size: metadata.len(),
+        last_used: metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH),
+    })
+}
+
+// 返回头部中的向量长度
+fn check_header(header: &[u8]) -> Result<usize, String> {
+    if &header[..4] != MAGIC {
+        return Err("bad magic".to_string());
+    }
+    let version = u32::from_le_bytes(header[4..8].try_into().unwrap());
+    if version != FORMAT_VERSION {
+        return Err(format!("unsupported format version {}", version));
+    }
+    Ok(u32::from_le_bytes(header[8..12].try_into().unwrap()) as usize)
+}
+
+fn encode(values: &[f32]) -> Vec<u8> {
+    let payload: Vec<u8> = values.iter().flat_map(|v| v.to_le_bytes()).collect();
+    let mut bytes = Vec::with_capacity(HEADER_LEN + payload.len());
+    bytes.extend_from_slice(MAGIC);
+    bytes.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+    bytes.extend_from_slice(&(values.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(&Sha256::digest(&payload));
+    bytes.extend_from_slice(&payload);
+    bytes
+}
+
+fn decode(bytes: &[u8]) -> Result<Vec<f32>, String> {
+    if bytes.len() < HEADER_LEN {
+        return Err("truncated header".to_string());
+    }
+    let len = check_header(bytes)?;
+    let payload = &bytes[HEADER_LEN..];
+    if payload.len() != len * 4 {
+        return Err(format!(
+            "expected {} bytes, found {}",
+            HEADER_LEN + len * 4,
+            bytes.len()
+        ));
+    }
+    if Sha256::digest(payload).as_slice() != &bytes[12..HEADER_LEN] {
+        return Err("checksum mismatch".to_string());
+    }
+    Ok(payload
+        .chunks_exact(4)
+        .map(|chunk| f32::from_le_bytes(chunk.try_into().unwrap()))
+        .collect())
+}
+
+fn subdirs(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut dirs = vec![];
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            dirs.push(path);
+        }
+    }
+    Ok(dirs)
+}
+
+fn file_name(path: &Path) -> Option<&str> {
+    path.file_name()?.to_str()
+}
+
+// 模型名称作为目录名，字母、数字、_和-以外的字节编码为%XX
+fn encode_name(name: &str) -> String {
+    name.bytes()
+        .map(|b| match b {
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'_' | b'-' => (b as char).to_string(),
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+fn decode_name(encoded: &str) -> Option<String> {
+    let mut bytes = vec![];
+    let mut rest = encoded.as_bytes();
+    while let Some((&b, tail)) = rest.split_first() {
+        match b {
+            b'%' if tail.len() >= 2 => {
+                let hex = std::str::from_utf8(&tail[..2]).ok()?;
+                bytes.push(u8::from_str_radix(hex, 16).ok()?);
+                rest = &tail[2..];
+            }
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'_' | b'-' => {
+                bytes.push(b);
+                rest = tail;
+            }
+            _ => return None,
+        }
+    }
+    String::from_utf8(bytes).ok()
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn from_hex(hex: &str) -> Option<[u8; 32]> {
+    if hex.len() != 64 {
+        return None;
+    }
+    let mut digest = [0; 32];
+    for (i, byte) in digest.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(hex.get(i * 2..i * 2 + 2)?, 16).ok()?;
+    }
+    Some(digest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn test_model(name: &str, version: u32) -> Model {
+        Model {
+            name: name.to_string(),
+            version,
+            input_name: "input".to_string(),
+            ..Model::default()
+        }
+    }
+
+    fn open(dir: &Path, max_bytes: u64) -> (Arc<VectorStore>, ScanSummary) {
+        let (store, summary) = VectorStore::open(&StoreOptions {
+            enabled: true,
+            path: dir.to_str().unwrap().to_string(),
+            max_bytes,
+        })
+        .unwrap();
+        (Arc::new(store), summary)
+    }
+
+    // 测试模型名称的编码可以还原，并且不会产生.或/
+    #[test]
+    fn test_encode_name() {
+        for name in ["illust2vec", "a.b/../c", "模型", "%"] {
+            let encoded = encode_name(name);
+            assert!(!encoded.contains('.') && !encoded.contains('/'));
+            assert_eq!(decode_name(&encoded).as_deref(), Some(name));
+        }
+        assert_eq!(decode_name("a.b"), None);
+    }
+
+    // 测试结果在重新打开存储后仍然可以读取
+    #[tokio::test]
+    async fn test_survives_restart() {
+        let dir = tempdir().unwrap();
+        let model = test_model("illust2vec", 1);
+        let key = CacheKey::new(&model, b"image");
+        {
+            let (store, _) = open(dir.path(), 1 << 20);
+            assert_eq!(store.get(&key).await, None);
+            store.put(key.clone(), vec![0.5, -1.0]).await;
+            assert_eq!(store.get(&key).await, Some(vec![0.5, -1.0]));
+        }
+
+        let (store, summary) = open(dir.path(), 1 << 20);
+        assert_eq!(
+            summary,
+            ScanSummary {
+                entries: 1,
+                bytes: (HEADER_LEN + 8) as u64,
+                removed: 0
+            }
+        );
+        assert_eq!(store.get(&key).await, Some(vec![0.5, -1.0]));
+    }
+
+    // 测试启动时删除长度不对的文件和残留的临时文件，读取时发现内容损坏的文件被删除
+    #[tokio::test]
+    async fn test_corruption_detection() {
+        let dir = tempdir().unwrap();
+        let model = test_model("foo", 1);
+        let truncated = CacheKey::new(&model, b"truncated");
+        let flipped = CacheKey::new(&model, b"flipped");
+        let healthy = CacheKey::new(&model, b"healthy");
+        let (store, _) = open(dir.path(), 1 << 20);
+        for key in [&truncated, &flipped, &healthy] {
+            store.put(key.clone(), vec![1.0, 2.0, 3.0]).await;
+        }
+        let truncated_path = store.path(&truncated);
+        let flipped_path = store.path(&flipped);
+        drop(store);
+
+        let bytes = fs::read(&truncated_path).unwrap();
+        fs::write(&truncated_path, &bytes[..bytes.len() - 1]).unwrap();
+        let mut bytes = fs::read(&flipped_path).unwrap();
+        *bytes.last_mut().unwrap() ^= 0xff;
+        fs::write(&flipped_path, bytes).unwrap();
+        fs::write(flipped_path.with_extension("1.tmp"), b"partial").unwrap();
+        let unknown = dir.path().join("foo").join("README");
+        fs::write(&unknown, b"not a vector").unwrap();
+
+        let (store, summary) = open(dir.path(), 1 << 20);
+        assert_eq!(summary.entries, 2);
+        assert_eq!(summary.removed, 2);
+        assert!(!truncated_path.exists());
+        assert!(unknown.exists());
+
+        assert_eq!(store.get(&flipped).await, None);
+        assert!(!flipped_path.exists());
+        assert_eq!(store.get(&healthy).await, Some(vec![1.0, 2.0, 3.0]));
+    }
+
+    // 测试超过上限后淘汰最久没有使用的结果
+    #[tokio::test]
+    async fn test_size_cap() {
+        let dir = tempdir().unwrap();
+        let model = test_model("foo", 1);
+        let size = (HEADER_LEN + 4 * 4) as u64;
+        let (store, _) = open(dir.path(), size * 10);
+        let keys: Vec<CacheKey> = (0..20u32)
+            .map(|i| CacheKey::new(&model, &i.to_le_bytes()))
+            .collect();
+
+        store.put(keys[0].clone(), vec![0.0; 4]).await;
+        for key in &keys[1..] {
+            // 第一个结果一直被使用，不会被淘汰
+            tokio::time::sleep(std::time::Duration::from_millis(2)).await;
+            store.get(&keys[0]).await.unwrap();
+            store.put(key.clone(), vec![0.0; 4]).await;
+        }
+
+        let total = store.index.lock().unwrap().total_bytes;
+        assert!(total <= size * 10, "total: {}", total);
+        assert!(store.get(&keys[0]).await.is_some());
+        assert!(store.get(&keys[1]).await.is_none());
+        assert!(!store.path(&keys[1]).exists());
+        assert!(store.get(&keys[19]).await.is_some());
+    }
+
+    // 测试删除配置中已经没有的模型和版本的结果
+    #[tokio::test]
+    async fn test_retain() {
+        let dir = tempdir().unwrap();
+        let v1 = test_model("foo", 1);
+        let v2 = test_model("foo", 2);
+        let bar = test_model("bar", 1);
+        let (store, _) = open(dir.path(), 1 << 20);
+        for model in [&v1, &v2, &bar] {
+            store.put(CacheKey::new(model, b"a"), vec![1.0]).await;
+        }
+
+        store.retain(std::slice::from_ref(&v2)).await;
+        assert_eq!(store.get(&CacheKey::new(&v1, b"a")).await, None);
+        assert_eq!(store.get(&CacheKey::new(&bar, b"a")).await, None);
+        assert_eq!(store.get(&CacheKey::new(&v2, b"a")).await, Some(vec![1.0]));
+        assert!(!dir.path().join("foo").join("1").exists());
+        assert!(!dir.path().join("bar").exists());
+        assert_eq!(
+            store.index.lock().unwrap().total_bytes,
+            (HEADER_LEN + 4) as u64
+        );
+    }
+
+    // 测试不会把其他非空目录当作存储目录
+    #[test]
+    fn test_refuses_foreign_directory() {
+        let dir = tempdir().unwrap();
+        fs::write(dir.path().join("important.txt"), b"data").unwrap();
+        let result = VectorStore::open(&StoreOptions {
+            enabled: true,
+            path: dir.path().to_str().unwrap().to_string(),
+            ..StoreOptions::default()
+        });
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,289 @@
+use opentelemetry::global::{self, BoxedTracer};
+use opentelemetry::propagation::{Extractor, Injector, TextMapPropagator};
+use opentelemetry::trace::{
+    Link, SpanKind, Status as SpanStatus, TraceContextExt, TraceError, Tracer,
+};
+use opentelemetry::{Context, KeyValue};
+use opentelemetry_http::HeaderInjector;
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use opentelemetry_sdk::trace::{self as sdktrace, TracerProvider};
+use opentelemetry_sdk::{runtime, Resource};
+use serde::{Deserialize, Serialize};
+use std::fmt::Display;
+use std::time::Duration;
+use tonic::metadata::{KeyRef, MetadataKey, MetadataMap, MetadataValue};
+
+// 链路追踪的参数，可以在config.yaml的tracing部分配置
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct TracingOptions {
+    // 是否通过OTLP导出span，关闭后仍然会向TensorFlow Serving传递traceparent
+    pub enabled: bool,
+    // OTLP/HTTP接收端的地址，span发送到{endpoint}/v1/traces
+    pub endpoint: String,
+    pub service_name: String,
+    // 一次导出请求的超时时间（毫秒）
+    pub export_timeout_ms: u64,
+}
+
+impl Default for TracingOptions {
+    fn default() -> Self {
+        TracingOptions {
+            enabled: false,
+            endpoint: "http://localhost:4318".to_string(),
+            service_name: "image-prediction-service".to_string(),
+            export_timeout_ms: 10_000,
+        }
+    }
+}
+
+// 所有span都使用同一个instrumentation名称
+const TRACER_NAME: &str = "image-prediction-service";
+
+// 创建通过OTLP/HTTP批量导出span的TracerProvider，需要在tokio运行时中调用
+pub fn new_provider(options: &TracingOptions) -> Result<TracerProvider, TraceError> {
+    let exporter = opentelemetry_otlp::new_exporter()
+        .http()
+        .with_http_client(reqwest::Client::new())
+        .with_endpoint(&options.endpoint)
+        .with_timeout(Duration::from_millis(options.export_timeout_ms))
+        .build_span_exporter()?;
+    let resource = Resource::new(vec![KeyValue::new(
+        "service.name",
+        options.service_name.clone(),
+    )]);
+
+    Ok(TracerProvider::builder()
+        .with_batch_exporter(exporter, runtime::Tokio)
+        .with_config(sdktrace::config().with_resource(resource))
+        .build())
+}
+
+// 把TracerProvider设置为全局的，之后创建的span都会被导出
+pub fn install(options: &TracingOptions) -> Result<(), TraceError> {
+    global::set_tracer_provider(new_provider(options)?);
+    Ok(())
+}
+
+// 退出前导出还没有发送的span
+pub fn shutdown() {
+    global::shutdown_tracer_provider();
+}
+
+pub fn tracer() -> BoxedTracer {
+    global::tracer(TRACER_NAME)
+}
+
+// 从gRPC请求的metadata中读取W3C traceparent，创建服务端的span作为本次RPC的上下文
+pub fn server_context(metadata: &MetadataMap, rpc: &'static str) -> Context {
+    let parent = TraceContextPropagator::new().extract(&MetadataExtractor(metadata));
+    let span = tracer()
+        .span_builder(format!("ImagePrediction/{}", rpc))
+        .with_kind(SpanKind::Server)
+        .with_attributes(vec![
+            KeyValue::new("rpc.system", "grpc"),
+            KeyValue::new("rpc.method", rpc),
+        ])
+        .start_with_context(&tracer(), &parent);
+    parent.with_span(span)
+}
+
+// 为流中的一张图片创建子span，图片在独立的任务中预测时也属于同一个trace
+pub fn image_context(parent: &Context, model_name: &str, id: i32, size: usize) -> Context {
+    let span = tracer()
+        .span_builder("predict_image")
+        .with_attributes(vec![
+            KeyValue::new("model", model_name.to_string()),
+            KeyValue::new("image.id", id as i64),
+            KeyValue::new("image.size", size as i64),
+        ])
+        .start_with_context(&tracer(), parent);
+    parent.with_span(span)
+}
+
+// 为批处理队列发送的一个批次创建span，父span为第一张图片的span，其他图片的span作为链接
+// 发往TensorFlow Serving的traceparent属于第一张图片的trace，其他图片可以通过链接找到这次调用
+pub fn batch_context(model_name: &str, images: &[Context]) -> Context {
+    let parent = images.first().cloned().unwrap_or_default();
+    let links = images
+        .iter()
+        .skip(1)
+        .map(|cx| cx.span().span_context().clone())
+        .filter(|span_context| span_context.is_valid())
+        .map(|span_context| Link::new(span_context, vec![]))
+        .collect();
+    let span = tracer()
+        .span_builder("predict_batch")
+        .with_attributes(vec![
+            KeyValue::new("model", model_name.to_string()),
+            KeyValue::new("batch.size", images.len() as i64),
+        ])
+        .with_links(links)
+        .start_with_context(&tracer(), &parent);
+    parent.with_span(span)
+}
+
+// 为一次发往TensorFlow Serving的请求创建客户端span，父span为当前的上下文
+pub fn client_context(name: &'static str, attributes: Vec<KeyValue>) -> Context {
+    let parent = Context::current();
+    let span = tracer()
+        .span_builder(name)
+        .with_kind(SpanKind::Client)
+        .with_attributes(attributes)
+        .start_with_context(&tracer(), &parent);
+    parent.with_span(span)
+}
+
+// 失败时把错误信息记录到span上
+pub fn record_result<T, E: Display>(cx: &Context, result: &Result<T, E>) {
+    if let Err(e) = result {
+        cx.span().set_status(SpanStatus::error(e.to_string()));
+    }
+}
+
+// 把上下文写入发往TensorFlow Serving的HTTP请求头
+pub fn inject_headers(cx: &Context, headers: &mut reqwest::header::HeaderMap) {
+    TraceContextPropagator::new().inject_context(cx, &mut HeaderInjector(headers));
+}
+
+// 把上下文写入发往TensorFlow Serving的gRPC请求的metadata
+pub fn inject_metadata(cx: &Context, metadata: &mut MetadataMap) {
+    TraceContextPropagator::new().inject_context(cx, &mut MetadataInjector(metadata));
+}
+
+struct MetadataInjector<'a>(&'a mut MetadataMap);
+
+impl Injector for MetadataInjector<'_> {
+    fn set(&mut self, key: &str, value: String) {
+        if let (Ok(key), Ok(value)) = (
+            MetadataKey::from_bytes(key.as_bytes()),
+            MetadataValue::try_from(value),
+        ) {
+            self.0.insert(key, value);
+        }
+    }
+}
+
+struct MetadataExtractor<'a>(&'a MetadataMap);
+
+impl Extractor for MetadataExtractor<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|value| value.to_str().ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0
+            .keys()
+            .filter_map(|key| match key {
+                KeyRef::Ascii(key) => Some(key.as_str()),
+                KeyRef::Binary(_) => None,
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::service::{make_service_fn, service_fn};
+    use hyper::{Body, Request, Response, Server};
+    use opentelemetry::trace::{TraceId, TracerProvider as _};
+    use std::convert::Infallible;
+    use std::net::SocketAddr;
+    use std::sync::{Arc, Mutex};
+
+    const TRACEPARENT: &str = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+    const TRACE_ID: &str = "4bf92f3577b34da6a3ce929d0e0e4736";
+
+    // 在随机端口上启动OTLP/HTTP接收端的替身，记录收到的每个请求的路径和内容
+    async fn start_collector() -> (SocketAddr, Arc<Mutex<Vec<(String, Vec<u8>)>>>) {
+        let received = Arc::new(Mutex::new(vec![]));
+        let captured = received.clone();
+        let make_svc = make_service_fn(move |_| {
+            let captured = captured.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |req: Request<Body>| {
+                    let captured = captured.clone();
+                    async move {
+                        let path = req.uri().path().to_string();
+                        let body = hyper::body::to_bytes(req.into_body()).await.unwrap();
+                        captured.lock().unwrap().push((path, body.to_vec()));
+                        Ok::<_, Infallible>(Response::new(Body::empty()))
+                    }
+                }))
+            }
+        });
+        let server = Server::bind(&"127.0.0.1:0".parse().unwrap()).serve(make_svc);
+        let addr = server.local_addr();
+        tokio::spawn(server);
+        (addr, received)
+    }
+
+    fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+        haystack
+            .windows(needle.len())
+            .any(|window| window == needle)
+    }
+
+    // 测试从metadata中读取traceparent，创建的span与写入HTTP请求头的traceparent属于同一个trace
+    #[test]
+    fn test_propagate_traceparent() {
+        let mut metadata = MetadataMap::new();
+        metadata.insert("traceparent", TRACEPARENT.parse().unwrap());
+
+        let cx = server_context(&metadata, "Predict");
+        assert_eq!(
+            cx.span().span_context().trace_id(),
+            TraceId::from_hex(TRACE_ID).unwrap()
+        );
+
+        let mut headers = reqwest::header::HeaderMap::new();
+        inject_headers(&cx, &mut headers);
+        let traceparent = headers["traceparent"].to_str().unwrap();
+        assert!(traceparent.starts_with(&format!("00-{}-", TRACE_ID)));
+
+        // 没有traceparent时不写入请求头
+        let cx = server_context(&MetadataMap::new(), "Predict");
+        let mut headers = reqwest::header::HeaderMap::new();
+        inject_headers(&cx, &mut headers);
+        assert!(headers.get("traceparent").is_none());
+    }
+
+    // 测试span通过OTLP/HTTP导出到指定的接收端
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_export_to_collector() {
+        let (addr, received) = start_collector().await;
+        let options = TracingOptions {
+            enabled: true,
+            endpoint: format!("http://{}", addr),
+            ..TracingOptions::default()
+        };
+        let provider = new_provider(&options).unwrap();
+
+        let parent = TraceContextPropagator::new().extract(&MetadataExtractor(&{
+            let mut metadata = MetadataMap::new();
+            metadata.insert("traceparent", TRACEPARENT.parse().unwrap());
+            metadata
+        }));
+        let tracer = provider.tracer(TRACER_NAME);
+        drop(tracer.start_with_context("exported_span", &parent));
+
+        // force_flush会阻塞等待导出完成，不能在运行时的工作线程中调用
+        let flushed = tokio::task::spawn_blocking(move || provider.force_flush())
+            .await
+            .unwrap();
+        assert!(flushed.iter().all(|r| r.is_ok()), "{:?}", flushed);
+
+        let received = received.lock().unwrap();
+        assert_eq!(received.len(), 1);
+        let (path, body) = &received[0];
+        assert_eq!(path, "/v1/traces");
+        assert!(contains(body, b"exported_span"));
+        assert!(contains(body, b"image-prediction-service"));
+        assert!(contains(
+            body,
+            &TraceId::from_hex(TRACE_ID).unwrap().to_bytes()
+        ));
+    }
+}
@@ -0,0 +1,211 @@
+use super::balancer::BalancedBackend;
+use super::client::{ClientOptions, Protocol, TfServingClient};
+use super::error::TfServingError;
+use super::grpc_client::TfServingGrpcClient;
+use super::retry::RetryPolicies;
+use crate::inference::InferenceBackend;
+use std::sync::Arc;
+
+// 以grpc://开头的地址表示使用TensorFlow Serving的gRPC接口
+const GRPC_SCHEME: &str = "grpc://";
+
+// 根据地址的scheme或配置中的protocol选择通信方式，返回实际使用的协议和地址
+fn resolve(addr: &str, options: &ClientOptions) -> (Protocol, String) {
+    match addr.strip_prefix(GRPC_SCHEME) {
+        Some(host) => (Protocol::Grpc, format!("http://{}", host)),
+        None => (options.protocol, addr.to_string()),
+    }
+}
+
+// 创建与TensorFlow Serving通信的推理服务：RESTful API或原生gRPC接口
+pub fn new_backend(
+    addr: &str,
+    options: &ClientOptions,
+) -> Result<Arc<dyn InferenceBackend>, TfServingError> {
+    let retry_policies = Arc::new(RetryPolicies::new(options.retry.clone()));
+    new_backend_with_retry(addr, options, retry_policies)
+}
+
+fn new_backend_with_retry(
+    addr: &str,
+    options: &ClientOptions,
+    retry_policies: Arc<RetryPolicies>,
+) -> Result<Arc<dyn InferenceBackend>, TfServingError> {
+    let backend: Arc<dyn InferenceBackend> = match resolve(addr, options) {
+        (Protocol::Rest, url) => {
+            Arc::new(TfServingClient::new(&url, options)?.with_retry_policies(retry_policies))
+        }
+        (Protocol::Grpc, url) => {
+            Arc::new(TfServingGrpcClient::new(&url, options)?.with_retry_policies(retry_policies))
+        }
+    };
+    Ok(backend)
+}
+
+// 为配置中的每个地址创建推理服务，有多个地址时在它们之间负载均衡
+// 负载均衡时每个实例只调用一次，重试和重试预算由负载均衡按模型统一处理，失败后立即换一个实例
+pub fn new_balanced_backend(
+    addrs: &[String],
+    options: &ClientOptions,
+) -> Result<Arc<dyn InferenceBackend>, TfServingError> {
+    if let [addr] = addrs {
+        return new_backend(addr, options);
+    }
+    let single_attempt = Arc::new(RetryPolicies::disabled());
+    let endpoints = addrs
+        .iter()
+        .map(|addr| {
+            let backend = new_backend_with_retry(addr, options, Arc::clone(&single_attempt))?;
+            Ok((addr.clone(), backend))
+        })
+        .collect::<Result<Vec<_>, TfServingError>>()?;
+    let balancer = BalancedBackend::new(endpoints, options.load_balancing.clone())
+        .with_retry_policies(Arc::new(RetryPolicies::new(options.retry.clone())));
+    Ok(Arc::new(balancer))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Model;
+    use hyper::service::{make_service_fn, service_fn};
+    use hyper::{Body, Response, Server, StatusCode};
+    use std::convert::Infallible;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+    use tonic::Code;
+
+    // 启动一个TensorFlow Serving实例的替身，统计收到的预测请求数，等待delay后以status响应
+    fn start_replica(status: StatusCode, delay: Duration) -> (String, Arc<AtomicUsize>) {
+        let requests = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&requests);
+        let make_svc = make_service_fn(move |_| {
+            let counter = Arc::clone(&counter);
+            async move {
+                Ok::<_, Infallible>(service_fn(move |_req| {
+                    counter.fetch_add(1, Ordering::SeqCst);
+                    async move {
+                        tokio::time::sleep(delay).await;
+                        let response = match status {
+                            StatusCode::OK => Response::builder()
+                                .header("content-type", "application/json")
+                                .body(Body::from(r#"{"predictions": [[0.1, 0.2]]}"#)),
+                            status => Response::builder().status(status).body(Body::empty()),
+                        };
+                        Ok::<_, Infallible>(response.unwrap())
+                    }
+                }))
+            }
+        });
+        let server = Server::bind(&"127.0.0.1:0".parse().unwrap()).serve(make_svc);
+        let url = format!("http://{}/v1", server.local_addr());
+        tokio::spawn(server);
+        (url, requests)
+    }
+
+    fn test_model() -> Model {
+        Model {
+            name: "foo".to_string(),
+            version: 1,
+            input_name: "input".to_string(),
+            ..Model::default()
+        }
+    }
+
+    // 测试根据地址的scheme和配置中的protocol选择通信方式
+    #[tokio::test]
+    async fn test_backend_selection() {
+        let rest = resolve("http://localhost:8501/v1", &ClientOptions::default());
+        assert_eq!(
+            rest,
+            (Protocol::Rest, "http://localhost:8501/v1".to_string())
+        );
+
+        let grpc = resolve("grpc://localhost:8500", &ClientOptions::default());
+        assert_eq!(grpc, (Protocol::Grpc, "http://localhost:8500".to_string()));
+
+        let options = ClientOptions {
+            protocol: Protocol::Grpc,
+            ..ClientOptions::default()
+        };
+        let grpc = resolve("http://localhost:8500", &options);
+        assert_eq!(grpc, (Protocol::Grpc, "http://localhost:8500".to_string()));
+
+        assert!(new_backend("grpc://localhost:8500", &ClientOptions::default()).is_ok());
+        assert!(new_backend("http://localhost:8501/v1", &options).is_ok());
+    }
+
+    // 测试多个地址之间轮流发送请求，返回503的实例被摘除后不再收到请求
+    #[tokio::test]
+    async fn test_balanced_backend() {
+        let (first, first_requests) = start_replica(StatusCode::OK, Duration::ZERO);
+        let (second, second_requests) = start_replica(StatusCode::OK, Duration::ZERO);
+        let (broken, broken_requests) =
+            start_replica(StatusCode::SERVICE_UNAVAILABLE, Duration::ZERO);
+        let model = test_model();
+        // 实例自己不重试，坏实例只收到一个请求
+        let mut options = ClientOptions::default();
+        options.load_balancing.max_consecutive_failures = 1;
+
+        let backend = new_balanced_backend(&[first, second, broken], &options).unwrap();
+        for _ in 0..6 {
+            assert_eq!(
+                backend.predict(&model, b"hello").await.unwrap(),
+                vec![0.1, 0.2]
+            );
+        }
+        assert_eq!(broken_requests.load(Ordering::SeqCst), 1);
+        assert_eq!(
+            first_requests.load(Ordering::SeqCst) + second_requests.load(Ordering::SeqCst),
+            6
+        );
+        assert!(first_requests.load(Ordering::SeqCst) >= 2);
+        assert!(second_requests.load(Ordering::SeqCst) >= 2);
+    }
+
+    // 测试一个实例超时后换一个实例，超时的实例计入失败
+    #[tokio::test]
+    async fn test_timeout_fails_over() {
+        let (slow, slow_requests) = start_replica(StatusCode::OK, Duration::from_millis(500));
+        let (fast, fast_requests) = start_replica(StatusCode::OK, Duration::ZERO);
+        let mut options = ClientOptions {
+            request_timeout_ms: 100,
+            ..ClientOptions::default()
+        };
+        options.load_balancing.max_consecutive_failures = 1;
+
+        let backend = new_balanced_backend(&[slow, fast], &options).unwrap();
+        for _ in 0..4 {
+            assert_eq!(
+                backend.predict(&test_model(), b"hello").await.unwrap(),
+                vec![0.1, 0.2]
+            );
+        }
+        // 超时的实例被摘除，只收到一个请求
+        assert_eq!(slow_requests.load(Ordering::SeqCst), 1);
+        assert_eq!(fast_requests.load(Ordering::SeqCst), 4);
+    }
+
+    // 测试模型不存在时不换实例、不重试，也不会导致实例被摘除
+    #[tokio::test]
+    async fn test_model_not_found_not_retried() {
+        let (missing, missing_requests) = start_replica(StatusCode::NOT_FOUND, Duration::ZERO);
+        let (loaded, loaded_requests) = start_replica(StatusCode::OK, Duration::ZERO);
+        let mut options = ClientOptions::default();
+        options.load_balancing.max_consecutive_failures = 1;
+        options.retry.initial_backoff_ms = 1;
+
+        let backend = new_balanced_backend(&[missing, loaded], &options).unwrap();
+        let mut not_found = 0;
+        for _ in 0..4 {
+            if let Err(status) = backend.predict(&test_model(), b"hello").await {
+                assert_eq!(status.code(), Code::Unavailable);
+                not_found += 1;
+            }
+        }
+        // 没有加载模型的实例仍然轮流收到请求，它的错误直接返回给调用方
+        assert_eq!(not_found, 2);
+        assert_eq!(missing_requests.load(Ordering::SeqCst), 2);
+        assert_eq!(loaded_requests.load(Ordering::SeqCst), 2);
+    }
+}
@@ -0,0 +1,498 @@
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tonic::Status;
+
+use super::error::{is_model_not_found, is_retryable};
+use super::retry::RetryPolicies;
+use crate::config::Model;
+use crate::inference::{InferenceBackend, ModelMetadata};
+use crate::metrics::metrics;
+
+// 在多个TensorFlow Serving实例之间选择的方式
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone, Copy, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum BalancePolicy {
+    // 依次轮流使用每个实例
+    #[default]
+    RoundRobin,
+    // 使用正在进行的请求最少的实例
+    LeastOutstanding,
+}
+
+// 多个TensorFlow Serving实例之间的负载均衡参数，可以在config.yaml的tf_serving.load_balancing部分配置
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct BalancerOptions {
+    pub policy: BalancePolicy,
+    // 一个实例连续失败这么多次后被摘除，直到模型状态检查或试探请求成功后才重新使用
+    pub max_consecutive_failures: u32,
+    // 被摘除的实例每隔这么长时间（毫秒）接收一个试探请求，成功后重新加入，0表示不试探
+    pub probe_interval_ms: u64,
+}
+
+impl Default for BalancerOptions {
+    fn default() -> Self {
+        BalancerOptions {
+            policy: BalancePolicy::RoundRobin,
+            max_consecutive_failures: 5,
+            probe_interval_ms: 10_000,
+        }
+    }
+}
+
+// 一个TensorFlow Serving实例及其状态
+struct Endpoint {
+    addr: String,
+    backend: Arc<dyn InferenceBackend>,
+    outstanding: AtomicUsize,
+    consecutive_failures: AtomicU32,
+    ejected: AtomicBool,
+    // 被摘除后下一次可以发送试探请求的时间
+    next_probe: Mutex<Instant>,
+}
+
+impl Endpoint {
+    // 被摘除的实例到了试探的时间时领取这次试探，同一时间只有一个请求被用作试探
+    fn claim_probe(&self, now: Instant, interval: Duration) -> bool {
+        if interval.is_zero() || !self.ejected.load(Ordering::SeqCst) {
+            return false;
+        }
+        let mut next_probe = self.next_probe.lock().unwrap();
+        if now < *next_probe {
+            return false;
+        }
+        *next_probe = now + interval;
+        true
+    }
+
+    // 可以重试的错误（不可用、超时等）视为实例的失败，请求本身的错误不影响实例的状态
+    // 模型不存在时既不计入失败也不清零，一个没有加载的模型不会导致实例被其他模型摘除
+    fn record(&self, result: &Result<(), &Status>, options: &BalancerOptions) {
+        let failed = match result {
+            Ok(()) => false,
+            Err(status) if is_model_not_found(status) => return,
+            Err(status) => is_retryable(status),
+        };
+        if !failed {
+            self.consecutive_failures.store(0, Ordering::SeqCst);
+            // 所有实例都被摘除时被摘除的实例也会收到请求，成功后直接恢复
+            if self.ejected.swap(false, Ordering::SeqCst) {
+                info!("TF Serving endpoint {} is readmitted", self.addr);
+            }
+            return;
+        }
+        let failures = self.consecutive_failures.fetch_add(1, Ordering::SeqCst) + 1;
+        if failures >= options.max_consecutive_failures.max(1)
+            && !self.ejected.swap(true, Ordering::SeqCst)
+        {
+            *self.next_probe.lock().unwrap() =
+                Instant::now() + Duration::from_millis(options.probe_interval_ms);
+            warn!(
+                "TF Serving endpoint {} is ejected after {} consecutive failures",
+                self.addr, failures
+            );
+        }
+    }
+}
+
+// 正在进行的请求，被释放时减少实例的请求数，请求被取消时也一样
+struct Outstanding<'a>(&'a AtomicUsize);
+
+impl<'a> Outstanding<'a> {
+    fn enter(counter: &'a AtomicUsize) -> Self {
+        counter.fetch_add(1, Ordering::SeqCst);
+        Outstanding(counter)
+    }
+}
+
+impl Drop for Outstanding<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+// 把请求分发到多个TensorFlow Serving实例：
+// 连续失败的实例被摘除，模型状态检查或定期的试探请求成功后重新加入；
+// 一个实例返回可以重试的错误时换一个还没有尝试过的实例，所有实例都失败后按重试策略等待并重试
+pub struct BalancedBackend {
+    endpoints: Vec<Endpoint>,
+    options: BalancerOptions,
+    next: AtomicUsize,
+    // 每个模型的重试策略，所有实例都尝试过一轮后才按策略等待并重试，max_attempts是最多的轮数
+    // 没有设置时每个实例最多尝试一次
+    retry_policies: Option<Arc<RetryPolicies>>,
+}
+
+impl BalancedBackend {
+    pub fn new(
+        endpoints: Vec<(String, Arc<dyn InferenceBackend>)>,
+        options: BalancerOptions,
+    ) -> Self {
+        BalancedBackend {
+            endpoints: endpoints
+                .into_iter()
+                .map(|(addr, backend)| Endpoint {
+                    addr,
+                    backend,
+                    outstanding: AtomicUsize::new(0),
+                    consecutive_failures: AtomicU32::new(0),
+                    ejected: AtomicBool::new(false),
+                    next_probe: Mutex::new(Instant::now()),
+                })
+                .collect(),
+            options,
+            next: AtomicUsize::new(0),
+            retry_policies: None,
+        }
+    }
+
+    // 重试在负载均衡之上进行，每个实例自己不再重试
+    pub fn with_retry_policies(mut self, retry_policies: Arc<RetryPolicies>) -> Self {
+        self.retry_policies = Some(retry_policies);
+        self
+    }
+
+    // 从还没有尝试过的实例中选择一个，所有实例都被摘除时从所有实例中选择
+    // 被摘除的实例到了试探的时间时优先选择它，不依赖模型状态检查也能恢复
+    fn pick(&self, tried: &[usize]) -> Option<usize> {
+        let untried = |index: &usize| !tried.contains(index);
+        let interval = Duration::from_millis(self.options.probe_interval_ms);
+        let now = Instant::now();
+        if let Some(index) = (0..self.endpoints.len())
+            .filter(untried)
+            .find(|index| self.endpoints[*index].claim_probe(now, interval))
+        {
+            return Some(index);
+        }
+
+        let admitted = |index: &usize| !self.endpoints[*index].ejected.load(Ordering::SeqCst);
+        let count = self.endpoints.len();
+        let start = self.next.fetch_add(1, Ordering::Relaxed);
+        let order = (0..count).map(|offset| (start + offset) % count);
+
+        let candidates: Vec<usize> = match order.clone().any(|index| admitted(&index)) {
+            true => order.filter(untried).filter(admitted).collect(),
+            false => order.filter(untried).collect(),
+        };
+        match self.options.policy {
+            BalancePolicy::RoundRobin => candidates.first().copied(),
+            // 请求数相同时按轮流的顺序选择
+            BalancePolicy::LeastOutstanding => candidates
+                .into_iter()
+                .min_by_key(|index| self.endpoints[*index].outstanding.load(Ordering::SeqCst)),
+        }
+    }
+}
+
+#[tonic::async_trait]
+impl InferenceBackend for BalancedBackend {
+    async fn predict_batch(
+        &self,
+        model: &Model,
+        images: &[&[u8]],
+    ) -> Result<Vec<Vec<f32>>, Status> {
+        let policy = self
+            .retry_policies
+            .as_ref()
+            .map(|policies| policies.for_model(model));
+        let mut tried = vec![];
+        let mut round = 1;
+        loop {
+            // 第一次总能选出一个实例
+            let index = self.pick(&tried).unwrap();
+            tried.push(index);
+            let endpoint = &self.endpoints[index];
+            let result = {
+                let _outstanding = Outstanding::enter(&endpoint.outstanding);
+                endpoint.backend.predict_batch(model, images).await
+            };
+            endpoint.record(&result.as_ref().map(|_| ()), &self.options);
+            let status = match result {
+                // 与TfServingError::is_retryable一致，超时也换一个实例，模型不存在等错误直接返回
+                Err(status) if is_retryable(&status) => status,
+                result => {
+                    if let (Ok(_), Some(policy)) = (&result, &policy) {
+                        policy.record_success();
+                    }
+                    return result;
+                }
+            };
+            warn!(
+                "TF Serving endpoint {} failed to predict for model {}: {}",
+                endpoint.addr,
+                model.name,
+                status.message()
+            );
+            // 还有没有尝试过的实例时立即换一个
+            if tried.len() < self.endpoints.len() {
+                continue;
+            }
+            // 所有实例都尝试过，按模型的重试策略等待后开始新的一轮，没有重试策略时不再重试
+            let backoff = match policy.as_ref().and_then(|policy| policy.consume(round)) {
+                Some(backoff) => backoff,
+                None => return Err(status),
+            };
+            warn!(
+                "All TF Serving endpoints failed in round {} for model {}, retrying in {:?}",
+                round, model.name, backoff
+            );
+            metrics().retry(&model.name);
+            tokio::time::sleep(backoff).await;
+            tried.clear();
+            round += 1;
+        }
+    }
+
+    // 检查所有实例，被摘除的实例检查成功后重新加入，任何一个实例可用时模型可用
+    async fn model_status(&self, model: &Model) -> Result<(), Status> {
+        let mut last_error = None;
+        let mut available = false;
+        for endpoint in &self.endpoints {
+            match endpoint.backend.model_status(model).await {
+                Ok(()) => {
+                    if endpoint.ejected.swap(false, Ordering::SeqCst) {
+                        endpoint.consecutive_failures.store(0, Ordering::SeqCst);
+                        info!(
+                            "TF Serving endpoint {} is readmitted, model {} is available",
+                            endpoint.addr, model.name
+                        );
+                    }
+                    available = true;
+                }
+                Err(status) => {
+                    last_error = Some(Status::new(
+                        status.code(),
+                        format!("{}: {}", endpoint.addr, status.message()),
+                    ))
+                }
+            }
+        }
+        match (available, last_error) {
+            (false, Some(status)) => Err(status),
+            _ => Ok(()),
+        }
+    }
+
+    // 依次尝试每个没有被摘除的实例
+    async fn model_metadata(&self, model: &Model) -> Result<ModelMetadata, Status> {
+        let mut tried = vec![];
+        let mut last_error = None;
+        while let Some(index) = self.pick(&tried) {
+            tried.push(index);
+            match self.endpoints[index].backend.model_metadata(model).await {
+                Ok(metadata) => return Ok(metadata),
+                Err(status) => last_error = Some(status),
+            }
+        }
+        Err(last_error.unwrap())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::inference::fake::FakeBackend;
+    use crate::tf_serving::retry::RetryOptions;
+    use tonic::Code;
+
+    fn test_model() -> Model {
+        Model {
+            name: "foo".to_string(),
+            version: 1,
+            input_name: "input".to_string(),
+            ..Model::default()
+        }
+    }
+
+    fn balanced(
+        backends: &[Arc<FakeBackend>],
+        policy: BalancePolicy,
+        max_consecutive_failures: u32,
+    ) -> BalancedBackend {
+        let endpoints = backends
+            .iter()
+            .enumerate()
+            .map(|(i, backend)| {
+                let backend: Arc<dyn InferenceBackend> = backend.clone();
+                (format!("http://replica{}:8501/v1", i), backend)
+            })
+            .collect();
+        BalancedBackend::new(
+            endpoints,
+            BalancerOptions {
+                policy,
+                max_consecutive_failures,
+                ..BalancerOptions::default()
+            },
+        )
+    }
+
+    // 测试轮流使用每个实例
+    #[tokio::test]
+    async fn test_round_robin() {
+        let backends: Vec<_> = (0..3).map(|_| Arc::new(FakeBackend::new())).collect();
+        let balancer = balanced(&backends, BalancePolicy::RoundRobin, 5);
+
+        for _ in 0..6 {
+            balancer.predict(&test_model(), b"hello").await.unwrap();
+        }
+        let calls: Vec<usize> = backends.iter().map(|b| b.calls()).collect();
+        assert_eq!(calls, vec![2, 2, 2]);
+    }
+
+    // 测试请求被发送到正在进行的请求最少的实例
+    #[tokio::test]
+    async fn test_least_outstanding() {
+        let slow = Arc::new(FakeBackend::new().with_delay(Duration::from_millis(200)));
+        let fast = Arc::new(FakeBackend::new());
+        let balancer = Arc::new(balanced(
+            &[slow.clone(), fast.clone()],
+            BalancePolicy::LeastOutstanding,
+            5,
+        ));
+
+        // 第一个请求占用慢的实例，之后的请求都发送到空闲的实例
+        let pending = {
+            let balancer = Arc::clone(&balancer);
+            tokio::spawn(async move { balancer.predict(&test_model(), b"slow").await })
+        };
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert_eq!(slow.in_flight(), 1);
+
+        for _ in 0..4 {
+            balancer.predict(&test_model(), b"hello").await.unwrap();
+        }
+        pending.await.unwrap().unwrap();
+        assert_eq!(slow.calls(), 1);
+        assert_eq!(fast.calls(), 4);
+    }
+
+    // 测试一个实例不可用时换一个实例，连续失败后被摘除，模型状态检查成功后重新加入
+    #[tokio::test]
+    async fn test_failover_and_ejection() {
+        let broken = Arc::new(FakeBackend::new().failing("foo"));
+        let healthy = Arc::new(FakeBackend::new());
+        let balancer = balanced(
+            &[broken.clone(), healthy.clone()],
+            BalancePolicy::RoundRobin,
+            2,
+        );
+
+        // 每个请求都成功，轮到坏实例的请求被转到另一个实例
+        for _ in 0..6 {
+            let result = balancer.predict(&test_model(), b"hello").await;
+            assert_eq!(result.unwrap(), vec![5.0, 1.0]);
+        }
+        // 坏实例失败两次后被摘除，不再收到请求
+        assert_eq!(broken.calls(), 2);
+        assert_eq!(healthy.calls(), 6);
+
+        // 模型状态检查失败时仍然被摘除
+        assert!(balancer.model_status(&test_model()).await.is_ok());
+        balancer.predict(&test_model(), b"hello").await.unwrap();
+        balancer.predict(&test_model(), b"hello").await.unwrap();
+        assert_eq!(broken.calls(), 2);
+
+        broken.set_failing("foo", false);
+        balancer.model_status(&test_model()).await.unwrap();
+        balancer.predict(&test_model(), b"hello").await.unwrap();
+        balancer.predict(&test_model(), b"hello").await.unwrap();
+        assert_eq!(broken.calls(), 3);
+    }
+
+    // 测试所有实例都失败时返回最后一个错误，所有实例都被摘除时仍然依次尝试每个实例
+    #[tokio::test]
+    async fn test_all_endpoints_failing() {
+        let backends: Vec<_> = (0..2)
+            .map(|_| Arc::new(FakeBackend::new().failing("foo")))
+            .collect();
+        let balancer = balanced(&backends, BalancePolicy::RoundRobin, 1);
+
+        let status = balancer.predict(&test_model(), b"hello").await.unwrap_err();
+        assert_eq!(status.code(), Code::Unavailable);
+        assert_eq!(backends[0].calls() + backends[1].calls(), 2);
+
+        backends[1].set_failing("foo", false);
+        balancer.predict(&test_model(), b"hello").await.unwrap();
+
+        let status = balancer.model_status(&test_model()).await;
+        assert!(status.is_ok());
+        backends[1].set_failing("foo", true);
+        let status = balancer.model_status(&test_model()).await.unwrap_err();
+        assert_eq!(
+            status.message(),
+            "http://replica1:8501/v1: model foo is broken"
+        );
+    }
+
+    // 测试被摘除的实例在试探间隔之后收到一个试探请求，成功后重新加入，不依赖模型状态检查
+    #[tokio::test]
+    async fn test_probe_readmits_ejected_endpoint() {
+        let broken = Arc::new(FakeBackend::new().failing("foo"));
+        let healthy = Arc::new(FakeBackend::new());
+        let endpoints: Vec<(String, Arc<dyn InferenceBackend>)> = vec![
+            ("http://replica0:8501/v1".to_string(), broken.clone()),
+            ("http://replica1:8501/v1".to_string(), healthy.clone()),
+        ];
+        let balancer = BalancedBackend::new(
+            endpoints,
+            BalancerOptions {
+                max_consecutive_failures: 1,
+                probe_interval_ms: 100,
+                ..BalancerOptions::default()
+            },
+        );
+
+        for _ in 0..4 {
+            balancer.predict(&test_model(), b"hello").await.unwrap();
+        }
+        assert_eq!(broken.calls(), 1);
+
+        // 试探失败时请求转到另一个实例，实例仍然被摘除
+        tokio::time::sleep(Duration::from_millis(150)).await;
+        balancer.predict(&test_model(), b"hello").await.unwrap();
+        balancer.predict(&test_model(), b"hello").await.unwrap();
+        assert_eq!(broken.calls(), 2);
+
+        // 试探成功后重新加入轮流的顺序
+        broken.set_failing("foo", false);
+        tokio::time::sleep(Duration::from_millis(150)).await;
+        balancer.predict(&test_model(), b"hello").await.unwrap();
+        assert_eq!(broken.calls(), 3);
+        for _ in 0..4 {
+            balancer.predict(&test_model(), b"hello").await.unwrap();
+        }
+        assert_eq!(broken.calls(), 5);
+    }
+
+    // 测试所有实例都失败后按模型的重试策略重试整轮，每个实例每轮只调用一次
+    #[tokio::test]
+    async fn test_retry_above_balancer() {
+        let backends: Vec<_> = (0..2)
+            .map(|_| Arc::new(FakeBackend::new().failing("foo")))
+            .collect();
+        let balancer = balanced(&backends, BalancePolicy::RoundRobin, 100).with_retry_policies(
+            Arc::new(RetryPolicies::new(RetryOptions {
+                max_attempts: 2,
+                initial_backoff_ms: 1,
+                budget_tokens: 4.0,
+                ..RetryOptions::default()
+            })),
+        );
+
+        let status = balancer.predict(&test_model(), b"hello").await.unwrap_err();
+        assert_eq!(status.code(), Code::Unavailable);
+        assert_eq!(backends[0].calls(), 2);
+        assert_eq!(backends[1].calls(), 2);
+
+        // 重试预算按模型计算：第二轮失败后令牌只剩一半，不再重试
+        balancer.predict(&test_model(), b"hello").await.unwrap_err();
+        assert_eq!(backends[0].calls() + backends[1].calls(), 6);
+
+        backends[0].set_failing("foo", false);
+        balancer.predict(&test_model(), b"hello").await.unwrap();
+    }
+}
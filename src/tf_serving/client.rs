@@ -0,0 +1,344 @@
+use super::balancer::BalancerOptions;
+use super::error::TfServingError;
+use super::model_metadata::get_model_metadata;
+use super::model_status::get_model_status;
+use super::predict_service::predict_batch_with_retry;
+use super::retry::{RetryOptions, RetryPolicies};
+use crate::config::Model;
+use crate::inference::{InferenceBackend, ModelMetadata};
+use crate::metrics::{metrics, Stage};
+use base64_simd::URL_SAFE;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, AUTHORIZATION};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tonic::Status;
+
+// 与TensorFlow Serving通信使用的协议
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone, Copy, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Protocol {
+    #[default]
+    Rest,
+    Grpc,
+}
+
+// TensorFlow Serving客户端的连接参数，可以在config.yaml的tf_serving部分配置
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct ClientOptions {
+    // TensorFlow Serving的地址，可以有多个实例，命令行中的--tensorflow_api_addr优先
+    pub endpoints: Vec<String>,
+    // 多个实例之间的负载均衡参数
+    pub load_balancing: BalancerOptions,
+    // 通信协议，地址以grpc://开头时总是使用gRPC
+    pub protocol: Protocol,
+    // 建立连接的超时时间（毫秒）
+    pub connect_timeout_ms: u64,
+    // 单次请求的超时时间（毫秒）
+    pub request_timeout_ms: u64,
+    // 每个主机保持的最大空闲连接数
+    pub pool_max_idle_per_host: usize,
+    // 空闲连接被回收前的保持时间（毫秒）
+    pub pool_idle_timeout_ms: u64,
+    // 直接使用HTTP/2与TensorFlow Serving通信，不经过HTTP/1.1升级，仅用于RESTful API
+    pub http2_prior_knowledge: bool,
+    // 暂时性失败的重试参数，可以被模型的retry覆盖
+    pub retry: RetryOptions,
+    // 每个请求附带的认证信息，可以被模型的auth覆盖
+    pub auth: AuthOptions,
+}
+
+impl Default for ClientOptions {
+    fn default() -> Self {
+        ClientOptions {
+            endpoints: vec!["http://localhost:8501/v1".to_string()],
+            load_balancing: BalancerOptions::default(),
+            protocol: Protocol::Rest,
+            connect_timeout_ms: 3_000,
+            request_timeout_ms: 30_000,
+            pool_max_idle_per_host: 32,
+            pool_idle_timeout_ms: 90_000,
+            http2_prior_knowledge: false,
+            retry: RetryOptions::default(),
+            auth: AuthOptions::default(),
+        }
+    }
+}
+
+// TensorFlow Serving前面有需要认证的网关时，每个请求附带的认证信息
+#[derive(PartialEq, Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct AuthOptions {
+    // 以Authorization: Bearer发送的令牌
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bearer_token: Option<String>,
+    // 其他请求头，例如网关要求的x-api-key，使用gRPC时作为metadata发送
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub headers: BTreeMap<String, String>,
+}
+
+impl AuthOptions {
+    // 每个请求附带的请求头，名称或值的格式错误时返回错误信息
+    pub fn header_map(&self) -> Result<HeaderMap, String> {
+        let mut headers = HeaderMap::new();
+        for (name, value) in &self.headers {
+            let name = HeaderName::from_bytes(name.as_bytes())
+                .map_err(|_| format!("Invalid header name {:?}", name))?;
+            let mut value = HeaderValue::from_str(value)
+                .map_err(|_| format!("Invalid value of header {}", name))?;
+            value.set_sensitive(true);
+            headers.insert(name, value);
+        }
+        if let Some(token) = &self.bearer_token {
+            let mut value = HeaderValue::from_str(&format!("Bearer {}", token))
+                .map_err(|_| "Invalid bearer_token".to_string())?;
+            value.set_sensitive(true);
+            headers.insert(AUTHORIZATION, value);
+        }
+        Ok(headers)
+    }
+}
+
+// 配置会被打印到日志中，不输出令牌和请求头的值
+impl fmt::Debug for AuthOptions {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("AuthOptions")
+            .field(
+                "bearer_token",
+                &self.bearer_token.as_ref().map(|_| "<redacted>"),
+            )
+            .field("headers", &self.headers.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+// 长期持有的TensorFlow Serving客户端，内部的连接池在所有请求之间共享
+#[derive(Clone, Debug)]
+pub struct TfServingClient {
+    http: reqwest::Client,
+    base_url: Arc<str>,
+    retry_policies: Arc<RetryPolicies>,
+}
+
+impl TfServingClient {
+    pub fn new(base_url: &str, options: &ClientOptions) -> Result<Self, TfServingError> {
+        let headers = options
+            .auth
+            .header_map()
+            .map_err(TfServingError::InvalidConfig)?;
+        let mut builder = reqwest::Client::builder()
+            .default_headers(headers)
+            .connect_timeout(Duration::from_millis(options.connect_timeout_ms))
+            .timeout(Duration::from_millis(options.request_timeout_ms))
+            .pool_max_idle_per_host(options.pool_max_idle_per_host)
+            .pool_idle_timeout(Duration::from_millis(options.pool_idle_timeout_ms))
+            .tcp_keepalive(Duration::from_secs(60));
+        if options.http2_prior_knowledge {
+            builder = builder.http2_prior_knowledge();
+        }
+
+        Ok(TfServingClient {
+            http: builder.build()?,
+            base_url: Arc::from(base_url),
+            retry_policies: Arc::new(RetryPolicies::new(options.retry.clone())),
+        })
+    }
+
+    // 替换按options.retry创建的重试策略
+    pub fn with_retry_policies(mut self, retry_policies: Arc<RetryPolicies>) -> Self {
+        self.retry_policies = retry_policies;
+        self
+    }
+}
+
+// 通过RESTful API访问TensorFlow Serving，模型的输入是URL安全的Base64字符串
+#[tonic::async_trait]
+impl InferenceBackend for TfServingClient {
+    async fn predict_batch(
+        &self,
+        model: &Model,
+        images: &[&[u8]],
+    ) -> Result<Vec<Vec<f32>>, Status> {
+        let start = Instant::now();
+        let images_base64: Vec<String> = images
+            .iter()
+            .map(|image| URL_SAFE.encode_to_string(image))
+            .collect();
+        let images_base64: Vec<&str> = images_base64.iter().map(String::as_str).collect();
+        metrics().observe(&model.name, Stage::Encode, start.elapsed());
+
+        let predictions = predict_batch_with_retry(
+            &self.http,
+            &self.retry_policies.for_model(model),
+            &self.base_url,
+            &model.name,
+            &model.version.to_string(),
+            &model.input_name,
+            &images_base64,
+        )
+        .await?;
+        Ok(predictions)
+    }
+
+    async fn model_status(&self, model: &Model) -> Result<(), Status> {
+        get_model_status(
+            &self.http,
+            &self.base_url,
+            &model.name,
+            &model.version.to_string(),
+            None,
+        )
+        .await
+        .map_err(|e| Status::unavailable(format!("{:#}", e)))
+    }
+
+    async fn model_metadata(&self, model: &Model) -> Result<ModelMetadata, Status> {
+        let metadata = get_model_metadata(
+            &self.http,
+            &self.base_url,
+            &model.name,
+            &model.version.to_string(),
+        )
+        .await?;
+        Ok(metadata)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::server::conn::AddrStream;
+    use hyper::service::{make_service_fn, service_fn};
+    use hyper::{Body, Response, Server};
+    use std::convert::Infallible;
+    use std::net::SocketAddr;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tokio::net::TcpListener;
+    use tonic::Code;
+
+    fn test_model() -> Model {
+        Model {
+            name: "foo".to_string(),
+            version: 1,
+            input_name: "input".to_string(),
+            ..Model::default()
+        }
+    }
+
+    // 启动一个本地的TensorFlow Serving替身，并统计建立过的TCP连接数
+    fn start_counting_server() -> (SocketAddr, Arc<AtomicUsize>) {
+        let connections = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&connections);
+        let make_svc = make_service_fn(move |_conn: &AddrStream| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            async {
+                Ok::<_, Infallible>(service_fn(|_req| async {
+                    Ok::<_, Infallible>(
+                        Response::builder()
+                            .header("content-type", "application/json")
+                            .body(Body::from(r#"{"predictions": [[0.1, 0.2]]}"#))
+                            .unwrap(),
+                    )
+                }))
+            }
+        });
+
+        let server = Server::bind(&"127.0.0.1:0".parse().unwrap()).serve(make_svc);
+        let addr = server.local_addr();
+        tokio::spawn(server);
+        (addr, connections)
+    }
+
+    // 对比共享客户端与每次新建客户端时建立的连接数
+    #[tokio::test]
+    async fn test_connection_reuse() {
+        const REQUESTS: usize = 50;
+
+        let (addr, connections) = start_counting_server();
+        let url = format!("http://{}", addr);
+        let client = TfServingClient::new(&url, &ClientOptions::default()).unwrap();
+
+        for _ in 0..REQUESTS {
+            let result = client.predict(&test_model(), b"hello").await;
+            assert_eq!(result.unwrap(), vec![0.1, 0.2]);
+        }
+        let pooled_connections = connections.swap(0, Ordering::SeqCst);
+
+        for _ in 0..REQUESTS {
+            let fresh = TfServingClient::new(&url, &ClientOptions::default()).unwrap();
+            fresh.predict(&test_model(), b"hello").await.unwrap();
+        }
+        let fresh_connections = connections.load(Ordering::SeqCst);
+
+        assert_eq!(pooled_connections, 1);
+        assert_eq!(fresh_connections, REQUESTS);
+    }
+
+    // 测试请求超时后被映射为DEADLINE_EXCEEDED
+    #[tokio::test]
+    async fn test_request_timeout() {
+        // 只接受连接但从不响应的服务器
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let mut sockets = vec![];
+            while let Ok((socket, _)) = listener.accept().await {
+                sockets.push(socket);
+            }
+        });
+
+        let options = ClientOptions {
+            request_timeout_ms: 50,
+            ..ClientOptions::default()
+        };
+        let client = TfServingClient::new(&format!("http://{}", addr), &options).unwrap();
+        let err = client.predict(&test_model(), b"hello").await.unwrap_err();
+        assert_eq!(err.code(), Code::DeadlineExceeded);
+    }
+
+    // 测试模型的retry覆盖tf_serving部分的重试参数
+    #[tokio::test]
+    async fn test_model_retry_overrides_default() {
+        let path = "/models/retry_override/versions/1:predict";
+        let unavailable = mockito::mock("POST", path)
+            .with_status(503)
+            .expect(2)
+            .create();
+        let _ok = mockito::mock("POST", path)
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(r#"{"predictions": [[0.3]]}"#)
+            .create();
+
+        // 默认不重试
+        let options = ClientOptions {
+            retry: RetryOptions {
+                max_attempts: 1,
+                ..RetryOptions::default()
+            },
+            ..ClientOptions::default()
+        };
+        let client = TfServingClient::new(&mockito::server_url(), &options).unwrap();
+        let model = Model {
+            name: "retry_override".to_string(),
+            ..test_model()
+        };
+        let err = client.predict(&model, b"hello").await.unwrap_err();
+        assert_eq!(err.code(), Code::Unavailable);
+
+        // 模型允许调用3次，第二次调用成功
+        let model = Model {
+            retry: Some(RetryOptions {
+                max_attempts: 3,
+                initial_backoff_ms: 1,
+                ..RetryOptions::default()
+            }),
+            ..model
+        };
+        assert_eq!(client.predict(&model, b"hello").await.unwrap(), vec![0.3]);
+        unavailable.assert();
+    }
+}
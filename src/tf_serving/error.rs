@@ -0,0 +1,357 @@
+use reqwest::StatusCode;
+use std::error::Error as StdError;
+use std::fmt;
+use tonic::metadata::MetadataValue;
+use tonic::{Code, Status};
+
+// 转换后的Status在metadata中标记错误的类型，负载均衡据此决定是否换一个实例，而不是根据gRPC状态码
+const ERROR_KIND_KEY: &str = "x-tf-serving-error";
+const RETRYABLE: &str = "retryable";
+const MODEL_NOT_FOUND: &str = "model-not-found";
+
+// 定义TensorFlow Serving调用过程中可能出现的错误类型
+#[derive(Debug)]
+pub enum TfServingError {
+    // TensorFlow Serving返回了非2xx的状态码
+    Status {
+        // 请求的用途，比如 predict、get metadata
+        action: &'static str,
+        model_name: String,
+        status: StatusCode,
+    },
+    // 请求超时
+    Timeout(reqwest::Error),
+    // 无法建立连接
+    Connect(reqwest::Error),
+    // 响应的数据无法解析
+    Decode(reqwest::Error),
+    // 其他请求错误
+    Request(reqwest::Error),
+    // TensorFlow Serving的gRPC接口返回了错误状态
+    Grpc(Box<Status>),
+    // 无法建立gRPC连接
+    Transport(tonic::transport::Error),
+    // 响应的数据不符合预期，例如输出张量的形状不正确
+    InvalidOutput(String),
+    // 客户端的配置不正确，例如请求头的格式错误
+    InvalidConfig(String),
+}
+
+impl TfServingError {
+    // 将错误映射为对应的gRPC状态码
+    pub fn code(&self) -> Code {
+        match self {
+            TfServingError::Status { status, .. } => match *status {
+                StatusCode::REQUEST_TIMEOUT | StatusCode::GATEWAY_TIMEOUT => Code::DeadlineExceeded,
+                // 404表示TensorFlow Serving上没有加载该模型或版本，属于服务端不可用
+                StatusCode::NOT_FOUND
+                | StatusCode::TOO_MANY_REQUESTS
+                | StatusCode::BAD_GATEWAY
+                | StatusCode::SERVICE_UNAVAILABLE => Code::Unavailable,
+                s if s.is_client_error() => Code::InvalidArgument,
+                _ => Code::Internal,
+            },
+            TfServingError::Timeout(_) => Code::DeadlineExceeded,
+            TfServingError::Connect(_) => Code::Unavailable,
+            TfServingError::Decode(_) | TfServingError::Request(_) => Code::Internal,
+            TfServingError::Grpc(status) => match status.code() {
+                // tonic在请求超时后返回CANCELLED
+                Code::Cancelled => Code::DeadlineExceeded,
+                code => code,
+            },
+            TfServingError::Transport(_) => Code::Unavailable,
+            TfServingError::InvalidOutput(_) | TfServingError::InvalidConfig(_) => Code::Internal,
+        }
+    }
+
+    // 是否是暂时性的错误，重试可能成功
+    // 400、404和无法解析的响应重试也不会成功
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            TfServingError::Status { status, .. } => matches!(
+                *status,
+                StatusCode::TOO_MANY_REQUESTS
+                    | StatusCode::BAD_GATEWAY
+                    | StatusCode::SERVICE_UNAVAILABLE
+                    | StatusCode::GATEWAY_TIMEOUT
+            ),
+            TfServingError::Timeout(_) | TfServingError::Connect(_) => true,
+            // 发送请求或读取响应头时连接被重置
+            TfServingError::Request(e) => e.is_request(),
+            TfServingError::Decode(_)
+            | TfServingError::InvalidOutput(_)
+            | TfServingError::InvalidConfig(_) => false,
+            TfServingError::Grpc(status) => matches!(
+                status.code(),
+                Code::Unavailable | Code::ResourceExhausted | Code::Cancelled
+            ),
+            TfServingError::Transport(_) => true,
+        }
+    }
+
+    // TensorFlow Serving上没有加载该模型或版本，与实例本身是否健康无关
+    pub fn is_model_not_found(&self) -> bool {
+        match self {
+            TfServingError::Status { status, .. } => *status == StatusCode::NOT_FOUND,
+            TfServingError::Grpc(status) => status.code() == Code::NotFound,
+            _ => false,
+        }
+    }
+}
+
+// 给Status加上可以重试的标记
+pub fn retryable(status: Status) -> Status {
+    with_kind(status, RETRYABLE)
+}
+
+// 给Status加上模型不存在的标记
+pub fn model_not_found(status: Status) -> Status {
+    with_kind(status, MODEL_NOT_FOUND)
+}
+
+fn with_kind(mut status: Status, kind: &'static str) -> Status {
+    status
+        .metadata_mut()
+        .insert(ERROR_KIND_KEY, MetadataValue::from_static(kind));
+    status
+}
+
+fn kind(status: &Status) -> Option<&str> {
+    status
+        .metadata()
+        .get(ERROR_KIND_KEY)
+        .and_then(|value| value.to_str().ok())
+}
+
+// 推理服务返回的错误是否是暂时性的，与TfServingError::is_retryable一致
+pub fn is_retryable(status: &Status) -> bool {
+    kind(status) == Some(RETRYABLE)
+}
+
+// 推理服务返回的错误是否表示模型不存在
+pub fn is_model_not_found(status: &Status) -> bool {
+    kind(status) == Some(MODEL_NOT_FOUND)
+}
+
+impl fmt::Display for TfServingError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TfServingError::Status {
+                action,
+                model_name,
+                status,
+            } => write!(
+                f,
+                "Failed to {} for model {}: status code {}",
+                action, model_name, status
+            ),
+            TfServingError::Timeout(e)
+            | TfServingError::Connect(e)
+            | TfServingError::Decode(e)
+            | TfServingError::Request(e) => write!(f, "{}", e),
+            TfServingError::Grpc(status) => write!(
+                f,
+                "TensorFlow Serving returned {:?}: {}",
+                status.code(),
+                status.message()
+            ),
+            TfServingError::Transport(e) => write!(f, "{}", e),
+            TfServingError::InvalidOutput(message) | TfServingError::InvalidConfig(message) => {
+                write!(f, "{}", message)
+            }
+        }
+    }
+}
+
+impl StdError for TfServingError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match self {
+            TfServingError::Status { .. }
+            | TfServingError::InvalidOutput(_)
+            | TfServingError::InvalidConfig(_) => None,
+            TfServingError::Timeout(e)
+            | TfServingError::Connect(e)
+            | TfServingError::Decode(e)
+            | TfServingError::Request(e) => Some(e),
+            TfServingError::Grpc(status) => Some(status.as_ref()),
+            TfServingError::Transport(e) => Some(e),
+        }
+    }
+}
+
+// 根据reqwest的错误类型进行分类
+impl From<reqwest::Error> for TfServingError {
+    fn from(e: reqwest::Error) -> Self {
+        if e.is_timeout() {
+            TfServingError::Timeout(e)
+        } else if e.is_connect() {
+            TfServingError::Connect(e)
+        } else if e.is_decode() {
+            TfServingError::Decode(e)
+        } else {
+            TfServingError::Request(e)
+        }
+    }
+}
+
+impl From<Status> for TfServingError {
+    fn from(status: Status) -> Self {
+        TfServingError::Grpc(Box::new(status))
+    }
+}
+
+impl From<tonic::transport::Error> for TfServingError {
+    fn from(e: tonic::transport::Error) -> Self {
+        TfServingError::Transport(e)
+    }
+}
+
+impl From<TfServingError> for Status {
+    fn from(e: TfServingError) -> Self {
+        let status = Status::new(e.code(), e.to_string());
+        if e.is_retryable() {
+            retryable(status)
+        } else if e.is_model_not_found() {
+            model_not_found(status)
+        } else {
+            status
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use tokio::net::TcpListener;
+
+    // 测试HTTP状态码到gRPC状态码的映射
+    #[test]
+    fn test_status_code_mapping() {
+        let cases = [
+            (StatusCode::BAD_REQUEST, Code::InvalidArgument),
+            (StatusCode::NOT_FOUND, Code::Unavailable),
+            (StatusCode::REQUEST_TIMEOUT, Code::DeadlineExceeded),
+            (StatusCode::TOO_MANY_REQUESTS, Code::Unavailable),
+            (StatusCode::INTERNAL_SERVER_ERROR, Code::Internal),
+            (StatusCode::BAD_GATEWAY, Code::Unavailable),
+            (StatusCode::SERVICE_UNAVAILABLE, Code::Unavailable),
+            (StatusCode::GATEWAY_TIMEOUT, Code::DeadlineExceeded),
+        ];
+        for (status, code) in cases {
+            let err = TfServingError::Status {
+                action: "predict",
+                model_name: "foo".to_string(),
+                status,
+            };
+            assert_eq!(err.code(), code, "status {}", status);
+        }
+    }
+
+    // 测试哪些HTTP状态码可以重试
+    #[test]
+    fn test_retryable_status() {
+        let cases = [
+            (StatusCode::BAD_REQUEST, false),
+            (StatusCode::NOT_FOUND, false),
+            (StatusCode::TOO_MANY_REQUESTS, true),
+            (StatusCode::INTERNAL_SERVER_ERROR, false),
+            (StatusCode::BAD_GATEWAY, true),
+            (StatusCode::SERVICE_UNAVAILABLE, true),
+            (StatusCode::GATEWAY_TIMEOUT, true),
+        ];
+        for (status, retryable) in cases {
+            let err = TfServingError::Status {
+                action: "predict",
+                model_name: "foo".to_string(),
+                status,
+            };
+            assert_eq!(err.is_retryable(), retryable, "status {}", status);
+        }
+    }
+
+    // 测试转换后的Status带有可以重试或模型不存在的标记
+    #[test]
+    fn test_status_markers() {
+        let cases = [
+            (StatusCode::NOT_FOUND, false, true),
+            (StatusCode::BAD_REQUEST, false, false),
+            (StatusCode::SERVICE_UNAVAILABLE, true, false),
+            (StatusCode::GATEWAY_TIMEOUT, true, false),
+        ];
+        for (status, retryable, not_found) in cases {
+            let status = Status::from(TfServingError::Status {
+                action: "predict",
+                model_name: "foo".to_string(),
+                status,
+            });
+            assert_eq!(is_retryable(&status), retryable, "{:?}", status);
+            assert_eq!(is_model_not_found(&status), not_found, "{:?}", status);
+        }
+
+        let status = Status::from(TfServingError::Grpc(Box::new(Status::not_found(""))));
+        assert!(is_model_not_found(&status));
+        // 没有标记的Status不可以重试，即使状态码是UNAVAILABLE
+        assert!(!is_retryable(&Status::unavailable("")));
+    }
+
+    // 测试连接被拒绝或被重置时可以重试
+    #[tokio::test]
+    async fn test_connection_errors_are_retryable() {
+        // 接受连接后立即关闭的服务器
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((socket, _)) = listener.accept().await {
+                drop(socket);
+            }
+        });
+
+        let client = reqwest::Client::new();
+        let err = client
+            .get(format!("http://{}", addr))
+            .send()
+            .await
+            .unwrap_err();
+        assert!(TfServingError::from(err).is_retryable());
+
+        // 没有监听的端口
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        drop(listener);
+        let err = client
+            .get(format!("http://{}", addr))
+            .send()
+            .await
+            .unwrap_err();
+        let err = TfServingError::from(err);
+        assert!(matches!(err, TfServingError::Connect(_)));
+        assert!(err.is_retryable());
+    }
+
+    // 测试连接超时的请求被映射为DEADLINE_EXCEEDED
+    #[tokio::test]
+    async fn test_timeout_maps_to_deadline_exceeded() {
+        // 只接受连接但从不响应的服务器
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let mut sockets = vec![];
+            while let Ok((socket, _)) = listener.accept().await {
+                sockets.push(socket);
+            }
+        });
+
+        let err = reqwest::Client::builder()
+            .timeout(Duration::from_millis(50))
+            .build()
+            .unwrap()
+            .get(format!("http://{}", addr))
+            .send()
+            .await
+            .unwrap_err();
+
+        let status = Status::from(TfServingError::from(err));
+        assert_eq!(status.code(), Code::DeadlineExceeded);
+    }
+}
@@ -0,0 +1,711 @@
+use super::client::ClientOptions;
+use super::error::TfServingError;
+use super::model_metadata::DEFAULT_SIGNATURE;
+use super::retry::{with_retry, RetryPolicies};
+use crate::config::Model;
+use crate::inference::{InferenceBackend, ModelMetadata, TensorSpec};
+use crate::metrics::{metrics, Stage};
+use crate::pb::tensorflow::error::Code as TfCode;
+use crate::pb::tensorflow::serving::model_service_client::ModelServiceClient;
+use crate::pb::tensorflow::serving::model_spec::VersionChoice;
+use crate::pb::tensorflow::serving::model_version_status::State;
+use crate::pb::tensorflow::serving::prediction_service_client::PredictionServiceClient;
+use crate::pb::tensorflow::serving::{
+    GetModelMetadataRequest, GetModelStatusRequest, ModelSpec, PredictRequest, SignatureDefMap,
+};
+use crate::pb::tensorflow::tensor_shape_proto::Dim;
+use crate::pb::tensorflow::{DataType, TensorInfo, TensorProto, TensorShapeProto};
+use crate::telemetry::{client_context, inject_metadata, record_result};
+use base64_simd::URL_SAFE;
+use opentelemetry::KeyValue;
+use prost::Message;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tonic::metadata::MetadataMap;
+use tonic::transport::{Channel, Endpoint};
+use tonic::{Request, Status};
+
+// GetModelMetadata中签名信息对应的字段名
+const SIGNATURE_DEF_FIELD: &str = "signature_def";
+
+// 通过gRPC发送时图片在DT_STRING张量中的编码方式，可以在模型的grpc_input_encoding中配置
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GrpcInputEncoding {
+    // 原始的图片字节，tf.io.decode_image可以直接解码
+    #[default]
+    Raw,
+    // URL安全的Base64字符串，用于与RESTful API使用相同输入的SavedModel
+    Base64,
+}
+
+// 通过TensorFlow Serving原生的gRPC接口发送请求，PredictionService和ModelService共用一个连接
+#[derive(Clone, Debug)]
+pub struct TfServingGrpcClient {
+    client: PredictionServiceClient<Channel>,
+    model_service: ModelServiceClient<Channel>,
+    // 每个请求附带的认证信息
+    metadata: MetadataMap,
+    retry_policies: Arc<RetryPolicies>,
+}
+
+impl TfServingGrpcClient {
+    // url形如http://localhost:8500，连接在第一次请求时才建立
+    pub fn new(url: &str, options: &ClientOptions) -> Result<Self, TfServingError> {
+        let headers = options
+            .auth
+            .header_map()
+            .map_err(TfServingError::InvalidConfig)?;
+        let channel = Endpoint::from_shared(url.to_string())?
+            .connect_timeout(Duration::from_millis(options.connect_timeout_ms))
+            .timeout(Duration::from_millis(options.request_timeout_ms))
+            .tcp_keepalive(Some(Duration::from_secs(60)))
+            .connect_lazy();
+
+        Ok(TfServingGrpcClient {
+            client: PredictionServiceClient::new(channel.clone()),
+            model_service: ModelServiceClient::new(channel),
+            metadata: MetadataMap::from_headers(headers),
+            retry_policies: Arc::new(RetryPolicies::new(options.retry.clone())),
+        })
+    }
+
+    // 替换按options.retry创建的重试策略
+    pub fn with_retry_policies(mut self, retry_policies: Arc<RetryPolicies>) -> Self {
+        self.retry_policies = retry_policies;
+        self
+    }
+
+    // 附带认证信息的请求
+    fn request<T>(&self, message: T) -> Request<T> {
+        let mut request = Request::new(message);
+        *request.metadata_mut() = self.metadata.clone();
+        request
+    }
+
+    // 每张图片作为DT_STRING张量中的一个元素发送，结果的顺序与inputs的顺序一致
+    // 与RESTful API一样为每次调用创建客户端span，并把当前的trace通过traceparent metadata传递下去
+    async fn predict_inputs(
+        &self,
+        model_name: &str,
+        version: i64,
+        input_name: &str,
+        inputs: Vec<Vec<u8>>,
+    ) -> Result<Vec<Vec<f32>>, TfServingError> {
+        let batch_size = inputs.len();
+        let tensor = TensorProto {
+            dtype: DataType::DtString as i32,
+            tensor_shape: Some(shape(&[batch_size as i64])),
+            string_val: inputs,
+            ..TensorProto::default()
+        };
+
+        let request = PredictRequest {
+            model_spec: Some(model_spec(model_name, version)),
+            inputs: HashMap::from([(input_name.to_string(), tensor)]),
+            output_filter: vec![],
+        };
+
+        let cx = client_context(
+            "tf_serving.predict",
+            vec![
+                KeyValue::new("rpc.system", "grpc"),
+                KeyValue::new("rpc.method", "tensorflow.serving.PredictionService/Predict"),
+                KeyValue::new("model", model_name.to_string()),
+                KeyValue::new("batch.size", batch_size as i64),
+            ],
+        );
+        let mut request = self.request(request);
+        inject_metadata(&cx, request.metadata_mut());
+
+        let start = Instant::now();
+        let response = self.client.clone().predict(request).await;
+        metrics().observe(model_name, Stage::Backend, start.elapsed());
+        record_result(&cx, &response);
+        let response = response?.into_inner();
+
+        // 模型只有一个输出时直接使用它
+        let mut outputs = response.outputs.into_values();
+        let output = match (outputs.next(), outputs.next()) {
+            (Some(output), None) => output,
+            _ => {
+                return Err(TfServingError::InvalidOutput(format!(
+                    "Expected exactly one output tensor from model {}",
+                    model_name
+                )))
+            }
+        };
+
+        output_rows(model_name, &output, batch_size)
+    }
+
+    // 模型的指定版本处于AVAILABLE状态并且没有错误时才认为可用
+    async fn get_model_status(&self, model_name: &str, version: i64) -> Result<(), TfServingError> {
+        let request = GetModelStatusRequest {
+            model_spec: Some(model_spec(model_name, version)),
+        };
+        let response = self
+            .model_service
+            .clone()
+            .get_model_status(self.request(request))
+            .await?
+            .into_inner();
+
+        let status = response
+            .model_version_status
+            .iter()
+            .find(|status| status.version == version)
+            .ok_or_else(|| {
+                TfServingError::InvalidOutput(format!(
+                    "Model {} version {} not found",
+                    model_name, version
+                ))
+            })?;
+
+        let error_code = status.status.as_ref().map(|s| s.error_code).unwrap_or(0);
+        if status.state != State::Available as i32 || error_code != TfCode::Ok as i32 {
+            let message = status
+                .status
+                .as_ref()
+                .map(|s| s.error_message.as_str())
+                .unwrap_or_default();
+            return Err(TfServingError::InvalidOutput(format!(
+                "Model {} version {} is not available: state {}, {}",
+                model_name,
+                version,
+                State::try_from(status.state)
+                    .map(|s| s.as_str_name())
+                    .unwrap_or("UNKNOWN"),
+                message
+            )));
+        }
+        Ok(())
+    }
+
+    // 获取模型默认签名的输入和输出
+    async fn get_model_metadata(
+        &self,
+        model_name: &str,
+        version: i64,
+    ) -> Result<ModelMetadata, TfServingError> {
+        let request = GetModelMetadataRequest {
+            model_spec: Some(model_spec(model_name, version)),
+            metadata_field: vec![SIGNATURE_DEF_FIELD.to_string()],
+        };
+        let mut response = self
+            .client
+            .clone()
+            .get_model_metadata(self.request(request))
+            .await?
+            .into_inner();
+
+        let invalid = |message: &str| {
+            TfServingError::InvalidOutput(format!("Model {} {}", model_name, message))
+        };
+        let any = response
+            .metadata
+            .remove(SIGNATURE_DEF_FIELD)
+            .ok_or_else(|| invalid("returned no signature_def metadata"))?;
+        let mut signatures = SignatureDefMap::decode(any.value.as_slice())
+            .map_err(|e| invalid(&format!("returned an invalid signature_def: {}", e)))?;
+        let signature = signatures
+            .signature_def
+            .remove(DEFAULT_SIGNATURE)
+            .ok_or_else(|| invalid(&format!("has no {} signature", DEFAULT_SIGNATURE)))?;
+
+        Ok(ModelMetadata {
+            inputs: tensor_specs(signature.inputs),
+            outputs: tensor_specs(signature.outputs),
+        })
+    }
+}
+
+// 默认发送原始的图片字节，省去Base64编码
+// 与RESTful API使用相同的重试策略，每次调用都重新复制一份输入
+#[tonic::async_trait]
+impl InferenceBackend for TfServingGrpcClient {
+    async fn predict_batch(
+        &self,
+        model: &Model,
+        images: &[&[u8]],
+    ) -> Result<Vec<Vec<f32>>, Status> {
+        let encoded: Option<Vec<Vec<u8>>> = match model.grpc_input_encoding.unwrap_or_default() {
+            GrpcInputEncoding::Raw => None,
+            GrpcInputEncoding::Base64 => {
+                let start = Instant::now();
+                let encoded = images
+                    .iter()
+                    .map(|image| URL_SAFE.encode_to_string(image).into_bytes())
+                    .collect();
+                metrics().observe(&model.name, Stage::Encode, start.elapsed());
+                Some(encoded)
+            }
+        };
+        let inputs = || match &encoded {
+            Some(encoded) => encoded.clone(),
+            None => images.iter().map(|image| image.to_vec()).collect(),
+        };
+        let policy = self.retry_policies.for_model(model);
+        let predictions = with_retry(&policy, &model.name, || {
+            self.predict_inputs(
+                &model.name,
+                model.version as i64,
+                &model.input_name,
+                inputs(),
+            )
+        })
+        .await?;
+        Ok(predictions)
+    }
+
+    async fn model_status(&self, model: &Model) -> Result<(), Status> {
+        self.get_model_status(&model.name, model.version as i64)
+            .await
+            .map_err(|e| Status::unavailable(e.to_string()))
+    }
+
+    async fn model_metadata(&self, model: &Model) -> Result<ModelMetadata, Status> {
+        let metadata = self
+            .get_model_metadata(&model.name, model.version as i64)
+            .await?;
+        Ok(metadata)
+    }
+}
+
+fn model_spec(model_name: &str, version: i64) -> ModelSpec {
+    ModelSpec {
+        name: model_name.to_string(),
+        version_choice: Some(VersionChoice::Version(version)),
+        ..ModelSpec::default()
+    }
+}
+
+fn tensor_specs(tensors: HashMap<String, TensorInfo>) -> HashMap<String, TensorSpec> {
+    tensors
+        .into_iter()
+        .map(|(name, info)| {
+            let dtype = DataType::try_from(info.dtype)
+                .map(|d| d.as_str_name().to_string())
+                .unwrap_or_else(|_| info.dtype.to_string());
+            let shape = info
+                .tensor_shape
+                .map(|s| s.dim.iter().map(|d| d.size).collect())
+                .unwrap_or_default();
+            (name, TensorSpec { dtype, shape })
+        })
+        .collect()
+}
+
+fn shape(dims: &[i64]) -> TensorShapeProto {
+    TensorShapeProto {
+        dim: dims
+            .iter()
+            .map(|&size| Dim {
+                size,
+                name: String::new(),
+            })
+            .collect(),
+        unknown_rank: false,
+    }
+}
+
+// 将形状为[batch_size, ...]的浮点张量拆分为每张图片一行
+fn output_rows(
+    model_name: &str,
+    tensor: &TensorProto,
+    batch_size: usize,
+) -> Result<Vec<Vec<f32>>, TfServingError> {
+    if tensor.dtype != DataType::DtFloat as i32 {
+        return Err(TfServingError::InvalidOutput(format!(
+            "Expected a DT_FLOAT output from model {}, got dtype {}",
+            model_name, tensor.dtype
+        )));
+    }
+
+    // TensorFlow Serving可能使用tensor_content以小端序存放原始数据
+    let values: Vec<f32> = if !tensor.tensor_content.is_empty() {
+        tensor
+            .tensor_content
+            .chunks_exact(4)
+            .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .collect()
+    } else {
+        tensor.float_val.clone()
+    };
+
+    let rows = tensor
+        .tensor_shape
+        .as_ref()
+        .and_then(|s| s.dim.first())
+        .map(|d| d.size)
+        .unwrap_or_default();
+    if rows != batch_size as i64 || batch_size == 0 || !values.len().is_multiple_of(batch_size) {
+        return Err(TfServingError::InvalidOutput(format!(
+            "Model {} returned an output of {} values with {} rows for {} images",
+            model_name,
+            values.len(),
+            rows,
+            batch_size
+        )));
+    }
+
+    let row_len = values.len() / batch_size;
+    if row_len == 0 {
+        return Ok(vec![vec![]; batch_size]);
+    }
+    Ok(values.chunks(row_len).map(|row| row.to_vec()).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pb::tensorflow::serving::model_service_server::{ModelService, ModelServiceServer};
+    use crate::pb::tensorflow::serving::prediction_service_server::{
+        PredictionService, PredictionServiceServer,
+    };
+    use crate::pb::tensorflow::serving::{
+        GetModelMetadataResponse, GetModelStatusResponse, ModelVersionStatus, PredictResponse,
+        StatusProto,
+    };
+    use crate::pb::tensorflow::SignatureDef;
+    use crate::tf_serving::retry::RetryOptions;
+    use opentelemetry::trace::{
+        FutureExt, SpanContext, SpanId, TraceContextExt, TraceFlags, TraceId, TraceState,
+    };
+    use opentelemetry::Context;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Mutex;
+    use tokio::net::TcpListener;
+    use tokio_stream::wrappers::TcpListenerStream;
+    use tonic::transport::Server;
+    use tonic::{Code, Request, Response, Status};
+
+    // TensorFlow Serving的替身：每个输入返回[输入长度, 版本号]，并记录每个预测请求的traceparent
+    // 模型unavailable总是返回UNAVAILABLE，并统计收到的请求数
+    #[derive(Clone, Default)]
+    struct FakePredictionService {
+        traceparents: Arc<Mutex<Vec<String>>>,
+        unavailable_calls: Arc<AtomicUsize>,
+    }
+
+    #[tonic::async_trait]
+    impl PredictionService for FakePredictionService {
+        async fn predict(
+            &self,
+            request: Request<PredictRequest>,
+        ) -> Result<Response<PredictResponse>, Status> {
+            if let Some(traceparent) = request.metadata().get("traceparent") {
+                let traceparent = traceparent.to_str().unwrap().to_string();
+                self.traceparents.lock().unwrap().push(traceparent);
+            }
+            let request = request.into_inner();
+            let spec = request.model_spec.unwrap();
+            if spec.name == "unavailable" {
+                self.unavailable_calls.fetch_add(1, Ordering::SeqCst);
+                return Err(Status::unavailable("Servable is loading"));
+            }
+            if spec.name != "foo" {
+                return Err(Status::not_found("Servable not found"));
+            }
+            let version = match spec.version_choice {
+                Some(VersionChoice::Version(v)) => v as f32,
+                _ => 0.0,
+            };
+
+            let input = &request.inputs["input"];
+            assert_eq!(input.dtype, DataType::DtString as i32);
+            let values: Vec<f32> = input
+                .string_val
+                .iter()
+                .flat_map(|s| [s.len() as f32, version])
+                .collect();
+            let output = TensorProto {
+                dtype: DataType::DtFloat as i32,
+                tensor_shape: Some(shape(&[input.string_val.len() as i64, 2])),
+                float_val: values,
+                ..TensorProto::default()
+            };
+
+            Ok(Response::new(PredictResponse {
+                model_spec: None,
+                outputs: HashMap::from([("output".to_string(), output)]),
+            }))
+        }
+
+        async fn get_model_metadata(
+            &self,
+            request: Request<GetModelMetadataRequest>,
+        ) -> Result<Response<GetModelMetadataResponse>, Status> {
+            let request = request.into_inner();
+            assert_eq!(request.metadata_field, vec![SIGNATURE_DEF_FIELD]);
+            let tensor = |dtype: DataType, dims: &[i64]| TensorInfo {
+                dtype: dtype as i32,
+                tensor_shape: Some(shape(dims)),
+                encoding: None,
+            };
+            let signatures = SignatureDefMap {
+                signature_def: HashMap::from([(
+                    DEFAULT_SIGNATURE.to_string(),
+                    SignatureDef {
+                        inputs: HashMap::from([(
+                            "input".to_string(),
+                            tensor(DataType::DtString, &[-1]),
+                        )]),
+                        outputs: HashMap::from([(
+                            "output".to_string(),
+                            tensor(DataType::DtFloat, &[-1, 2]),
+                        )]),
+                        ..SignatureDef::default()
+                    },
+                )]),
+            };
+            let any = prost_types::Any {
+                type_url: "type.googleapis.com/tensorflow.serving.SignatureDefMap".to_string(),
+                value: signatures.encode_to_vec(),
+            };
+
+            Ok(Response::new(GetModelMetadataResponse {
+                model_spec: request.model_spec,
+                metadata: HashMap::from([(SIGNATURE_DEF_FIELD.to_string(), any)]),
+            }))
+        }
+    }
+
+    // 模型foo的版本1可用，版本2仍在加载中
+    #[tonic::async_trait]
+    impl ModelService for FakePredictionService {
+        async fn get_model_status(
+            &self,
+            request: Request<GetModelStatusRequest>,
+        ) -> Result<Response<GetModelStatusResponse>, Status> {
+            let spec = request.into_inner().model_spec.unwrap();
+            let version = match spec.version_choice {
+                Some(VersionChoice::Version(v)) => v,
+                _ => 0,
+            };
+            let state = match version {
+                1 => State::Available,
+                _ => State::Loading,
+            };
+
+            Ok(Response::new(GetModelStatusResponse {
+                model_version_status: vec![ModelVersionStatus {
+                    version,
+                    state: state as i32,
+                    status: Some(StatusProto {
+                        error_code: TfCode::Ok as i32,
+                        error_message: String::new(),
+                    }),
+                }],
+            }))
+        }
+    }
+
+    async fn start_fake_server() -> String {
+        serve(FakePredictionService::default()).await
+    }
+
+    async fn serve(service: FakePredictionService) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(
+            Server::builder()
+                .add_service(PredictionServiceServer::new(service.clone()))
+                .add_service(ModelServiceServer::new(service))
+                .serve_with_incoming(TcpListenerStream::new(listener)),
+        );
+        format!("http://{}", addr)
+    }
+
+    // 测试通过gRPC发送多张图片并按顺序得到结果
+    #[tokio::test]
+    async fn test_grpc_predict_batch() {
+        let url = start_fake_server().await;
+        let client = TfServingGrpcClient::new(&url, &ClientOptions::default()).unwrap();
+
+        let rows = client
+            .predict_inputs("foo", 3, "input", vec![b"a".to_vec(), b"bbb".to_vec()])
+            .await
+            .unwrap();
+        assert_eq!(rows, vec![vec![1.0, 3.0], vec![3.0, 3.0]]);
+    }
+
+    // 测试默认发送原始的图片字节，配置为base64时发送Base64字符串
+    #[tokio::test]
+    async fn test_grpc_input_encoding() {
+        let url = start_fake_server().await;
+        let client = TfServingGrpcClient::new(&url, &ClientOptions::default()).unwrap();
+        let mut model = Model {
+            name: "foo".to_string(),
+            version: 1,
+            input_name: "input".to_string(),
+            ..Model::default()
+        };
+
+        let rows = client.predict_batch(&model, &[b"hello"]).await.unwrap();
+        assert_eq!(rows, vec![vec![5.0, 1.0]]);
+
+        model.grpc_input_encoding = Some(GrpcInputEncoding::Base64);
+        let rows = client.predict_batch(&model, &[b"hello"]).await.unwrap();
+        assert_eq!(rows, vec![vec!["aGVsbG8=".len() as f32, 1.0]]);
+    }
+
+    // 测试当前的trace通过traceparent metadata传递到TensorFlow Serving
+    #[tokio::test]
+    async fn test_grpc_trace_context_propagated() {
+        let service = FakePredictionService::default();
+        let url = serve(service.clone()).await;
+        let client = TfServingGrpcClient::new(&url, &ClientOptions::default()).unwrap();
+
+        let trace_id = TraceId::from_hex("0af7651916cd43dd8448eb211c80319c").unwrap();
+        let span_context = SpanContext::new(
+            trace_id,
+            SpanId::from_hex("b7ad6b7169203331").unwrap(),
+            TraceFlags::SAMPLED,
+            true,
+            TraceState::default(),
+        );
+        let cx = Context::new().with_remote_span_context(span_context);
+        client
+            .predict_inputs("foo", 1, "input", vec![b"a".to_vec()])
+            .with_context(cx)
+            .await
+            .unwrap();
+
+        let traceparents = service.traceparents.lock().unwrap();
+        assert_eq!(traceparents.len(), 1);
+        assert!(
+            traceparents[0].starts_with(&format!("00-{}-", trace_id)),
+            "{}",
+            traceparents[0]
+        );
+    }
+
+    // 测试UNAVAILABLE按模型的retry重试，其他错误不重试
+    #[tokio::test]
+    async fn test_grpc_retry() {
+        let service = FakePredictionService::default();
+        let url = serve(service.clone()).await;
+        let options = ClientOptions {
+            retry: RetryOptions {
+                initial_backoff_ms: 1,
+                ..RetryOptions::default()
+            },
+            ..ClientOptions::default()
+        };
+        let client = TfServingGrpcClient::new(&url, &options).unwrap();
+        let mut model = Model {
+            name: "unavailable".to_string(),
+            version: 1,
+            input_name: "input".to_string(),
+            ..Model::default()
+        };
+
+        let status = client.predict_batch(&model, &[b"a"]).await.unwrap_err();
+        assert_eq!(status.code(), Code::Unavailable);
+        assert_eq!(service.unavailable_calls.load(Ordering::SeqCst), 3);
+
+        model.retry = Some(RetryOptions {
+            max_attempts: 1,
+            ..RetryOptions::default()
+        });
+        client.predict_batch(&model, &[b"a"]).await.unwrap_err();
+        assert_eq!(service.unavailable_calls.load(Ordering::SeqCst), 4);
+
+        model.name = "bar".to_string();
+        model.retry = None;
+        let status = client.predict_batch(&model, &[b"a"]).await.unwrap_err();
+        assert_eq!(status.code(), Code::NotFound);
+    }
+
+    // 测试gRPC错误状态被透传
+    #[tokio::test]
+    async fn test_grpc_predict_error_status() {
+        let url = start_fake_server().await;
+        let client = TfServingGrpcClient::new(&url, &ClientOptions::default()).unwrap();
+
+        let err = client
+            .predict_inputs("bar", 1, "input", vec![b"a".to_vec()])
+            .await
+            .unwrap_err();
+        assert_eq!(err.code(), Code::NotFound);
+    }
+
+    // 测试无法连接时被映射为UNAVAILABLE
+    #[tokio::test]
+    async fn test_grpc_connection_refused() {
+        let addr = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let client =
+            TfServingGrpcClient::new(&format!("http://{}", addr), &ClientOptions::default())
+                .unwrap();
+
+        let err = client
+            .predict_inputs("foo", 1, "input", vec![b"a".to_vec()])
+            .await
+            .unwrap_err();
+        assert_eq!(err.code(), Code::Unavailable);
+    }
+
+    // 测试通过ModelService检查模型版本的状态
+    #[tokio::test]
+    async fn test_grpc_model_status() {
+        let url = start_fake_server().await;
+        let client = TfServingGrpcClient::new(&url, &ClientOptions::default()).unwrap();
+
+        client.get_model_status("foo", 1).await.unwrap();
+        let err = client.get_model_status("foo", 2).await.unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Model foo version 2 is not available: state LOADING, "
+        );
+    }
+
+    // 测试从GetModelMetadata中解析默认签名
+    #[tokio::test]
+    async fn test_grpc_model_metadata() {
+        let url = start_fake_server().await;
+        let client = TfServingGrpcClient::new(&url, &ClientOptions::default()).unwrap();
+
+        let metadata = client.get_model_metadata("foo", 1).await.unwrap();
+        assert_eq!(
+            metadata.inputs["input"],
+            TensorSpec {
+                dtype: "DT_STRING".to_string(),
+                shape: vec![-1]
+            }
+        );
+        assert_eq!(metadata.outputs["output"].shape, vec![-1, 2]);
+    }
+
+    // 测试tensor_content形式的输出
+    #[test]
+    fn test_output_rows_tensor_content() {
+        let values = [0.5f32, 1.5, 2.5, 3.5];
+        let tensor = TensorProto {
+            dtype: DataType::DtFloat as i32,
+            tensor_shape: Some(shape(&[2, 2])),
+            tensor_content: values.iter().flat_map(|v| v.to_le_bytes()).collect(),
+            ..TensorProto::default()
+        };
+        let rows = output_rows("foo", &tensor, 2).unwrap();
+        assert_eq!(rows, vec![vec![0.5, 1.5], vec![2.5, 3.5]]);
+    }
+
+    // 测试输出的行数与图片数量不一致时返回错误
+    #[test]
+    fn test_output_rows_shape_mismatch() {
+        let tensor = TensorProto {
+            dtype: DataType::DtFloat as i32,
+            tensor_shape: Some(shape(&[1, 2])),
+            float_val: vec![0.5, 1.5],
+            ..TensorProto::default()
+        };
+        let err = output_rows("foo", &tensor, 2).unwrap_err();
+        assert_eq!(err.code(), Code::Internal);
+    }
+}
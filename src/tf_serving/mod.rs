@@ -1,2 +1,10 @@
+pub mod backend;
+pub mod balancer;
+pub mod client;
+pub mod error;
+pub mod grpc_client;
+pub mod model_metadata;
 pub mod model_status;
 pub mod predict_service;
+pub mod retry;
+pub mod router;
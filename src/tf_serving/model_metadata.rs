@@ -0,0 +1,195 @@
+use super::error::TfServingError;
+use crate::inference::{ModelMetadata, TensorSpec};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+// 默认的签名名称
+pub const DEFAULT_SIGNATURE: &str = "serving_default";
+
+// 定义元数据响应的数据结构，与TensorFlow Serving的JSON格式保持一致
+#[derive(Serialize, Deserialize, Debug)]
+struct MetadataResponse {
+    metadata: Metadata,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct Metadata {
+    signature_def: SignatureDefMap,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct SignatureDefMap {
+    signature_def: HashMap<String, SignatureDef>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct SignatureDef {
+    #[serde(default)]
+    inputs: HashMap<String, TensorInfo>,
+    #[serde(default)]
+    outputs: HashMap<String, TensorInfo>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct TensorInfo {
+    dtype: String,
+    #[serde(default)]
+    tensor_shape: TensorShape,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+struct TensorShape {
+    #[serde(default)]
+    dim: Vec<Dim>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct Dim {
+    size: Int64,
+}
+
+// JSON中的int64会被编码为字符串，比如 "size": "-1"
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(untagged)]
+enum Int64 {
+    Number(i64),
+    Text(String),
+}
+
+impl Int64 {
+    fn value(&self) -> i64 {
+        match self {
+            Int64::Number(v) => *v,
+            Int64::Text(s) => s.parse().unwrap_or(-1),
+        }
+    }
+}
+
+fn tensor_specs(tensors: HashMap<String, TensorInfo>) -> HashMap<String, TensorSpec> {
+    tensors
+        .into_iter()
+        .map(|(name, info)| {
+            let spec = TensorSpec {
+                dtype: info.dtype,
+                shape: info
+                    .tensor_shape
+                    .dim
+                    .iter()
+                    .map(|d| d.size.value())
+                    .collect(),
+            };
+            (name, spec)
+        })
+        .collect()
+}
+
+// 获取模型默认签名的输入和输出
+pub async fn get_model_metadata(
+    client: &reqwest::Client,
+    url: &str,
+    model_name: &str,
+    version: &str,
+) -> Result<ModelMetadata, TfServingError> {
+    let url = format!(
+        "{}/models/{}/versions/{}/metadata",
+        url, model_name, version
+    );
+
+    let response = client.get(&url).send().await?;
+    if !response.status().is_success() {
+        return Err(TfServingError::Status {
+            action: "get metadata",
+            model_name: model_name.to_string(),
+            status: response.status(),
+        });
+    }
+
+    let mut response: MetadataResponse = response.json().await?;
+    let signature = response
+        .metadata
+        .signature_def
+        .signature_def
+        .remove(DEFAULT_SIGNATURE)
+        .ok_or_else(|| {
+            TfServingError::InvalidOutput(format!(
+                "Model {} has no {} signature",
+                model_name, DEFAULT_SIGNATURE
+            ))
+        })?;
+
+    Ok(ModelMetadata {
+        inputs: tensor_specs(signature.inputs),
+        outputs: tensor_specs(signature.outputs),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mockito::mock;
+
+    // 测试解析TensorFlow Serving返回的元数据
+    #[tokio::test]
+    async fn test_get_model_metadata() {
+        let _m = mock("GET", "/models/foo/versions/1/metadata")
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(
+                r#"{
+                    "model_spec": {"name": "foo", "signature_name": "", "version": "1"},
+                    "metadata": {"signature_def": {"signature_def": {
+                        "serving_default": {
+                            "inputs": {"b64_input_bytes": {
+                                "dtype": "DT_STRING",
+                                "tensor_shape": {"dim": [{"size": "-1", "name": ""}], "unknown_rank": false},
+                                "name": "serving_default_b64_input_bytes:0"
+                            }},
+                            "outputs": {"resnet_custom_v3": {
+                                "dtype": "DT_FLOAT",
+                                "tensor_shape": {"dim": [{"size": "-1", "name": ""}, {"size": "4096", "name": ""}], "unknown_rank": false},
+                                "name": "StatefulPartitionedCall:0"
+                            }},
+                            "method_name": "tensorflow/serving/predict"
+                        }
+                    }}}
+                }"#,
+            )
+            .create();
+
+        let metadata =
+            get_model_metadata(&reqwest::Client::new(), &mockito::server_url(), "foo", "1")
+                .await
+                .unwrap();
+        assert_eq!(
+            metadata.inputs["b64_input_bytes"],
+            TensorSpec {
+                dtype: "DT_STRING".to_string(),
+                shape: vec![-1]
+            }
+        );
+        assert_eq!(
+            metadata.outputs["resnet_custom_v3"],
+            TensorSpec {
+                dtype: "DT_FLOAT".to_string(),
+                shape: vec![-1, 4096]
+            }
+        );
+    }
+
+    // 测试没有默认签名时返回错误
+    #[tokio::test]
+    async fn test_get_model_metadata_no_default_signature() {
+        let _m = mock("GET", "/models/foo/versions/1/metadata")
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(r#"{"metadata": {"signature_def": {"signature_def": {}}}}"#)
+            .create();
+
+        let result =
+            get_model_metadata(&reqwest::Client::new(), &mockito::server_url(), "foo", "1").await;
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Model foo has no serving_default signature"
+        );
+    }
+}
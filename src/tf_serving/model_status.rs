@@ -4,6 +4,9 @@ use anyhow::{anyhow, Context, Result};
 use std::error::Error as StdError;
 use std::fmt;
 
+// 模型可以处理请求时的状态
+const AVAILABLE: &str = "AVAILABLE";
+
 // 定义一个结构体，用于表示响应的Json数据
 #[derive(Serialize, Deserialize, Debug)]
 struct Response {
@@ -30,8 +33,8 @@ struct Status {
 }
 
 // 定义一个异步函数，接受模型名称和版本，以及可选的标签
-#[allow(dead_code)]
 async fn get_response(
+    client: &reqwest::Client,
     url: &str,
     model_name: &str,
     version: &str,
@@ -47,7 +50,7 @@ async fn get_response(
     };
 
     // 发送GET请求，并等待响应
-    let response = reqwest::get(&url).await?;
+    let response = client.get(&url).send().await?;
 
     // 判断响应是否为成功的状态码
     if response.status().is_success() {
@@ -69,15 +72,15 @@ async fn get_response(
 }
 
 // 定义一个函数，接受模型名称和版本，以及可选的标签
-#[allow(dead_code)]
-async fn get_model_status(
+pub async fn get_model_status(
+    client: &reqwest::Client,
     url: &str,
     model_name: &str,
     version: &str,
     label: Option<&str>,
 ) -> Result<()> {
     // 调用异步函数get_response，并等待结果
-    let response = get_response(url, model_name, version, label)
+    let response = get_response(client, url, model_name, version, label)
         .await // 等待异步函数完成
         .context(format!(
             // 添加上下文信息
@@ -86,9 +89,18 @@ async fn get_model_status(
         ))?;
 
     // 检查响应是否为符合结构体
-    if let Some(model_version_status) = response.model_version_status.get(0) {
+    if let Some(model_version_status) = response.model_version_status.first() {
         // 检查status.error_code是否为OK
         if model_version_status.status.error_code == "OK" {
+            // 模型仍在加载或正在卸载时也不能处理请求
+            if model_version_status.state != AVAILABLE {
+                return Err(anyhow!(
+                    "Model {} version {} is {}",
+                    model_name,
+                    version,
+                    model_version_status.state
+                ));
+            }
             // 返回成功的结果
             Ok(())
         } else {
@@ -167,9 +179,15 @@ mod tests {
             .create();
 
         // 调用get_response函数，并传入模型名称和版本
-        let response = get_response(&mockito::server_url(), "foo", "1", None)
-            .await
-            .unwrap();
+        let response = get_response(
+            &reqwest::Client::new(),
+            &mockito::server_url(),
+            "foo",
+            "1",
+            None,
+        )
+        .await
+        .unwrap();
 
         // 检查响应是否符合预期
         assert_eq!(response.model_version_status.len(), 1);
@@ -222,9 +240,23 @@ mod tests {
             .create();
 
         // 调用get_model_status函数，并传入模型名称和版本
-        let result1 = get_model_status(&mockito::server_url(), "foo", "1", None).await;
-
-        let result2 = get_model_status(&mockito::server_url(), "bar", "2", None).await;
+        let result1 = get_model_status(
+            &reqwest::Client::new(),
+            &mockito::server_url(),
+            "foo",
+            "1",
+            None,
+        )
+        .await;
+
+        let result2 = get_model_status(
+            &reqwest::Client::new(),
+            &mockito::server_url(),
+            "bar",
+            "2",
+            None,
+        )
+        .await;
 
         // 检查结果是否符合预期
         assert!(result1.is_ok());
@@ -235,6 +267,42 @@ mod tests {
         );
     }
 
+    // 测试模型仍在加载时返回错误
+    #[tokio::test]
+    async fn test_get_model_status_loading() {
+        let _m = mock("GET", "/models/foo/versions/3")
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(
+                r#"{
+                    "model_version_status": [
+                        {
+                            "state": "LOADING",
+                            "status": {
+                                "error_code": "OK",
+                                "error_message": ""
+                            },
+                            "version": "3"
+                        }
+                    ]
+                }"#,
+            )
+            .create();
+
+        let result = get_model_status(
+            &reqwest::Client::new(),
+            &mockito::server_url(),
+            "foo",
+            "3",
+            None,
+        )
+        .await;
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Model foo version 3 is LOADING"
+        );
+    }
+
     // 测试当请求成功但返回无效的JSON数据时，是否返回错误信息和上下文
     #[tokio::test]
     async fn test_get_response_invalid_json() {
@@ -259,7 +327,14 @@ mod tests {
             .create();
 
         // 调用get_response函数，并传入模型名称和版本
-        let response = get_response(&mockito::server_url(), "foo", "1", None).await;
+        let response = get_response(
+            &reqwest::Client::new(),
+            &mockito::server_url(),
+            "foo",
+            "1",
+            None,
+        )
+        .await;
 
         // 检查结果是否为错误
         assert!(response.is_err());
@@ -281,7 +356,7 @@ mod tests {
 
         let url = &mockito::server_url();
         // 调用get_response函数，并传入模型名称和版本
-        let response = get_response(url, "foo", "1", None).await;
+        let response = get_response(&reqwest::Client::new(), url, "foo", "1", None).await;
 
         // 检查结果是否为错误
         assert!(response.is_err());
@@ -317,9 +392,23 @@ mod tests {
             .create();
 
         // 调用get_model_status函数，并传入模型名称和版本
-        let result1 = get_model_status(&mockito::server_url(), "foo", "1", None).await;
-
-        let result2 = get_model_status(&mockito::server_url(), "bar", "2", None).await;
+        let result1 = get_model_status(
+            &reqwest::Client::new(),
+            &mockito::server_url(),
+            "foo",
+            "1",
+            None,
+        )
+        .await;
+
+        let result2 = get_model_status(
+            &reqwest::Client::new(),
+            &mockito::server_url(),
+            "bar",
+            "2",
+            None,
+        )
+        .await;
 
         // 检查结果是否为错误，并包含上下文信息
         assert!(result1.is_err());
@@ -358,7 +447,14 @@ mod tests {
             .create();
 
         // 调用get_model_status函数，并传入模型名称和版本
-        let result = get_model_status(&mockito::server_url(), "foo", "1", None).await;
+        let result = get_model_status(
+            &reqwest::Client::new(),
+            &mockito::server_url(),
+            "foo",
+            "1",
+            None,
+        )
+        .await;
 
         // 检查结果是否为自定义错误，并包含错误码和消息
         assert!(result.is_err());
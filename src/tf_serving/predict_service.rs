@@ -1,31 +1,82 @@
-use anyhow::{anyhow, Result};
-
+use super::error::TfServingError;
+use super::retry::{with_retry, RetryPolicy};
+use crate::metrics::{metrics, Stage};
+use crate::telemetry::{client_context, inject_headers, record_result};
+use opentelemetry::KeyValue;
+use reqwest::header::HeaderMap;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::time::Instant;
 
-// 用于发送Base64编码的图像预测请求并获取图像特征向量
-// 因为只能传一张图片，所以结果固定是数量为1的Vec<f32>数组，比如说 vec![vec![0.1,0.2,0.3]]
-pub async fn predict(
+// 在一次请求中发送多张Base64编码的图像，结果的顺序与images_base64的顺序一致
+// 无论成功与否都记录与TensorFlow Serving之间往返的耗时，并把当前的trace通过traceparent请求头传递下去
+pub async fn predict_batch(
+    client: &reqwest::Client,
     url: &str,
     model_name: &str,
     version: &str,
     input_name: &str,
-    image_base64: &str,
-) -> Result<Vec<Vec<f32>>> {
+    images_base64: &[&str],
+) -> Result<Vec<Vec<f32>>, TfServingError> {
     // 构造 API URL
     let url = format!("{}/models/{}/versions/{}:predict", url, model_name, version);
 
-    // 构造 POST 请求的 JSON 数据
+    let cx = client_context(
+        "tf_serving.predict",
+        vec![
+            KeyValue::new("http.method", "POST"),
+            KeyValue::new("http.url", url.clone()),
+            KeyValue::new("model", model_name.to_string()),
+            KeyValue::new("batch.size", images_base64.len() as i64),
+        ],
+    );
+    let mut headers = HeaderMap::new();
+    inject_headers(&cx, &mut headers);
+
+    let start = Instant::now();
+    let result = send_predict(client, &url, headers, model_name, input_name, images_base64).await;
+    metrics().observe(model_name, Stage::Backend, start.elapsed());
+    record_result(&cx, &result);
+    result
+}
+
+// 按重试策略调用predict_batch，暂时性的错误在等待一段时间后重试，每次重试都是一个新的span
+// 达到最大调用次数或重试预算不足时返回最后一次的错误
+pub async fn predict_batch_with_retry(
+    client: &reqwest::Client,
+    policy: &RetryPolicy,
+    url: &str,
+    model_name: &str,
+    version: &str,
+    input_name: &str,
+    images_base64: &[&str],
+) -> Result<Vec<Vec<f32>>, TfServingError> {
+    with_retry(policy, model_name, || {
+        predict_batch(client, url, model_name, version, input_name, images_base64)
+    })
+    .await
+}
+
+async fn send_predict(
+    client: &reqwest::Client,
+    url: &str,
+    headers: HeaderMap,
+    model_name: &str,
+    input_name: &str,
+    images_base64: &[&str],
+) -> Result<Vec<Vec<f32>>, TfServingError> {
+    // 构造 POST 请求的 JSON 数据，每张图片对应一个instance
     let request_data = PredctionRequest {
-        instances: vec![HashMap::from([(
-            input_name.to_string(),
-            image_base64.to_string(),
-        )])],
+        instances: images_base64
+            .iter()
+            .map(|image_base64| HashMap::from([(input_name.to_string(), image_base64.to_string())]))
+            .collect(),
     };
 
     // 发送 POST 请求，并等待响应
-    let response = reqwest::Client::new()
-        .post(&url)
+    let response = client
+        .post(url)
+        .headers(headers)
         .json(&request_data)
         .send()
         .await?;
@@ -38,12 +89,12 @@ pub async fn predict(
         // 返回预测结果
         Ok(model_response.predictions)
     } else {
-        // 返回自定义错误信息，并附加状态码和 URL
-        Err(anyhow!(
-            "Failed to predict for model {}: status code {}",
-            model_name,
-            response.status()
-        ))
+        // 返回自定义错误信息，并附加状态码
+        Err(TfServingError::Status {
+            action: "predict",
+            model_name: model_name.to_string(),
+            status: response.status(),
+        })
     }
 }
 
@@ -62,7 +113,43 @@ struct PredctionResponse {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use mockito::mock;
+    use crate::tf_serving::retry::RetryOptions;
+    use mockito::{mock, Mock};
+
+    // 重试前只等待1毫秒，避免测试变慢
+    fn fast_retry(max_attempts: u32) -> RetryPolicy {
+        RetryPolicy::new(RetryOptions {
+            max_attempts,
+            initial_backoff_ms: 1,
+            ..RetryOptions::default()
+        })
+    }
+
+    // 按顺序返回的一个响应，mockito在前一个mock的次数用完后才匹配下一个
+    fn scripted(path: &str, status: usize, body: &str) -> Mock {
+        mock("POST", path)
+            .with_status(status)
+            .with_header("content-type", "application/json")
+            .with_body(body)
+            .expect(1)
+            .create()
+    }
+
+    async fn predict_with_retry(
+        policy: &RetryPolicy,
+        model_name: &str,
+    ) -> Result<Vec<Vec<f32>>, TfServingError> {
+        predict_batch_with_retry(
+            &reqwest::Client::new(),
+            policy,
+            &mockito::server_url(),
+            model_name,
+            "1",
+            "b64_input_bytes",
+            &["some_base64_string"],
+        )
+        .await
+    }
 
     // 测试当请求成功并返回有效的JSON数据时，是否能正确解析预测结果
     #[tokio::test]
@@ -78,13 +165,14 @@ mod tests {
             )
             .create();
 
-        // 调用predict函数，并传入模型名称、版本、输入名称和图片Base64编码
-        let result = predict(
+        // 调用predict_batch函数，只发送一张图片的Base64编码
+        let result = predict_batch(
+            &reqwest::Client::new(),
             &mockito::server_url(),
             "foo",
             "1",
             "b64_input_bytes",
-            "some_base64_string",
+            &["some_base64_string"],
         )
         .await;
 
@@ -103,13 +191,14 @@ mod tests {
             .with_body("Not Found")
             .create();
 
-        // 调用predict函数，并传入模型名称、版本、输入名称和图片Base64编码
-        let result = predict(
+        // 调用predict_batch函数，只发送一张图片的Base64编码
+        let result = predict_batch(
+            &reqwest::Client::new(),
             &mockito::server_url(),
             "bar",
             "2",
             "b64_input_bytes",
-            "some_base64_string",
+            &["some_base64_string"],
         )
         .await;
 
@@ -135,13 +224,14 @@ mod tests {
             )
             .create();
 
-        // 调用predict函数，并传入模型名称、版本、输入名称和图片Base64编码
-        let result = predict(
+        // 调用predict_batch函数，只发送一张图片的Base64编码
+        let result = predict_batch(
+            &reqwest::Client::new(),
             &mockito::server_url(),
             "foo",
             "1",
             "b64_input_bytes",
-            "some_base64_string",
+            &["some_base64_string"],
         )
         .await;
 
@@ -152,4 +242,80 @@ mod tests {
             "error decoding response body: expected `,` or `]` at line 3 column 17"
         );
     }
+
+    // 测试暂时性的错误被重试，直到TensorFlow Serving返回成功
+    #[tokio::test]
+    async fn test_retry_until_success() {
+        let path = "/models/retry_ok/versions/1:predict";
+        let unavailable = scripted(path, 503, "");
+        let throttled = scripted(path, 429, "");
+        let ok = scripted(path, 200, r#"{"predictions": [[0.5, 0.6]]}"#);
+
+        let result = predict_with_retry(&fast_retry(3), "retry_ok").await;
+        assert_eq!(result.unwrap(), vec![vec![0.5, 0.6]]);
+        unavailable.assert();
+        throttled.assert();
+        ok.assert();
+    }
+
+    // 测试达到最大调用次数后返回最后一次的错误
+    #[tokio::test]
+    async fn test_retry_exhausted() {
+        let path = "/models/retry_exhausted/versions/1:predict";
+        let bad_gateway = scripted(path, 502, "");
+        let timeout = scripted(path, 504, "");
+        let unavailable = scripted(path, 503, "");
+
+        let result = predict_with_retry(&fast_retry(3), "retry_exhausted").await;
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Failed to predict for model retry_exhausted: status code 503 Service Unavailable"
+        );
+        bad_gateway.assert();
+        timeout.assert();
+        unavailable.assert();
+    }
+
+    // 测试400、404和无法解析的响应不会被重试
+    #[tokio::test]
+    async fn test_permanent_errors_not_retried() {
+        for (model_name, status, body) in [
+            ("retry_bad_request", 400, ""),
+            ("retry_not_found", 404, ""),
+            ("retry_invalid_json", 200, r#"{"predictions": ["#),
+        ] {
+            let path = format!("/models/{}/versions/1:predict", model_name);
+            let failed = scripted(&path, status, body);
+            let ok = scripted(&path, 200, r#"{"predictions": [[0.1]]}"#).expect(0);
+
+            let result = predict_with_retry(&fast_retry(3), model_name).await;
+            assert!(result.is_err(), "model {}", model_name);
+            failed.assert();
+            ok.assert();
+        }
+    }
+
+    // 测试重试预算耗尽后失败的请求不再重试
+    #[tokio::test]
+    async fn test_retry_budget_exhausted() {
+        let path = "/models/retry_budget/versions/1:predict";
+        let policy = RetryPolicy::new(RetryOptions {
+            max_attempts: 3,
+            initial_backoff_ms: 1,
+            budget_tokens: 4.0,
+            ..RetryOptions::default()
+        });
+
+        // 第一个请求失败后重试一次，第二次失败时令牌只剩一半，不再重试
+        let unavailable = mock("POST", path).with_status(503).expect(2).create();
+        let result = predict_with_retry(&policy, "retry_budget").await;
+        assert!(result.is_err());
+        unavailable.assert();
+
+        // 预算不足时第二个请求只调用一次
+        let unavailable = mock("POST", path).with_status(503).expect(1).create();
+        let result = predict_with_retry(&policy, "retry_budget").await;
+        assert!(result.is_err());
+        unavailable.assert();
+    }
 }
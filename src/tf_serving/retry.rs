@@ -0,0 +1,274 @@
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use super::error::TfServingError;
+use crate::config::Model;
+use crate::metrics::metrics;
+
+// 调用TensorFlow Serving失败后的重试参数，可以在config.yaml的tf_serving.retry部分配置，
+// 也可以在每个模型的retry部分单独配置
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct RetryOptions {
+    // 包括第一次调用在内最多调用的次数，1表示不重试
+    pub max_attempts: u32,
+    // 第一次重试前等待的时间（毫秒），之后每次乘以multiplier
+    pub initial_backoff_ms: u64,
+    // 两次调用之间最多等待的时间（毫秒）
+    pub max_backoff_ms: u64,
+    pub multiplier: f64,
+    // 等待时间随机减少的比例，0到1之间，避免大量请求同时重试
+    pub jitter: f64,
+    // 重试预算的令牌数，每次可重试的失败消耗一个令牌，令牌不超过一半时不再重试
+    pub budget_tokens: f64,
+    // 每次成功的调用归还的令牌数
+    pub budget_token_ratio: f64,
+}
+
+impl Default for RetryOptions {
+    fn default() -> Self {
+        RetryOptions {
+            max_attempts: 3,
+            initial_backoff_ms: 50,
+            max_backoff_ms: 1_000,
+            multiplier: 2.0,
+            jitter: 0.2,
+            budget_tokens: 10.0,
+            budget_token_ratio: 0.1,
+        }
+    }
+}
+
+// 每个模型的重试策略，模型的retry覆盖默认的重试参数
+#[derive(Debug)]
+pub struct RetryPolicies {
+    default: RetryOptions,
+    // 为false时忽略模型的retry
+    per_model: bool,
+    // 模型名称对应的重试策略，重试参数变化时替换为新的策略
+    policies: Mutex<HashMap<String, Arc<RetryPolicy>>>,
+}
+
+impl RetryPolicies {
+    pub fn new(default: RetryOptions) -> Self {
+        RetryPolicies {
+            default,
+            per_model: true,
+            policies: Mutex::new(HashMap::new()),
+        }
+    }
+
+    // 每次只调用一次，用于负载均衡中的实例，重试在负载均衡之上进行
+    pub fn disabled() -> Self {
+        RetryPolicies {
+            default: RetryOptions {
+                max_attempts: 1,
+                ..RetryOptions::default()
+            },
+            per_model: false,
+            policies: Mutex::new(HashMap::new()),
+        }
+    }
+
+    // 同一个模型的所有请求共享重试预算，配置热加载后模型的重试参数可能变化
+    pub fn for_model(&self, model: &Model) -> Arc<RetryPolicy> {
+        let options = match self.per_model {
+            true => model.retry.as_ref().unwrap_or(&self.default),
+            false => &self.default,
+        };
+        let mut policies = self.policies.lock().unwrap();
+        match policies.get(&model.name) {
+            Some(policy) if policy.options() == options => Arc::clone(policy),
+            _ => {
+                let policy = Arc::new(RetryPolicy::new(options.clone()));
+                policies.insert(model.name.clone(), Arc::clone(&policy));
+                policy
+            }
+        }
+    }
+}
+
+// 一个模型的重试策略，重试预算在该模型的所有请求之间共享
+#[derive(Debug)]
+pub struct RetryPolicy {
+    options: RetryOptions,
+    tokens: Mutex<f64>,
+}
+
+impl RetryPolicy {
+    pub fn new(options: RetryOptions) -> Self {
+        RetryPolicy {
+            tokens: Mutex::new(options.budget_tokens),
+            options,
+        }
+    }
+
+    pub fn options(&self) -> &RetryOptions {
+        &self.options
+    }
+
+    // 第attempt次调用失败后是否重试，可以重试时返回等待的时间
+    // 无论是否重试，可重试的失败都会消耗预算
+    pub fn should_retry(&self, attempt: u32, err: &TfServingError) -> Option<Duration> {
+        if !err.is_retryable() {
+            return None;
+        }
+        self.consume(attempt)
+    }
+
+    // 第attempt次调用以可重试的错误失败，消耗预算，没有超过最大调用次数并且预算充足时返回等待的时间
+    pub fn consume(&self, attempt: u32) -> Option<Duration> {
+        let mut tokens = self.tokens.lock().unwrap();
+        *tokens = (*tokens - 1.0).max(0.0);
+        if attempt >= self.options.max_attempts || *tokens <= self.options.budget_tokens / 2.0 {
+            return None;
+        }
+        Some(self.backoff(attempt, fastrand::f64()))
+    }
+
+    // 成功的调用归还一部分预算
+    pub fn record_success(&self) {
+        let mut tokens = self.tokens.lock().unwrap();
+        *tokens = (*tokens + self.options.budget_token_ratio).min(self.options.budget_tokens);
+    }
+
+    // 第attempt次调用失败后的等待时间，random在0到1之间
+    fn backoff(&self, attempt: u32, random: f64) -> Duration {
+        let options = &self.options;
+        let backoff = options.initial_backoff_ms as f64
+            * options.multiplier.powi(attempt.saturating_sub(1) as i32);
+        let backoff = backoff.min(options.max_backoff_ms as f64);
+        let jitter = options.jitter.clamp(0.0, 1.0) * random;
+        Duration::from_secs_f64(backoff * (1.0 - jitter) / 1000.0)
+    }
+}
+
+// 按重试策略调用call，暂时性的错误在等待一段时间后重试
+// 达到最大调用次数或重试预算不足时返回最后一次的错误
+pub async fn with_retry<T, F, Fut>(
+    policy: &RetryPolicy,
+    model_name: &str,
+    mut call: F,
+) -> Result<T, TfServingError>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, TfServingError>>,
+{
+    let mut attempt = 1;
+    loop {
+        let err = match call().await {
+            Ok(result) => {
+                policy.record_success();
+                return Ok(result);
+            }
+            Err(e) => e,
+        };
+        let backoff = match policy.should_retry(attempt, &err) {
+            Some(backoff) => backoff,
+            None => return Err(err),
+        };
+        warn!(
+            "Attempt {} to predict for model {} failed, retrying in {:?}: {}",
+            attempt, model_name, backoff, err
+        );
+        metrics().retry(model_name);
+        tokio::time::sleep(backoff).await;
+        attempt += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::StatusCode;
+
+    fn status_error(status: StatusCode) -> TfServingError {
+        TfServingError::Status {
+            action: "predict",
+            model_name: "foo".to_string(),
+            status,
+        }
+    }
+
+    // 测试等待时间按指数增长、不超过上限，并在jitter范围内随机减少
+    #[test]
+    fn test_backoff() {
+        let policy = RetryPolicy::new(RetryOptions {
+            initial_backoff_ms: 100,
+            max_backoff_ms: 500,
+            multiplier: 2.0,
+            jitter: 0.5,
+            ..RetryOptions::default()
+        });
+        assert_eq!(policy.backoff(1, 0.0), Duration::from_millis(100));
+        assert_eq!(policy.backoff(2, 0.0), Duration::from_millis(200));
+        assert_eq!(policy.backoff(3, 0.0), Duration::from_millis(400));
+        assert_eq!(policy.backoff(4, 0.0), Duration::from_millis(500));
+        assert_eq!(policy.backoff(2, 1.0), Duration::from_millis(100));
+    }
+
+    // 测试只有可重试的错误才会重试，并且不超过最大调用次数
+    #[test]
+    fn test_should_retry() {
+        let policy = RetryPolicy::new(RetryOptions::default());
+        let unavailable = status_error(StatusCode::SERVICE_UNAVAILABLE);
+        assert!(policy.should_retry(1, &unavailable).is_some());
+        assert!(policy.should_retry(2, &unavailable).is_some());
+        assert!(policy.should_retry(3, &unavailable).is_none());
+
+        assert!(policy
+            .should_retry(1, &status_error(StatusCode::BAD_REQUEST))
+            .is_none());
+        assert!(policy
+            .should_retry(1, &status_error(StatusCode::NOT_FOUND))
+            .is_none());
+    }
+
+    // 测试重试预算：连续失败耗尽一半的令牌后不再重试，成功的调用逐渐恢复预算
+    #[test]
+    fn test_retry_budget() {
+        let policy = RetryPolicy::new(RetryOptions {
+            budget_tokens: 4.0,
+            budget_token_ratio: 0.5,
+            ..RetryOptions::default()
+        });
+        let unavailable = status_error(StatusCode::BAD_GATEWAY);
+        assert!(policy.should_retry(1, &unavailable).is_some());
+        assert!(policy.should_retry(1, &unavailable).is_none());
+        assert!(policy.should_retry(1, &unavailable).is_none());
+
+        // 令牌从1恢复到3.5，消耗一个后仍超过一半，可以再次重试
+        for _ in 0..5 {
+            policy.record_success();
+        }
+        assert!(policy.should_retry(1, &unavailable).is_some());
+    }
+
+    // 测试同一个模型共用一个策略，模型的retry覆盖默认参数，参数变化后使用新的策略
+    #[test]
+    fn test_policies_for_model() {
+        let policies = RetryPolicies::new(RetryOptions::default());
+        let mut model = Model {
+            name: "foo".to_string(),
+            ..Model::default()
+        };
+        let policy = policies.for_model(&model);
+        assert!(Arc::ptr_eq(&policy, &policies.for_model(&model)));
+        assert_eq!(policy.options().max_attempts, 3);
+
+        model.retry = Some(RetryOptions {
+            max_attempts: 5,
+            ..RetryOptions::default()
+        });
+        let changed = policies.for_model(&model);
+        assert!(!Arc::ptr_eq(&policy, &changed));
+        assert_eq!(changed.options().max_attempts, 5);
+
+        let disabled = RetryPolicies::disabled();
+        assert_eq!(disabled.for_model(&model).options().max_attempts, 1);
+    }
+}
@@ -0,0 +1,223 @@
+use log::info;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tonic::Status;
+
+use super::backend::new_balanced_backend;
+use super::client::ClientOptions;
+use super::error::TfServingError;
+use crate::config::Model;
+use crate::inference::{InferenceBackend, ModelMetadata};
+
+// 模型自己的地址、超时时间和认证信息覆盖全局的客户端参数
+fn model_options(model: &Model, global: &ClientOptions) -> Option<ClientOptions> {
+    if model.endpoints.is_none() && model.request_timeout_ms.is_none() && model.auth.is_none() {
+        return None;
+    }
+    let mut options = global.clone();
+    if let Some(endpoints) = &model.endpoints {
+        options.endpoints = endpoints.clone();
+    }
+    if let Some(timeout) = model.request_timeout_ms {
+        options.request_timeout_ms = timeout;
+    }
+    if let Some(auth) = &model.auth {
+        options.auth = auth.clone();
+    }
+    Some(options)
+}
+
+// 模型自己的客户端参数和据此创建的推理服务
+type ModelBackend = (ClientOptions, Arc<dyn InferenceBackend>);
+
+// 把每个模型的请求发送到它所在的TensorFlow Serving实例：
+// 没有自己的客户端参数的模型共用全局的推理服务，其他模型在第一次请求时创建自己的推理服务，
+// 配置热加载后参数变化时重新创建
+pub struct ModelRouter {
+    default: Arc<dyn InferenceBackend>,
+    // 命令行参数已经覆盖过的全局客户端参数
+    options: ClientOptions,
+    backends: Mutex<HashMap<String, ModelBackend>>,
+}
+
+impl ModelRouter {
+    pub fn new(options: ClientOptions) -> Result<Self, TfServingError> {
+        Ok(ModelRouter {
+            default: new_balanced_backend(&options.endpoints, &options)?,
+            options,
+            backends: Mutex::new(HashMap::new()),
+        })
+    }
+
+    // 模型使用的推理服务
+    fn backend(&self, model: &Model) -> Result<Arc<dyn InferenceBackend>, TfServingError> {
+        let options = match model_options(model, &self.options) {
+            Some(options) => options,
+            None => return Ok(Arc::clone(&self.default)),
+        };
+        let mut backends = self.backends.lock().unwrap();
+        if let Some((current, backend)) = backends.get(&model.name) {
+            if *current == options {
+                return Ok(Arc::clone(backend));
+            }
+        }
+        info!(
+            "model {} uses TF Serving endpoints {:?}",
+            model.name, options.endpoints
+        );
+        let backend = new_balanced_backend(&options.endpoints, &options)?;
+        backends.insert(model.name.clone(), (options, Arc::clone(&backend)));
+        Ok(backend)
+    }
+
+    // 配置热加载后释放已经删除的模型、不再有自己的客户端参数的模型以及参数已经变化的模型的推理服务
+    pub fn retain(&self, models: &[Model]) {
+        let mut backends = self.backends.lock().unwrap();
+        backends.retain(|name, (current, _)| {
+            models
+                .iter()
+                .filter(|model| &model.name == name)
+                .any(|model| model_options(model, &self.options).as_ref() == Some(current))
+        });
+    }
+}
+
+#[tonic::async_trait]
+impl InferenceBackend for ModelRouter {
+    async fn predict_batch(
+        &self,
+        model: &Model,
+        images: &[&[u8]],
+    ) -> Result<Vec<Vec<f32>>, Status> {
+        self.backend(model)?.predict_batch(model, images).await
+    }
+
+    async fn model_status(&self, model: &Model) -> Result<(), Status> {
+        self.backend(model)?.model_status(model).await
+    }
+
+    async fn model_metadata(&self, model: &Model) -> Result<ModelMetadata, Status> {
+        self.backend(model)?.model_metadata(model).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tf_serving::client::AuthOptions;
+    use mockito::mock;
+    use std::time::Duration;
+    use tokio::net::TcpListener;
+    use tonic::Code;
+
+    // 没有监听的端口，发送到这里的请求都会失败
+    async fn closed_addr() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        format!("http://{}", addr)
+    }
+
+    fn test_model(name: &str) -> Model {
+        Model {
+            name: name.to_string(),
+            version: 1,
+            input_name: "input".to_string(),
+            ..Model::default()
+        }
+    }
+
+    // 测试模型被发送到自己的TensorFlow Serving实例并附带自己的认证信息，其他模型使用全局的实例
+    #[tokio::test]
+    async fn test_model_routed_to_own_endpoint() {
+        let router = ModelRouter::new(ClientOptions {
+            endpoints: vec![closed_addr().await],
+            ..ClientOptions::default()
+        })
+        .unwrap();
+
+        let routed = Model {
+            endpoints: Some(vec![mockito::server_url()]),
+            auth: Some(AuthOptions {
+                bearer_token: Some("routed-token".to_string()),
+                ..AuthOptions::default()
+            }),
+            ..test_model("routed_model")
+        };
+        let _m = mock("POST", "/models/routed_model/versions/1:predict")
+            .match_header("authorization", "Bearer routed-token")
+            .with_status(200)
+            .with_body(r#"{"predictions": [[0.5, 0.25]]}"#)
+            .create();
+
+        let prediction = router.predict(&routed, b"hello").await.unwrap();
+        assert_eq!(prediction, vec![0.5, 0.25]);
+
+        let status = router
+            .predict(&test_model("unrouted_model"), b"hello")
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), Code::Unavailable);
+    }
+
+    // 测试模型自己的超时时间，以及参数变化后重新创建推理服务
+    #[tokio::test]
+    async fn test_model_request_timeout() {
+        // 只接受连接但从不响应的服务器
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let mut sockets = vec![];
+            while let Ok((socket, _)) = listener.accept().await {
+                sockets.push(socket);
+            }
+        });
+
+        let router = ModelRouter::new(ClientOptions {
+            endpoints: vec![format!("http://{}", addr)],
+            request_timeout_ms: 60_000,
+            ..ClientOptions::default()
+        })
+        .unwrap();
+        let model = Model {
+            request_timeout_ms: Some(50),
+            ..test_model("slow_model")
+        };
+
+        let result = tokio::time::timeout(Duration::from_secs(5), router.predict(&model, b"hello"))
+            .await
+            .expect("the model's request_timeout_ms is not applied");
+        assert_eq!(result.unwrap_err().code(), Code::DeadlineExceeded);
+
+        // 参数没有变化时复用推理服务，变化后重新创建
+        let first = router.backend(&model).unwrap();
+        assert!(Arc::ptr_eq(&first, &router.backend(&model).unwrap()));
+        let changed = Model {
+            request_timeout_ms: Some(100),
+            ..model.clone()
+        };
+        assert!(!Arc::ptr_eq(&first, &router.backend(&changed).unwrap()));
+    }
+
+    // 测试热加载后删除的模型和不再有自己的参数的模型的推理服务被释放，其他模型的推理服务被保留
+    #[tokio::test]
+    async fn test_retain() {
+        let router = ModelRouter::new(ClientOptions {
+            endpoints: vec![closed_addr().await],
+            ..ClientOptions::default()
+        })
+        .unwrap();
+        let routed = |name: &str| Model {
+            request_timeout_ms: Some(100),
+            ..test_model(name)
+        };
+        let kept = router.backend(&routed("kept")).unwrap();
+        router.backend(&routed("removed")).unwrap();
+        router.backend(&routed("unrouted")).unwrap();
+        assert_eq!(router.backends.lock().unwrap().len(), 3);
+
+        router.retain(&[routed("kept"), test_model("unrouted")]);
+        let backends = router.backends.lock().unwrap();
+        assert_eq!(backends.keys().collect::<Vec<_>>(), vec!["kept"]);
+        assert!(Arc::ptr_eq(&kept, &backends["kept"].1));
+    }
+}
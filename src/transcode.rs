@@ -0,0 +1,298 @@
+use image::io::{Limits, Reader};
+use image::{DynamicImage, ImageOutputFormat, Rgb, RgbImage};
+use serde::{Deserialize, Serialize};
+use std::io::Cursor;
+use std::time::Instant;
+use tokio::task;
+use tonic::Status;
+
+use crate::config::Model;
+use crate::metrics::{metrics, Stage};
+use crate::validation::{options_for, ImageFormat, ValidationOptions};
+
+// 转换后的图片格式
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TargetFormat {
+    Jpeg,
+    Png,
+}
+
+// 模型的transcode参数，模型无法解码的图片格式在转发之前被转换为target
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct TranscodeOptions {
+    // 模型可以直接解码的格式，这些格式的图片原样转发
+    pub accepted: Vec<ImageFormat>,
+    pub target: TargetFormat,
+    // 转换为JPEG时的质量，1到100
+    pub jpeg_quality: u8,
+}
+
+impl Default for TranscodeOptions {
+    fn default() -> Self {
+        TranscodeOptions {
+            accepted: vec![ImageFormat::Jpeg, ImageFormat::Png],
+            target: TargetFormat::Jpeg,
+            jpeg_quality: 90,
+        }
+    }
+}
+
+impl TranscodeOptions {
+    pub fn check(&self) -> Result<(), String> {
+        if !(1..=100).contains(&self.jpeg_quality) {
+            return Err(format!(
+                "transcode jpeg_quality must be between 1 and 100, got {}",
+                self.jpeg_quality
+            ));
+        }
+        Ok(())
+    }
+}
+
+// 模型配置了transcode时，在阻塞线程池中转换模型无法解码的图片，不阻塞异步运行时
+// global为全局的图片校验参数，决定解码时的尺寸上限
+pub async fn transcode_for(
+    model: &Model,
+    global: Option<&ValidationOptions>,
+    image: Vec<u8>,
+) -> Result<Vec<u8>, Status> {
+    let options = match &model.transcode {
+        Some(options) => options.clone(),
+        None => return Ok(image),
+    };
+    // 无法识别的格式原样转发，由图片校验或推理服务返回错误
+    let format = match ImageFormat::sniff(&image) {
+        Some(format) if !options.accepted.contains(&format) => format,
+        _ => return Ok(image),
+    };
+    let limits = decode_limits(model, global);
+    let start = Instant::now();
+    let transcoded = task::spawn_blocking(move || transcode(&image, format, &options, limits))
+        .await
+        .map_err(|e| Status::internal(format!("Transcoding task failed: {}", e)))?
+        .map_err(Status::invalid_argument)?;
+    metrics().observe(&model.name, Stage::Transcode, start.elapsed());
+    Ok(transcoded)
+}
+
+// 解码时的尺寸和内存上限：与图片校验使用同样的参数（模型自己的优先，其次是全局的），
+// 关闭图片校验时使用默认的校验参数，声明了巨大尺寸的小文件也不会在解码时分配大量内存
+fn decode_limits(model: &Model, global: Option<&ValidationOptions>) -> Limits {
+    let default = ValidationOptions::default();
+    let options = options_for(model, global).unwrap_or(&default);
+    let mut limits = Limits::default();
+    limits.max_image_width = Some(options.max_width);
+    limits.max_image_height = Some(options.max_height);
+    // 足够容纳最大尺寸的RGBA图片
+    limits.max_alloc = Some(options.max_width as u64 * options.max_height as u64 * 4);
+    limits
+}
+
+// 解码图片（GIF只取第一帧），把透明部分合成到白色背景上，再编码为目标格式
+pub fn transcode(
+    image: &[u8],
+    format: ImageFormat,
+    options: &TranscodeOptions,
+    limits: Limits,
+) -> Result<Vec<u8>, String> {
+    let mut reader = Reader::with_format(Cursor::new(image), format.image_format());
+    reader.limits(limits);
+    let decoded = reader
+        .decode()
+        .map_err(|e| format!("Cannot decode {} image: {}", format, e))?;
+    let output = match options.target {
+        TargetFormat::Jpeg => ImageOutputFormat::Jpeg(options.jpeg_quality),
+        TargetFormat::Png => ImageOutputFormat::Png,
+    };
+    let mut encoded = Cursor::new(vec![]);
+    DynamicImage::ImageRgb8(flatten(decoded))
+        .write_to(&mut encoded, output)
+        .map_err(|e| {
+            format!(
+                "Cannot encode {} image as {:?}: {}",
+                format, options.target, e
+            )
+        })?;
+    Ok(encoded.into_inner())
+}
+
+// 按alpha通道与白色背景混合，得到不透明的RGB图片
+fn flatten(image: DynamicImage) -> RgbImage {
+    if !image.color().has_alpha() {
+        return image.into_rgb8();
+    }
+    let rgba = image.into_rgba8();
+    RgbImage::from_fn(rgba.width(), rgba.height(), |x, y| {
+        let pixel = rgba.get_pixel(x, y);
+        let alpha = pixel[3] as u32;
+        let blend = |c: u8| ((c as u32 * alpha + 255 * (255 - alpha) + 127) / 255) as u8;
+        Rgb([blend(pixel[0]), blend(pixel[1]), blend(pixel[2])])
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::validation::tests::{encode_image, WEBP_1X1};
+    use crate::validation::{validate, ValidationOptions};
+    use image::codecs::gif::GifEncoder;
+    use image::{Frame, Rgba, RgbaImage};
+
+    fn model_with(transcode: Option<TranscodeOptions>) -> Model {
+        Model {
+            name: "transcoded".to_string(),
+            version: 1,
+            input_name: "input".to_string(),
+            transcode,
+            ..Model::default()
+        }
+    }
+
+    fn format_of(image: &[u8]) -> Option<ImageFormat> {
+        ImageFormat::sniff(image)
+    }
+
+    // 测试模型无法解码的格式被转换，可以解码的格式和没有配置transcode的模型原样转发
+    #[tokio::test]
+    async fn test_transcode_for() {
+        let model = model_with(Some(TranscodeOptions::default()));
+        for output in [
+            ImageOutputFormat::Bmp,
+            ImageOutputFormat::Tiff,
+            ImageOutputFormat::Gif,
+        ] {
+            let image = encode_image(5, 4, output);
+            let transcoded = transcode_for(&model, None, image).await.unwrap();
+            let info = validate(&transcoded, &ValidationOptions::default()).unwrap();
+            assert_eq!(
+                (info.format, info.width, info.height),
+                (ImageFormat::Jpeg, 5, 4)
+            );
+        }
+        let transcoded = transcode_for(&model, None, WEBP_1X1.to_vec())
+            .await
+            .unwrap();
+        assert_eq!(format_of(&transcoded), Some(ImageFormat::Jpeg));
+
+        let png = encode_image(5, 4, ImageOutputFormat::Png);
+        assert_eq!(transcode_for(&model, None, png.clone()).await.unwrap(), png);
+        assert_eq!(
+            transcode_for(&model, None, b"unknown".to_vec())
+                .await
+                .unwrap(),
+            b"unknown"
+        );
+
+        let bmp = encode_image(5, 4, ImageOutputFormat::Bmp);
+        assert_eq!(
+            transcode_for(&model_with(None), None, bmp.clone())
+                .await
+                .unwrap(),
+            bmp
+        );
+
+        let png_only = model_with(Some(TranscodeOptions {
+            accepted: vec![ImageFormat::Png],
+            target: TargetFormat::Png,
+            ..TranscodeOptions::default()
+        }));
+        let jpeg = encode_image(5, 4, ImageOutputFormat::Jpeg(80));
+        let transcoded = transcode_for(&png_only, None, jpeg).await.unwrap();
+        assert_eq!(format_of(&transcoded), Some(ImageFormat::Png));
+    }
+
+    // 测试损坏的图片返回INVALID_ARGUMENT
+    #[tokio::test]
+    async fn test_transcode_corrupted_image() {
+        let model = model_with(Some(TranscodeOptions::default()));
+        let mut bmp = encode_image(5, 4, ImageOutputFormat::Bmp);
+        bmp.truncate(20);
+        let status = transcode_for(&model, None, bmp).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::InvalidArgument);
+        assert!(
+            status.message().starts_with("Cannot decode BMP image"),
+            "{}",
+            status.message()
+        );
+    }
+
+    // 测试透明部分被合成到白色背景上
+    #[test]
+    fn test_flatten_alpha() {
+        let mut rgba = RgbaImage::new(2, 1);
+        rgba.put_pixel(0, 0, Rgba([0, 0, 0, 0]));
+        rgba.put_pixel(1, 0, Rgba([255, 0, 0, 255]));
+        let rgb = flatten(DynamicImage::ImageRgba8(rgba));
+        assert_eq!(rgb.get_pixel(0, 0), &Rgb([255, 255, 255]));
+        assert_eq!(rgb.get_pixel(1, 0), &Rgb([255, 0, 0]));
+
+        let mut half = RgbaImage::new(1, 1);
+        half.put_pixel(0, 0, Rgba([0, 0, 0, 128]));
+        let rgb = flatten(DynamicImage::ImageRgba8(half));
+        assert_eq!(rgb.get_pixel(0, 0), &Rgb([127, 127, 127]));
+    }
+
+    // 测试动画GIF只转换第一帧
+    #[test]
+    fn test_transcode_first_gif_frame() {
+        let mut gif = vec![];
+        {
+            let mut encoder = GifEncoder::new(&mut gif);
+            for color in [[255, 0, 0, 255], [0, 0, 255, 255]] {
+                let frame = Frame::new(RgbaImage::from_pixel(4, 4, Rgba(color)));
+                encoder.encode_frame(frame).unwrap();
+            }
+        }
+        let options = TranscodeOptions {
+            target: TargetFormat::Png,
+            ..TranscodeOptions::default()
+        };
+        let png = transcode(&gif, ImageFormat::Gif, &options, Limits::default()).unwrap();
+        let decoded = image::load_from_memory(&png).unwrap().into_rgb8();
+        assert_eq!(decoded.dimensions(), (4, 4));
+        assert_eq!(decoded.get_pixel(0, 0), &Rgb([255, 0, 0]));
+    }
+
+    // 测试声明了巨大尺寸的图片在分配内存之前被拒绝，模型自己的或全局的校验参数决定解码的上限
+    #[tokio::test]
+    async fn test_transcode_dimension_limits() {
+        let model = model_with(Some(TranscodeOptions::default()));
+        let mut bmp = encode_image(5, 4, ImageOutputFormat::Bmp);
+        bmp[18..22].copy_from_slice(&60_000i32.to_le_bytes());
+        bmp[22..26].copy_from_slice(&60_000i32.to_le_bytes());
+        let status = transcode_for(&model, None, bmp).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::InvalidArgument);
+        assert!(
+            status.message().starts_with("Cannot decode BMP image"),
+            "{}",
+            status.message()
+        );
+
+        let small = Model {
+            validation: Some(ValidationOptions {
+                max_width: 4,
+                max_height: 4,
+                ..ValidationOptions::default()
+            }),
+            ..model.clone()
+        };
+        let bmp = encode_image(5, 4, ImageOutputFormat::Bmp);
+        assert!(transcode_for(&small, None, bmp.clone()).await.is_err());
+        assert!(transcode_for(&model, None, bmp).await.is_ok());
+
+        // 全局的校验参数放宽了上限时，超过默认上限的图片也能解码
+        let global = ValidationOptions {
+            max_width: 20_000,
+            ..ValidationOptions::default()
+        };
+        let wide = encode_image(12_000, 1, ImageOutputFormat::Bmp);
+        assert!(transcode_for(&model, None, wide.clone()).await.is_err());
+        assert!(transcode_for(&model, Some(&global), wide.clone())
+            .await
+            .is_ok());
+        // 模型自己的校验参数优先于全局的
+        assert!(transcode_for(&small, Some(&global), wide).await.is_err());
+    }
+}
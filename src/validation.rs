@@ -0,0 +1,328 @@
+use image::io::Reader;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::io::Cursor;
+
+use crate::config::Model;
+
+// JPEG的结束标记在文件末尾的这个范围内查找，允许结束标记之后有少量填充
+const JPEG_TRAILER_WINDOW: usize = 1024;
+
+// 图片校验的参数，可以在config.yaml的validation部分配置，也可以被模型的validation覆盖
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct ValidationOptions {
+    pub enabled: bool,
+    // 图片最多的字节数
+    pub max_bytes: usize,
+    // 图片最大的宽度和高度（像素）
+    pub max_width: u32,
+    pub max_height: u32,
+}
+
+impl Default for ValidationOptions {
+    fn default() -> Self {
+        ValidationOptions {
+            enabled: true,
+            max_bytes: 20 * 1024 * 1024,
+            max_width: 10_000,
+            max_height: 10_000,
+        }
+    }
+}
+
+impl ValidationOptions {
+    // 限制为0时任何图片都无法通过校验，视为配置错误
+    pub fn check(&self) -> Result<(), String> {
+        if self.max_bytes == 0 || self.max_width == 0 || self.max_height == 0 {
+            return Err(format!(
+                "validation limits must be positive: max_bytes {}, max_width {}, max_height {}",
+                self.max_bytes, self.max_width, self.max_height
+            ));
+        }
+        Ok(())
+    }
+}
+
+// 根据文件开头的标识识别的图片格式
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ImageFormat {
+    Jpeg,
+    Png,
+    Gif,
+    WebP,
+    Bmp,
+    Tiff,
+}
+
+impl ImageFormat {
+    pub fn sniff(bytes: &[u8]) -> Option<ImageFormat> {
+        match bytes {
+            [0xFF, 0xD8, 0xFF, ..] => Some(ImageFormat::Jpeg),
+            [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A, ..] => Some(ImageFormat::Png),
+            [b'G', b'I', b'F', b'8', b'7' | b'9', b'a', ..] => Some(ImageFormat::Gif),
+            [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'E', b'B', b'P', ..] => {
+                Some(ImageFormat::WebP)
+            }
+            [b'B', b'M', ..] => Some(ImageFormat::Bmp),
+            [b'I', b'I', 0x2A, 0x00, ..] | [b'M', b'M', 0x00, 0x2A, ..] => Some(ImageFormat::Tiff),
+            _ => None,
+        }
+    }
+
+    pub fn image_format(&self) -> image::ImageFormat {
+        match self {
+            ImageFormat::Jpeg => image::ImageFormat::Jpeg,
+            ImageFormat::Png => image::ImageFormat::Png,
+            ImageFormat::Gif => image::ImageFormat::Gif,
+            ImageFormat::WebP => image::ImageFormat::WebP,
+            ImageFormat::Bmp => image::ImageFormat::Bmp,
+            ImageFormat::Tiff => image::ImageFormat::Tiff,
+        }
+    }
+
+    // 检查文件的结尾，发现上传时被截断的图片
+    fn check_complete(&self, bytes: &[u8]) -> Result<(), String> {
+        let complete = match self {
+            ImageFormat::Jpeg => {
+                let tail = &bytes[bytes.len().saturating_sub(JPEG_TRAILER_WINDOW)..];
+                tail.windows(2).any(|marker| marker == [0xFF, 0xD9])
+            }
+            ImageFormat::Png => {
+                bytes.len() >= 12 && &bytes[bytes.len() - 8..bytes.len() - 4] == b"IEND"
+            }
+            ImageFormat::Gif => bytes.last() == Some(&0x3B),
+            // RIFF头部中记录了之后的长度
+            ImageFormat::WebP => {
+                let declared = u32::from_le_bytes(bytes[4..8].try_into().unwrap()) as usize;
+                bytes.len() >= declared + 8
+            }
+            ImageFormat::Bmp => match bytes.get(2..6) {
+                Some(size) => bytes.len() >= u32::from_le_bytes(size.try_into().unwrap()) as usize,
+                None => false,
+            },
+            // TIFF的图像数据可以位于文件的任何位置，只检查头部
+            ImageFormat::Tiff => true,
+        };
+        match complete {
+            true => Ok(()),
+            false => Err(format!("Truncated {} image", self)),
+        }
+    }
+}
+
+impl fmt::Display for ImageFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            ImageFormat::Jpeg => "JPEG",
+            ImageFormat::Png => "PNG",
+            ImageFormat::Gif => "GIF",
+            ImageFormat::WebP => "WebP",
+            ImageFormat::Bmp => "BMP",
+            ImageFormat::Tiff => "TIFF",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+// 校验通过的图片的格式和尺寸
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ImageInfo {
+    pub format: ImageFormat,
+    pub width: u32,
+    pub height: u32,
+}
+
+// 模型的validation覆盖全局的设置，都没有时不校验
+pub fn options_for<'a>(
+    model: &'a Model,
+    global: Option<&'a ValidationOptions>,
+) -> Option<&'a ValidationOptions> {
+    model
+        .validation
+        .as_ref()
+        .or(global)
+        .filter(|options| options.enabled)
+}
+
+// 识别图片的格式，检查大小、头部和尺寸，不合法时返回可以直接发给客户端的错误信息
+pub fn validate(bytes: &[u8], options: &ValidationOptions) -> Result<ImageInfo, String> {
+    if bytes.is_empty() {
+        return Err("Empty image".to_string());
+    }
+    if bytes.len() > options.max_bytes {
+        return Err(format!(
+            "Image is {} bytes, larger than the limit of {} bytes",
+            bytes.len(),
+            options.max_bytes
+        ));
+    }
+    let format = ImageFormat::sniff(bytes).ok_or_else(|| {
+        "Unrecognized image format, expected JPEG, PNG, GIF, WebP, BMP or TIFF".to_string()
+    })?;
+    let (width, height) = Reader::with_format(Cursor::new(bytes), format.image_format())
+        .into_dimensions()
+        .map_err(|e| format!("Invalid {} header: {}", format, e))?;
+    format.check_complete(bytes)?;
+    if width == 0 || height == 0 {
+        return Err(format!("{} image has no pixels", format));
+    }
+    if width > options.max_width || height > options.max_height {
+        return Err(format!(
+            "Image is {}x{} pixels, larger than the limit of {}x{}",
+            width, height, options.max_width, options.max_height
+        ));
+    }
+    Ok(ImageInfo {
+        format,
+        width,
+        height,
+    })
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use image::{DynamicImage, ImageOutputFormat, RgbImage};
+
+    // 无损压缩的1x1 WebP图片
+    pub const WEBP_1X1: &[u8] = &[
+        b'R', b'I', b'F', b'F', 0x1a, 0x00, 0x00, 0x00, b'W', b'E', b'B', b'P', b'V', b'P', b'8',
+        b'L', 0x0d, 0x00, 0x00, 0x00, 0x2f, 0x00, 0x00, 0x00, 0x10, 0x07, 0x10, 0x11, 0x11, 0x88,
+        0x88, 0xfe, 0x07, 0x00,
+    ];
+
+    // 编码一张指定尺寸的图片，供其他模块的测试使用
+    pub fn encode_image(width: u32, height: u32, format: ImageOutputFormat) -> Vec<u8> {
+        let mut bytes = Cursor::new(vec![]);
+        DynamicImage::ImageRgb8(RgbImage::new(width, height))
+            .write_to(&mut bytes, format)
+            .unwrap();
+        bytes.into_inner()
+    }
+
+    // 测试每种格式都能被识别并读出尺寸
+    #[test]
+    fn test_supported_formats() {
+        let options = ValidationOptions::default();
+        let cases = [
+            (
+                encode_image(3, 2, ImageOutputFormat::Jpeg(80)),
+                ImageFormat::Jpeg,
+            ),
+            (encode_image(3, 2, ImageOutputFormat::Png), ImageFormat::Png),
+            (encode_image(3, 2, ImageOutputFormat::Gif), ImageFormat::Gif),
+            (encode_image(3, 2, ImageOutputFormat::Bmp), ImageFormat::Bmp),
+            (
+                encode_image(3, 2, ImageOutputFormat::Tiff),
+                ImageFormat::Tiff,
+            ),
+        ];
+        for (bytes, format) in cases {
+            assert_eq!(
+                validate(&bytes, &options),
+                Ok(ImageInfo {
+                    format,
+                    width: 3,
+                    height: 2
+                })
+            );
+        }
+        assert_eq!(
+            validate(WEBP_1X1, &options),
+            Ok(ImageInfo {
+                format: ImageFormat::WebP,
+                width: 1,
+                height: 1
+            })
+        );
+    }
+
+    // 测试无法识别、头部损坏和被截断的图片
+    #[test]
+    fn test_invalid_images() {
+        let options = ValidationOptions::default();
+        assert_eq!(validate(b"", &options), Err("Empty image".to_string()));
+        assert_eq!(
+            validate(b"hello world", &options),
+            Err(
+                "Unrecognized image format, expected JPEG, PNG, GIF, WebP, BMP or TIFF".to_string()
+            )
+        );
+
+        let mut png = encode_image(3, 2, ImageOutputFormat::Png);
+        png[12..16].copy_from_slice(b"XXXX");
+        let message = validate(&png, &options).unwrap_err();
+        assert!(message.starts_with("Invalid PNG header"), "{}", message);
+
+        for format in [
+            ImageOutputFormat::Jpeg(80),
+            ImageOutputFormat::Png,
+            ImageOutputFormat::Gif,
+            ImageOutputFormat::Bmp,
+        ] {
+            let bytes = encode_image(64, 64, format);
+            let truncated = &bytes[..bytes.len() - 10];
+            let message = validate(truncated, &options).unwrap_err();
+            assert!(message.starts_with("Truncated"), "{}", message);
+        }
+        let message = validate(&WEBP_1X1[..WEBP_1X1.len() - 2], &options).unwrap_err();
+        assert!(message.contains("WebP"), "{}", message);
+    }
+
+    // 测试字节数和尺寸的上限
+    #[test]
+    fn test_limits() {
+        let bytes = encode_image(300, 200, ImageOutputFormat::Png);
+        let options = ValidationOptions {
+            max_bytes: 100,
+            ..ValidationOptions::default()
+        };
+        assert_eq!(
+            validate(&bytes, &options),
+            Err(format!(
+                "Image is {} bytes, larger than the limit of 100 bytes",
+                bytes.len()
+            ))
+        );
+
+        let options = ValidationOptions {
+            max_width: 256,
+            max_height: 256,
+            ..ValidationOptions::default()
+        };
+        assert_eq!(
+            validate(&bytes, &options),
+            Err("Image is 300x200 pixels, larger than the limit of 256x256".to_string())
+        );
+    }
+
+    // 测试模型的设置覆盖全局的设置，enabled为false时不校验
+    #[test]
+    fn test_options_for() {
+        let global = ValidationOptions::default();
+        let model = Model::default();
+        assert_eq!(options_for(&model, None), None);
+        assert_eq!(options_for(&model, Some(&global)), Some(&global));
+
+        let model = Model {
+            validation: Some(ValidationOptions {
+                max_bytes: 10,
+                ..ValidationOptions::default()
+            }),
+            ..Model::default()
+        };
+        assert_eq!(options_for(&model, Some(&global)).unwrap().max_bytes, 10);
+        assert_eq!(options_for(&model, None).unwrap().max_bytes, 10);
+
+        let model = Model {
+            validation: Some(ValidationOptions {
+                enabled: false,
+                ..ValidationOptions::default()
+            }),
+            ..Model::default()
+        };
+        assert_eq!(options_for(&model, Some(&global)), None);
+    }
+}